@@ -1,15 +1,17 @@
-use std::fmt::format;
-
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use tree::avl_tree_type::AVLTree as AVLT;
 
 
 fn avl_insert_worst_case(tree: &mut AVLT<u128>, tree_size: &u128) {
-    for key in 0..tree_size.clone() {
-        tree.insert(&key);
+    for key in 0..*tree_size {
+        tree.insert(key);
     }
 }
 
+fn avl_bulk_load(tree_size: &u128) -> AVLT<u128> {
+    AVLT::from_sorted_iter(0..*tree_size)
+}
+
 fn avl_search_worst_case(tree: &AVLT<u128>, tree_size: &u128) {
     for key in 0..tree_size/10 {
         let _ = tree.search(&key);
@@ -26,6 +28,10 @@ fn criterion_benchmark(c: &mut Criterion) {
                 black_box(&tree_size)
             ))
         );
+        c.bench_function(
+            format!("avl_bulk_load_{:?}", tree_size).as_str(),
+            |b| b.iter(|| avl_bulk_load(black_box(&tree_size)))
+        );
         c.bench_function(
             format!("avl_search_{:?}", tree_size/10).as_str(),
             |b| b.iter(|| avl_search_worst_case(
@@ -1,15 +1,17 @@
-use std::fmt::format;
-
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use tree::tree_type::RedBlackTree as RBT;
 
 
 fn rb_insert_worst_case(tree: &mut RBT<u128>, tree_size: &u128) {
-    for key in 0..tree_size.clone() {
-        tree.insert(&key);
+    for key in 0..*tree_size {
+        tree.insert(key);
     }
 }
 
+fn rb_bulk_load(tree_size: &u128) -> RBT<u128> {
+    RBT::from_sorted_iter(0..*tree_size)
+}
+
 fn rb_search_worst_case(tree: &RBT<u128>, tree_size: &u128) {
     for key in 0..tree_size/10 {
         let _ = tree.search(&key);
@@ -26,6 +28,10 @@ fn criterion_benchmark(c: &mut Criterion) {
                 black_box(&tree_size)
             ))
         );
+        c.bench_function(
+            format!("rb_bulk_load_{:?}", tree_size).as_str(),
+            |b| b.iter(|| rb_bulk_load(black_box(&tree_size)))
+        );
         c.bench_function(
             format!("rb_search_{:?}", tree_size/10).as_str(),
             |b| b.iter(|| rb_search_worst_case(
@@ -0,0 +1,27 @@
+/*
+    Per-node summaries of a subtree, e.g. the sum or maximum of the values below a node. Every node
+    stores `combine(combine(left, summarize(key, value)), right)` of its own entry and its children,
+    kept up to date wherever the subtree sizes are, so trees can fold any key range in O(log n).
+    `identity` stands in for empty subtrees and `combine` has to be associative; it does not need to
+    be commutative, summaries are always combined in key order.
+*/
+pub trait Augment<T, V> {
+    type Summary: Clone;
+
+    fn identity() -> Self::Summary;
+
+    fn summarize(key: &T, value: &V) -> Self::Summary;
+
+    fn combine(left: &Self::Summary, right: &Self::Summary) -> Self::Summary;
+}
+
+// No augmentation, the default for every tree.
+impl<T, V> Augment<T, V> for () {
+    type Summary = ();
+
+    fn identity() {}
+
+    fn summarize(_key: &T, _value: &V) {}
+
+    fn combine(_left: &(), _right: &()) {}
+}
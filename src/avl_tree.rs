@@ -1,105 +1,315 @@
-﻿use std::cell::RefCell;
 use std::fmt::Debug;
-use std::rc::{Rc, Weak};
 use std::cmp::{Ordering, max};
+use std::ops::Bound;
+use std::borrow;
+use crate::comparator::Comparator;
+#[cfg(any(test, feature = "unstable-internals"))]
+use crate::comparator::NaturalOrder;
+use crate::augment::Augment;
+use crate::node_arena::{Arena, NodeId};
+use crate::tree_violation::TreeViolation;
+use crate::tree_error::TreeError;
 
 pub enum Direction {
     Left,
     Right,
 }
 
-type TChild<T> = Rc<RefCell<AVLTreeNode<T>>>;
-type TParent<T> = Weak<RefCell<AVLTreeNode<T>>>;
-pub type AVLChild<T> = Option<TChild<T>>;
-type AVLParent<T> = Option<TParent<T>>;
+pub type AVLChild = Option<NodeId>;
+// (keys below, node with the key, keys above)
+type AVLSplit = (AVLChild, AVLChild, AVLChild);
+// the node holding a key, or the parent and side of the empty slot where it would go
+pub type AVLSlot = Result<AVLChild, (AVLChild, Direction)>;
 
 #[derive(Debug)]
-pub struct AVLTreeNode<T: Ord + Clone> {
-    pub key: T,
-    pub parent: AVLParent<T>,
-    left_child: AVLChild<T>,
-    right_child: AVLChild<T>,
-    pub _ptr_self: AVLParent<T>,
+pub struct AVLTreeNode<T, V = (), A: Augment<T, V> = ()> {
+    pub key: Option<T>,
+    value: Option<V>,
+    pub parent: AVLChild,
+    left_child: AVLChild,
+    right_child: AVLChild,
     pub is_nil: bool,
     pub height: u128,
+    pub size: usize,
+    summary: A::Summary,
 }
 
-impl<T: Ord + Clone + Debug> AVLTreeNode<T> {
+// The nodes of one AVL tree, linked by `NodeId` like those of `RBArena`.
+pub type AVLArena<T, V = (), A = ()> = Arena<AVLTreeNode<T, V, A>>;
+
+impl<T: Clone, V: Clone, A: Augment<T, V>> Clone for AVLTreeNode<T, V, A> {
+
+    fn clone(&self) -> Self {
+        AVLTreeNode {
+            key: self.key.clone(),
+            value: self.value.clone(),
+            parent: self.parent,
+            left_child: self.left_child,
+            right_child: self.right_child,
+            is_nil: self.is_nil,
+            height: self.height,
+            size: self.size,
+            summary: self.summary.clone(),
+        }
+    }
+}
+
+// Entry handles for AVL nodes; they work like `RBEntryRef`/`RBEntryMut` in `red_black_tree`.
+pub struct AVLEntryRef<'a, T, V = (), A: Augment<T, V> = ()> {
+    nodes: &'a AVLArena<T, V, A>,
+    node: NodeId,
+}
+
+pub struct AVLEntryMut<'a, T, V = (), A: Augment<T, V> = ()> {
+    nodes: &'a mut AVLArena<T, V, A>,
+    node: NodeId,
+}
+
+impl<'a, T, V, A: Augment<T, V>> Clone for AVLEntryRef<'a, T, V, A> {
+
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, V, A: Augment<T, V>> Copy for AVLEntryRef<'a, T, V, A> {}
+
+impl<'a, T, V, A: Augment<T, V>> AVLEntryRef<'a, T, V, A> {
+
+    pub(crate) fn from_node(nodes: &'a AVLArena<T, V, A>, root: &AVLChild) -> Option<Self> {
+        match root {
+            Some(id) if !nodes.get(*id).is_nil => Some(AVLEntryRef { nodes, node: *id }),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self) -> &'a T {
+        self.nodes.get(self.node).key.as_ref().expect("NIL nodes are never handed out")
+    }
+
+    pub fn value(&self) -> &'a V {
+        self.nodes.get(self.node).value.as_ref().expect("NIL nodes are never handed out")
+    }
+}
+
+impl<'a, T, V, A: Augment<T, V>> AVLEntryMut<'a, T, V, A> {
+
+    pub(crate) fn from_node(nodes: &'a mut AVLArena<T, V, A>, root: &AVLChild) -> Option<Self> {
+        match root {
+            Some(id) if !nodes.get(*id).is_nil => Some(AVLEntryMut { nodes, node: *id }),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self) -> &T {
+        self.nodes.get(self.node).key.as_ref().expect("NIL nodes are never handed out")
+    }
+
+    pub fn value(&self) -> &V {
+        self.nodes.get(self.node).value.as_ref().expect("NIL nodes are never handed out")
+    }
+
+    pub fn value_mut(&mut self) -> &mut V {
+        self.nodes.get_mut(self.node).value.as_mut().expect("NIL nodes are never handed out")
+    }
+}
+
+// Refreshes the summaries above the node, as `RBEntryMut` does.
+impl<'a, T, V, A: Augment<T, V>> Drop for AVLEntryMut<'a, T, V, A> {
+
+    fn drop(&mut self) {
+        self.nodes.update_size_to_root(&Some(self.node));
+    }
+}
+
+// Read-only view of an AVL node, the counterpart of `RBNodeView` with heights instead of colours.
+pub struct AVLNodeView<'a, T, V = (), A: Augment<T, V> = ()> {
+    nodes: &'a AVLArena<T, V, A>,
+    node: NodeId,
+}
+
+impl<'a, T, V, A: Augment<T, V>> Clone for AVLNodeView<'a, T, V, A> {
+
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, V, A: Augment<T, V>> Copy for AVLNodeView<'a, T, V, A> {}
+
+impl<'a, T, V, A: Augment<T, V>> AVLNodeView<'a, T, V, A> {
+
+    pub(crate) fn from_node(nodes: &'a AVLArena<T, V, A>, root: &AVLChild) -> Option<Self> {
+        match root {
+            Some(id) if !nodes.get(*id).is_nil => Some(AVLNodeView { nodes, node: *id }),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self) -> &'a T {
+        self.nodes.get(self.node).key.as_ref().expect("NIL nodes are never handed out")
+    }
+
+    pub fn value(&self) -> &'a V {
+        self.nodes.get(self.node).value.as_ref().expect("NIL nodes are never handed out")
+    }
+
+    pub fn height(&self) -> u128 {
+        self.nodes.get(self.node).height
+    }
+
+    // Height of the right subtree minus the height of the left one.
+    pub fn balance_factor(&self) -> i64 {
+        let height = |child: Option<Self>| child.map_or(0, |child| child.height() as i64);
+        height(self.right()) - height(self.left())
+    }
+
+    // Number of keys in the subtree below and including this node.
+    pub fn size(&self) -> usize {
+        self.nodes.get(self.node).size
+    }
+
+    pub fn left(&self) -> Option<Self> {
+        AVLNodeView::from_node(self.nodes, &self.nodes.get(self.node).left_child)
+    }
+
+    pub fn right(&self) -> Option<Self> {
+        AVLNodeView::from_node(self.nodes, &self.nodes.get(self.node).right_child)
+    }
 
-    pub fn empty() -> AVLChild<T> {
+    pub fn parent(&self) -> Option<Self> {
+        AVLNodeView::from_node(self.nodes, &self.nodes.get(self.node).parent)
+    }
+}
+
+impl<'a, T: Debug, V, A: Augment<T, V>> Debug for AVLNodeView<'a, T, V, A> {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AVLNodeView").field("key", self.key()).field("height", &self.height()).finish()
+    }
+}
+
+impl<T, V, A: Augment<T, V>> AVLTreeNode<T, V, A> {
+
+    // Key of a node that is known not to be NIL.
+    fn _key(&self) -> &T {
+        self.key.as_ref().expect("NIL nodes have no key")
+    }
+}
+
+impl<T, V, A: Augment<T, V>> AVLArena<T, V, A> {
+
+    fn _refresh(&mut self, id: NodeId) {
+        /*
+            Recomputes the subtree size and summary of a node from its entry and its two children.
+        */
+        let node_ref = self.get(id);
+        if node_ref.is_nil {
+            return;
+        }
+        let (left_size, left_summary) = self._subtree_totals(&node_ref.left_child);
+        let (right_size, right_summary) = self._subtree_totals(&node_ref.right_child);
+        let entry_summary = match (&node_ref.key, &node_ref.value) {
+            (Some(key), Some(value)) => A::summarize(key, value),
+            _ => A::identity(),
+        };
+        let node_mut = self.get_mut(id);
+        node_mut.size = 1 + left_size + right_size;
+        node_mut.summary = A::combine(&A::combine(&left_summary, &entry_summary), &right_summary);
+    }
+
+
+    fn _subtree_totals(&self, root: &AVLChild) -> (usize, A::Summary) {
+        match root {
+            Some(id) => {
+                let node_ref = self.get(*id);
+                (node_ref.size, node_ref.summary.clone())
+            },
+            None => (0, A::identity()),
+        }
+    }
+}
+
+impl<T: Ord + Debug> AVLArena<T> {
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn new_node(&mut self, key: T) -> AVLChild {
+        self.new_entry(key, ())
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn insert_node(&mut self, root: &AVLChild, key: T) {
+        self.insert_entry_node(root, key, ());
+    }
+}
+
+
+impl<T, V, A: Augment<T, V>> AVLArena<T, V, A> {
+
+    #[cfg(feature = "unstable-internals")]
+    pub fn empty(&self) -> AVLChild {
         None
     }
 
 
-    pub fn new(key: T) -> AVLChild<T> {
-        AVLTreeNode::_new(key, None, false)
+    pub fn new_entry(&mut self, key: T, value: V) -> AVLChild {
+        self._new(Some(key), Some(value), None, false)
     }
 
-    pub fn count_leaves(root: &AVLChild<T>) -> u128 {
-        if AVLTreeNode::is_leaf(root) { return 1; }
-        if AVLTreeNode::get_root_nil(root) { return 0; }
+    pub fn count_leaves(&self, root: &AVLChild) -> u128 {
+        if self.is_leaf(root) { return 1; }
+        if self.get_root_nil(root) { return 0; }
         //if RBTreeNode::is_leaf(root) {return 1;}
-        AVLTreeNode::count_leaves(&AVLTreeNode::get_left(root)) + AVLTreeNode::count_leaves(&AVLTreeNode::get_right(root))
+        self.count_leaves(&self.get_left(root)) + self.count_leaves(&self.get_right(root))
     }
 
 
-    fn _new(key: T, parent: AVLParent<T>, is_nil: bool) -> AVLChild<T> {
-        let node = Rc::new(RefCell::new(Self { 
-            key, 
-            parent, 
-            left_child: None, 
+    fn _new(&mut self, key: Option<T>, value: Option<V>, parent: AVLChild, is_nil: bool) -> AVLChild {
+        let node = self.alloc(AVLTreeNode {
+            key,
+            value,
+            parent,
+            left_child: None,
             right_child: None,
-            _ptr_self: None,
             is_nil,
-            height: 1
-        }));
-
-        let weak_ptr = Rc::downgrade(&node);
-        {
-            let mut node_ref = node.borrow_mut();
-            node_ref._ptr_self = Some(weak_ptr);
-        }
-
+            height: 1,
+            size: 0,
+            summary: A::identity(),
+        });
+        self._refresh(node);
         Some(node)
     }
 
-    pub fn get_height(root: &AVLChild<T>) -> u128 {
-        if AVLTreeNode::get_root_nil(root) {return 0;}
+    pub fn get_height(&self, root: &AVLChild) -> u128 {
+        if self.get_root_nil(root) {return 0;}
         //1 + max(
-            //AVLTreeNode::get_height(&AVLTreeNode::get_left(root)),
-            //AVLTreeNode::get_height(&AVLTreeNode::get_right(root))
+            //self.get_height(&self.get_left(root)),
+            //self.get_height(&self.get_right(root))
         //)
         match root {
-            Some(root_ptr) => root_ptr.borrow().height,
-            None => todo!("should never happen"),
-        } 
+            Some(root_ptr) => self.get(*root_ptr).height,
+            None => 0,
+        }
     }
 
-    pub fn set_height(root: &AVLChild<T>, height: u128) {
-        match root {
-            Some(root_ptr) => {
-                let mut node_ref = root_ptr.borrow_mut();
-                //match parent {
-                    //Some(parent_ptr) => node_ref.parent = parent_ptr.borrow()._ptr_self.clone(),
-                    //None => node_ref.parent = None,
-                //}
-                node_ref.height = height.clone();
-            },
-            None => {},
+    pub fn set_height(&mut self, root: &AVLChild, height: u128) {
+        if let Some(id) = root {
+            self.get_mut(*id).height = height;
         }
     }
 
-    pub fn update_height(root: &AVLChild<T>) {
-        AVLTreeNode::set_height(root, 1 + max(
-            AVLTreeNode::get_height(&AVLTreeNode::get_left(root)),
-            AVLTreeNode::get_height(&AVLTreeNode::get_right(root))
+    pub fn update_height(&mut self, root: &AVLChild) {
+        self.set_height(root, 1 + max(
+            self.get_height(&self.get_left(root)),
+            self.get_height(&self.get_right(root))
         ))
     }
 
-    pub fn get_balance_factor(root: &AVLChild<T>) -> i64 {
-        if AVLTreeNode::get_root_nil(root) {return 0;}
-        let lh: i64 = AVLTreeNode::get_height(&AVLTreeNode::get_left(root)) as i64;
-        let rh: i64 = AVLTreeNode::get_height(&AVLTreeNode::get_right(root)) as i64;
+    pub fn get_balance_factor(&self, root: &AVLChild) -> i64 {
+        if self.get_root_nil(root) {return 0;}
+        let lh: i64 = self.get_height(&self.get_left(root)) as i64;
+        let rh: i64 = self.get_height(&self.get_right(root)) as i64;
         let balance_factor: i64 = rh - lh;
         balance_factor
     }
@@ -114,14 +324,15 @@ impl<T: Ord + Clone + Debug> AVLTreeNode<T> {
     }
 
 
-    fn to_string(direction: &Direction, parent: &AVLChild<T>, key: &T, extra: &str) {
+    fn to_string(&self, direction: &Direction, parent: &AVLChild, key: &T, extra: &str) where T: Debug {
         let direction_str = match direction {
             Direction::Left => "<──",
             Direction::Right => "──>",
         };
-        let parent_key = if let Some(parent_ptr) = parent {
-            parent_ptr.borrow().key.clone()
-        } else {key.clone()};
+        let parent_key = match parent {
+            Some(parent_id) => self.get(*parent_id).key.as_ref().unwrap_or(key),
+            None => key,
+        };
 
         println!("{}", extra);
         println!(
@@ -130,389 +341,1110 @@ impl<T: Ord + Clone + Debug> AVLTreeNode<T> {
     }
 
 
-    fn _print_tree(root: &AVLChild<T>, direction: Direction, extra: &str) {
+    fn _print_tree(&self, root: &AVLChild, direction: Direction, extra: &str) where T: Debug {
         match root {
-            Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                AVLTreeNode::to_string(
+            Some(tree_ptr) if self.get(*tree_ptr).key.is_some() => {
+                let node_ref = self.get(*tree_ptr);
+                self.to_string(
                     &direction, 
-                    &AVLTreeNode::get_parent(&root),
-                    &node_ref.key,
+                    &self.get_parent(root),
+                    node_ref._key(),
                     extra);
                 
                 let (left_child, right_child) = (&node_ref.left_child, &node_ref.right_child);
                 match left_child {
-                    Some(_) => AVLTreeNode::_print_tree(left_child, Direction::Left, &(extra.to_owned()+"|\t")),
-                    None => AVLTreeNode::<T>::to_string_nil(&Direction::Left, &(extra.to_owned()+"|\t")),
+                    Some(_) => self._print_tree(left_child, Direction::Left, &(extra.to_owned()+"|\t")),
+                    None => Self::to_string_nil(&Direction::Left, &(extra.to_owned()+"|\t")),
                 };
                 match right_child {
-                    Some(_) => AVLTreeNode::_print_tree(right_child, Direction::Right, &(extra.to_owned()+"|\t")),
-                    None => AVLTreeNode::<T>::to_string_nil(&Direction::Right, &(extra.to_owned()+"|\t")),
+                    Some(_) => self._print_tree(right_child, Direction::Right, &(extra.to_owned()+"|\t")),
+                    None => Self::to_string_nil(&Direction::Right, &(extra.to_owned()+"|\t")),
                 };
             },
-            None => AVLTreeNode::<T>::to_string_nil(&Direction::Left, ""),
+            _ => Self::to_string_nil(&Direction::Left, ""),
         }
     }
 
 
-    pub fn print_tree(root: &AVLChild<T>) {
-        AVLTreeNode::_print_tree(root, Direction::Left, "");
+    pub fn print_tree(&self, root: &AVLChild) where T: Debug {
+        self._print_tree(root, Direction::Left, "");
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn left_rotate(&mut self, root: AVLChild, key: &T) -> AVLChild where T: Ord {
+        let x = self.find_node(&root, key);
+        self.left_rotate_at(root, &x)
     }
 
 
-    pub fn left_rotate(root: AVLChild<T>, key: T) -> AVLChild<T> {
-        let root_copy = root.clone();
-        let x = AVLTreeNode::find_node(&root, key.clone());
-        let parent = AVLTreeNode::get_parent(&x);
+    pub fn left_rotate_at(&mut self, root: AVLChild, x: &AVLChild) -> AVLChild {
+        /*
+            Rotates left at the node x of the tree rooted at root, without searching for it by key.
+            Returns the root of the whole tree, which changes only when x was the root.
+        */
+        if self.get_root_nil(x) {
+            return root;
+        }
+        let parent = self.get_parent(x);
         match &parent {
             Some(_) => {
-                if AVLTreeNode::is_node_equal(&x, &AVLTreeNode::get_left(&parent)) {
-                    AVLTreeNode::set_child(
-                        &parent, 
-                        AVLTreeNode::_left_rotate(&root, key), 
-                        Direction::Left
-                    );
+                if self.is_node_equal(x, &self.get_left(&parent)) {
+                    let rotated = self._left_rotate(x);
+                    self.set_child(&parent, rotated, Direction::Left);
                 } else {
-                    AVLTreeNode::set_child(
-                        &parent, 
-                        AVLTreeNode::_left_rotate(&root, key), 
-                        Direction::Right
-                    );
+                    let rotated = self._left_rotate(x);
+                    self.set_child(&parent, rotated, Direction::Right);
                 }
-                return root_copy;
+                root
             },
             None => {
-                return AVLTreeNode::_left_rotate(&root, key)
+                self._left_rotate(x)
             },
         }
     }
 
 
-    fn _left_rotate(root: &AVLChild<T>, key: T) -> AVLChild<T> {
-        match root {
+    fn _left_rotate(&mut self, x: &AVLChild) -> AVLChild {
+        match x {
             Some(_) => {
-                let x = AVLTreeNode::find_node(root, key);
-                let y = AVLTreeNode::get_right(&x);
-                match y {
-                    None => return root.clone(),
-                    _ => (),
+                let y = self.get_right(x);
+                // nothing to rotate; x stays where it is (see `RBTreeNode::_left_rotate`)
+                if y.is_none() {
+                    return *x;
                 }
                 
-                AVLTreeNode::set_child(&x, AVLTreeNode::get_left(&y), Direction::Right);
+                self.set_child(x, self.get_left(&y), Direction::Right);
 
-                match AVLTreeNode::get_left(&y) {
-                    Some(_) => AVLTreeNode::set_parent(&AVLTreeNode::get_left(&y), &x),
-                    None => (),
-                };
-                AVLTreeNode::set_parent(&y, &AVLTreeNode::get_parent(&x));
+                self.set_parent(&self.get_left(&y), x);
+                self.set_parent(&y, &self.get_parent(x));
 
-                AVLTreeNode::set_parent(&x, &y);
-                let z = x.clone();
-                AVLTreeNode::set_child(&y, z, Direction::Left);
+                self.set_parent(x, &y);
+                let z = *x;
+                self.set_child(&y, z, Direction::Left);
 
-                AVLTreeNode::update_height(&x);
-                AVLTreeNode::update_height(&y);
+                self.update_height(x);
+                self.update_height(&y);
+                self.update_size(x);
+                self.update_size(&y);
 
-                return y; // this y must be used to set the parent's left or right
+                y  // this y must be used to set the parent's left or right
 
             },
-            None => todo!("Does not support empty root now"),
+            None => None,
         }
     }
 
-    pub fn right_rotate(root: AVLChild<T>, key: T) -> AVLChild<T> {
-        let root_copy = root.clone();
-        let x = AVLTreeNode::find_node(&root, key.clone());
-        let parent = AVLTreeNode::get_parent(&x);
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn right_rotate(&mut self, root: AVLChild, key: &T) -> AVLChild where T: Ord {
+        let x = self.find_node(&root, key);
+        self.right_rotate_at(root, &x)
+    }
+
+
+    pub fn right_rotate_at(&mut self, root: AVLChild, x: &AVLChild) -> AVLChild {
+        if self.get_root_nil(x) {
+            return root;
+        }
+        let parent = self.get_parent(x);
         match &parent {
             Some(_) => {
-                if AVLTreeNode::is_node_equal(&x, &AVLTreeNode::get_right(&parent)) {
-                    AVLTreeNode::set_child(
-                        &parent, 
-                        AVLTreeNode::_right_rotate(&root, key), 
-                        Direction::Right
-                    );
+                if self.is_node_equal(x, &self.get_right(&parent)) {
+                    let rotated = self._right_rotate(x);
+                    self.set_child(&parent, rotated, Direction::Right);
                 } else {
-                    AVLTreeNode::set_child(
-                        &parent, 
-                        AVLTreeNode::_right_rotate(&root, key), 
-                        Direction::Left
-                    );
+                    let rotated = self._right_rotate(x);
+                    self.set_child(&parent, rotated, Direction::Left);
                 }
-                return root_copy;
+                root
             },
             None => {
-                return AVLTreeNode::_right_rotate(&root, key)
+                self._right_rotate(x)
             },
         }
     }
 
-    pub fn _right_rotate(root: &AVLChild<T>, key: T) -> AVLChild<T> {
-        match root {
+    fn _right_rotate(&mut self, x: &AVLChild) -> AVLChild {
+        match x {
             Some(_) => {
-                let x = AVLTreeNode::find_node(root, key);
-                let y = AVLTreeNode::get_left(&x);
-                match y {
-                    None => return root.clone(),
-                    _ => (),
+                let y = self.get_left(x);
+                if y.is_none() {
+                    return *x;
                 }
                 
-                AVLTreeNode::set_child(&x, AVLTreeNode::get_right(&y), Direction::Left);
+                self.set_child(x, self.get_right(&y), Direction::Left);
 
-                match AVLTreeNode::get_right(&y) {
-                    Some(_) => AVLTreeNode::set_parent(&AVLTreeNode::get_right(&y), &x),
-                    None => (),
-                };
-                AVLTreeNode::set_parent(&y, &AVLTreeNode::get_parent(&x));
+                self.set_parent(&self.get_right(&y), x);
+                self.set_parent(&y, &self.get_parent(x));
 
-                AVLTreeNode::set_parent(&x, &y);
-                let z = x.clone();
-                AVLTreeNode::set_child(&y, z, Direction::Right);
+                self.set_parent(x, &y);
+                let z = *x;
+                self.set_child(&y, z, Direction::Right);
 
-                AVLTreeNode::update_height(&x);
-                AVLTreeNode::update_height(&y);
+                self.update_height(x);
+                self.update_height(&y);
+                self.update_size(x);
+                self.update_size(&y);
 
-                return y; // this y must be used to set the parent's left or right
+                y  // this y must be used to set the parent's left or right
 
             },
-            None => todo!("Does not support empty root now"),
+            None => None,
         }
     }
 
 
-    pub fn is_node_equal(root1: &AVLChild<T>, root2: &AVLChild<T>) -> bool {
-        match (root1, root2) {
-            (None, None) => true,
-            (Some(ptr1), Some(ptr2)) => {
-                match ptr1.borrow().key.cmp(&ptr2.borrow().key) {
-                    Ordering::Equal => true,
-                    _ => false,
-                }
-            },
-            _ => false
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_left_rotate(&mut self, root: AVLChild, key: &T) -> Result<AVLChild, TreeError> where T: Ord {
+        let x = self.find_node(&root, key);
+        if self.get_root_nil(&x) {
+            return Err(TreeError::KeyNotFound);
         }
+        self.try_left_rotate_at(root, &x)
     }
 
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_right_rotate(&mut self, root: AVLChild, key: &T) -> Result<AVLChild, TreeError> where T: Ord {
+        let x = self.find_node(&root, key);
+        if self.get_root_nil(&x) {
+            return Err(TreeError::KeyNotFound);
+        }
+        self.try_right_rotate_at(root, &x)
+    }
 
-    pub fn get_root_key(root: &AVLChild<T>) -> T {
-        match root {
-            Some(root_ptr) => root_ptr.borrow().key.clone(),
-            None => todo!("should never happen"),
+    // Unlike `left_rotate_at`, reports a rotation that cannot happen instead of leaving the tree as it is.
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_left_rotate_at(&mut self, root: AVLChild, x: &AVLChild) -> Result<AVLChild, TreeError> {
+        if self.get_root_nil(x) {
+            return Err(TreeError::EmptyNode);
         }
+        if self.get_root_nil(&self.get_right(x)) {
+            return Err(TreeError::MissingChild);
+        }
+        Ok(self.left_rotate_at(root, x))
     }
 
-    pub fn set_root_key(root: &AVLChild<T>, key: T) {
-        match root {
-            Some(root_ptr) => {
-                let mut node_ref = root_ptr.borrow_mut();
-                node_ref.key = key;
-            },
-            None => todo!("should never happen"),
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_right_rotate_at(&mut self, root: AVLChild, x: &AVLChild) -> Result<AVLChild, TreeError> {
+        if self.get_root_nil(x) {
+            return Err(TreeError::EmptyNode);
+        }
+        if self.get_root_nil(&self.get_left(x)) {
+            return Err(TreeError::MissingChild);
         }
+        Ok(self.right_rotate_at(root, x))
+    }
+
+    pub fn is_node_equal(&self, root1: &AVLChild, root2: &AVLChild) -> bool {
+        root1 == root2
+    }
+
+
+    // Only the tests copy keys out of AVL nodes; outside the crate `try_get_root_key` does it.
+    #[cfg(test)]
+    pub(crate) fn get_root_key(&self, root: &AVLChild) -> T where T: Clone {
+        self.get_root_key_ref(root).clone()
     }
 
-    pub fn get_root_nil(root: &AVLChild<T>) -> bool {
+
+    // Borrows the key instead of copying it; the arena stays borrowed for as long as the reference.
+    pub(crate) fn get_root_key_ref(&self, root: &AVLChild) -> &T {
         match root {
-            Some(target_ptr) => target_ptr.borrow().is_nil.clone(),
-            None => true,
+            Some(id) => self.get(*id)._key(),
+            None => panic!("get_root_key_ref: {}", TreeError::EmptyNode),
         }
     }
 
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_get_root_key_ref(&self, root: &AVLChild) -> Result<&T, TreeError> {
+        root.and_then(|id| self.get(id).key.as_ref()).ok_or(TreeError::EmptyNode)
+    }
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_get_root_key(&self, root: &AVLChild) -> Result<T, TreeError> where T: Clone {
+        self.try_get_root_key_ref(root).cloned()
+    }
 
-    pub fn get_parent(root: &AVLChild<T>) -> AVLChild<T> {
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn set_root_key(&mut self, root: &AVLChild, key: T) {
+        if let Some(id) = root {
+            self.get_mut(*id).key = Some(key);
+        }
+    }
+
+    pub fn replace_root_key(&mut self, root: &AVLChild, key: T) -> Option<T> {
+        let old = match root {
+            Some(root_ptr) => self.get_mut(*root_ptr).key.replace(key),
+            None => None,
+        };
+        self.update_size_to_root(root);
+        old
+    }
+
+    pub fn replace_root_value(&mut self, root: &AVLChild, value: V) -> Option<V> {
+        let old = match root {
+            Some(root_ptr) => self.get_mut(*root_ptr).value.replace(value),
+            None => None,
+        };
+        self.update_size_to_root(root);
+        old
+    }
+
+    pub fn take_root_value(&mut self, root: &AVLChild) -> Option<V> {
         match root {
-            Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                if let Some(parent_ptr) = node_ref.parent.clone() {
-                    return parent_ptr.upgrade();
-                } else {return None;}
-            }
+            Some(root_ptr) => self.get_mut(*root_ptr).value.take(),
             None => None,
         }
     }
 
-    pub fn is_leaf(root: &AVLChild<T>) -> bool {
+    pub fn swap_root_entry(&mut self, root1: &AVLChild, root2: &AVLChild) {
+        if let (Some(id1), Some(id2)) = (*root1, *root2) {
+            if id1 != id2 {
+                let node1 = self.get_mut(id1);
+                let (key, value) = (node1.key.take(), node1.value.take());
+                let node2 = self.get_mut(id2);
+                let (key, value) = (std::mem::replace(&mut node2.key, key), std::mem::replace(&mut node2.value, value));
+                let node1 = self.get_mut(id1);
+                node1.key = key;
+                node1.value = value;
+            }
+        }
+    }
+
+    pub fn get_root_nil(&self, root: &AVLChild) -> bool {
+        match root {
+            Some(target_ptr) => self.get(*target_ptr).is_nil,
+            None => true,
+        }
+    }
+
+
+    pub fn get_parent(&self, root: &AVLChild) -> AVLChild {
+        root.and_then(|id| self.get(id).parent)
+    }
+
+    pub fn is_leaf(&self, root: &AVLChild) -> bool {
         match root {
             Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                match (&node_ref.left_child, &node_ref.right_child) {
-                    (None, None) => return true,
-                    _ => return false,
-                };
+                let node_ref = self.get(*tree_ptr);
+                matches!((&node_ref.left_child, &node_ref.right_child), (None, None))
             },
             None => false,
         }
     }
 
-    pub fn get_parent_by_key(root: &AVLChild<T>, key: T) -> AVLChild<T> {
-        AVLTreeNode::get_parent(&AVLTreeNode::find_node(root, key))
+    #[cfg(feature = "unstable-internals")]
+    pub fn get_parent_by_key(&self, root: &AVLChild, key: &T) -> AVLChild where T: Ord {
+        self.get_parent(&self.find_node(root, key))
     }
 
 
-    pub fn set_parent(root: &AVLChild<T>, parent: &AVLChild<T>) {
-        match root {
-            Some(tree_ptr) => {
-                let mut node_ref = tree_ptr.borrow_mut();
-                match parent {
-                    Some(parent_ptr) => node_ref.parent = parent_ptr.borrow()._ptr_self.clone(),
-                    None => node_ref.parent = None,
-                }
-            },
-            None => todo!("not supported"),
+    pub fn set_parent(&mut self, root: &AVLChild, parent: &AVLChild) {
+        if let Some(id) = root {
+            self.get_mut(*id).parent = *parent;
         }
     }
 
 
-    pub fn set_child(root: &AVLChild<T>, child: AVLChild<T>, direction: Direction) {
-        match root {
-            Some(tree_ptr) => {
-                let mut node_ref = tree_ptr.borrow_mut();
-                match direction {
-                    Direction::Left => node_ref.left_child = child,
-                    Direction::Right => node_ref.right_child = child,
-                }
-            },
-            None => todo!("not supported"),
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_set_child(&mut self, root: &AVLChild, child: AVLChild, direction: Direction) -> Result<(), TreeError> {
+        if self.get_root_nil(root) {
+            return Err(TreeError::EmptyNode);
         }
+        self.set_child(root, child, direction);
+        Ok(())
     }
 
-    pub fn set_child_nil(root: &AVLChild<T>, direction: Direction) {
-        match root {
-            Some(_) => {
-                let nil_node = AVLTreeNode::_new(AVLTreeNode::get_root_key(root).clone(), None, true);
-                match direction {
-                    Direction::Left => {
-                        AVLTreeNode::set_child(root, nil_node, direction);
-                        AVLTreeNode::set_parent(&AVLTreeNode::get_left(root), root);
-                    },
-                    Direction::Right => {
-                        AVLTreeNode::set_child(root, nil_node, direction);
-                        AVLTreeNode::set_parent(&AVLTreeNode::get_right(root), root);
-                    },
-                }
-            },
-            None => todo!("not supported"),
+    pub fn set_child(&mut self, root: &AVLChild, child: AVLChild, direction: Direction) {
+        if let Some(tree_ptr) = root {
+            let node_ref = self.get_mut(*tree_ptr);
+            match direction {
+                Direction::Left => node_ref.left_child = child,
+                Direction::Right => node_ref.right_child = child,
+            }
         }
     }
 
-    pub fn solidify_all_nil(root: &AVLChild<T>) {
-        if let None = AVLTreeNode::get_left(root) {
-            AVLTreeNode::set_child_nil(root, Direction::Left);
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn set_child_nil(&mut self, root: &AVLChild, direction: Direction) {
+        if root.is_some() {
+            let nil_node = self._new(None, None, None, true);
+            match direction {
+                Direction::Left => {
+                    self.set_child(root, nil_node, direction);
+                    self.set_parent(&self.get_left(root), root);
+                },
+                Direction::Right => {
+                    self.set_child(root, nil_node, direction);
+                    self.set_parent(&self.get_right(root), root);
+                },
+            }
+        }
+    }
+
+    #[cfg(feature = "unstable-internals")]
+    pub fn solidify_all_nil(&mut self, root: &AVLChild) where T: Clone {
+        if self.get_left(root).is_none() {
+            self.set_child_nil(root, Direction::Left);
         } else {
-            AVLTreeNode::solidify_all_nil(&AVLTreeNode::get_left(root));
+            self.solidify_all_nil(&self.get_left(root));
         };
-        if let None = AVLTreeNode::get_right(root) {
-            AVLTreeNode::set_child_nil(root, Direction::Right);
+        if self.get_right(root).is_none() {
+            self.set_child_nil(root, Direction::Right);
         } else {
-            AVLTreeNode::solidify_all_nil(&AVLTreeNode::get_right(root));
+            self.solidify_all_nil(&self.get_right(root));
         };
     }
 
-    pub fn virtualize_all_nil(root: &AVLChild<T>) {
-        if AVLTreeNode::get_root_nil(&AVLTreeNode::get_left(root)) {
-            AVLTreeNode::set_child(root, None, Direction::Left);
+    #[cfg(feature = "unstable-internals")]
+    pub fn virtualize_all_nil(&mut self, root: &AVLChild) {
+        if self.get_root_nil(&self.get_left(root)) {
+            self.set_child(root, None, Direction::Left);
         } else {
-            AVLTreeNode::virtualize_all_nil(&AVLTreeNode::get_left(root));
+            self.virtualize_all_nil(&self.get_left(root));
         };
-        if AVLTreeNode::get_root_nil(&AVLTreeNode::get_right(root)) {
-            AVLTreeNode::set_child(root, None, Direction::Right);
+        if self.get_root_nil(&self.get_right(root)) {
+            self.set_child(root, None, Direction::Right);
         } else {
-            AVLTreeNode::virtualize_all_nil(&AVLTreeNode::get_right(root));
+            self.virtualize_all_nil(&self.get_right(root));
         };
     }
 
-    pub fn get_left(root: &AVLChild<T>) -> AVLChild<T> {
+    pub fn get_left(&self, root: &AVLChild) -> AVLChild {
+        root.and_then(|id| self.get(id).left_child)
+    }
+
+    pub fn get_minimum(&self, root: &AVLChild) -> AVLChild {
         match root {
             Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                if let Some(left) = &node_ref.left_child {
-                    return Some(left.clone());
-                } else {return None;}
+                let node_ref = self.get(*tree_ptr);
+                match &node_ref.left_child {
+                    Some(_) => {
+                        if self.get_root_nil(&node_ref.left_child) {
+                            return *root;
+                        }
+                        self.get_minimum(&node_ref.left_child)
+                    },
+                    None => *root,
+                }
             },
             None => None,
         }
     }
 
-    pub fn get_minimum(root: &AVLChild<T>) -> AVLChild<T> {
+    pub fn get_maximum(&self, root: &AVLChild) -> AVLChild {
         match root {
             Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                match &node_ref.left_child {
+                let node_ref = self.get(*tree_ptr);
+                match &node_ref.right_child {
                     Some(_) => {
-                        if AVLTreeNode::get_root_nil(&node_ref.left_child) {
-                            return root.clone();
+                        if self.get_root_nil(&node_ref.right_child) {
+                            return *root;
                         }
-                        return AVLTreeNode::get_minimum(&node_ref.left_child)
+                        self.get_maximum(&node_ref.right_child)
                     },
-                    None => return root.clone(),
+                    None => *root,
+                }
+            },
+            None => None,
+        }
+    }
+
+
+    pub fn get_successor(&self, root: &AVLChild) -> AVLChild {
+        let right = self.get_right(root);
+        if !self.get_root_nil(&right) {
+            return self.get_minimum(&right);
+        }
+        let mut node = *root;
+        let mut parent = self.get_parent(&node);
+        while !self.get_root_nil(&parent) && self.is_node_equal(&node, &self.get_right(&parent)) {
+            node = parent;
+            parent = self.get_parent(&node);
+        }
+        parent
+    }
+
+
+    pub fn get_predecessor(&self, root: &AVLChild) -> AVLChild {
+        let left = self.get_left(root);
+        if !self.get_root_nil(&left) {
+            return self.get_maximum(&left);
+        }
+        let mut node = *root;
+        let mut parent = self.get_parent(&node);
+        while !self.get_root_nil(&parent) && self.is_node_equal(&node, &self.get_left(&parent)) {
+            node = parent;
+            parent = self.get_parent(&node);
+        }
+        parent
+    }
+
+
+    pub fn satisfies_lower_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &AVLChild, bound: Bound<&Q>, cmp: &C) -> bool where T: borrow::Borrow<Q> {
+        match root {
+            Some(tree_ptr) => {
+                let node_ref = self.get(*tree_ptr);
+                let node_key = match &node_ref.key {
+                    Some(node_key) => node_key,
+                    None => return false,
+                };
+                match bound {
+                    Bound::Included(key) => cmp.compare(borrow::Borrow::borrow(node_key), key) != Ordering::Less,
+                    Bound::Excluded(key) => cmp.compare(borrow::Borrow::borrow(node_key), key) == Ordering::Greater,
+                    Bound::Unbounded => true,
                 }
             },
-            None => todo!("not supported"),
+            None => false,
         }
     }
 
-    pub fn get_right(root: &AVLChild<T>) -> AVLChild<T> {
+
+    pub fn satisfies_upper_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &AVLChild, bound: Bound<&Q>, cmp: &C) -> bool where T: borrow::Borrow<Q> {
         match root {
             Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                if let Some(left) = &node_ref.right_child {
-                    return Some(left.clone());
-                } else {return None;}
+                let node_ref = self.get(*tree_ptr);
+                let node_key = match &node_ref.key {
+                    Some(node_key) => node_key,
+                    None => return false,
+                };
+                match bound {
+                    Bound::Included(key) => cmp.compare(borrow::Borrow::borrow(node_key), key) != Ordering::Greater,
+                    Bound::Excluded(key) => cmp.compare(borrow::Borrow::borrow(node_key), key) == Ordering::Less,
+                    Bound::Unbounded => true,
+                }
             },
-            None => None,
+            None => false,
         }
     }
 
 
-    pub fn insert_node(root: &AVLChild<T>, key: T) {
-        let _ = AVLTreeNode::_recurse_node(root, key, true);
+    pub fn find_lower_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &AVLChild, bound: Bound<&Q>, cmp: &C) -> AVLChild where T: borrow::Borrow<Q> {
+        /*
+            Returns the node with the smallest key satisfying the lower bound, descending once from root.
+        */
+        let mut node = *root;
+        let mut found = None;
+        while !self.get_root_nil(&node) {
+            if self.satisfies_lower_bound(&node, bound, cmp) {
+                let left = self.get_left(&node);
+                found = node;
+                node = left;
+            } else {
+                node = self.get_right(&node);
+            }
+        }
+        found
     }
 
 
-    pub fn find_node(root: &AVLChild<T>, key: T) -> AVLChild<T> {
-        AVLTreeNode::_recurse_node(root, key, false)
+    pub fn find_upper_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &AVLChild, bound: Bound<&Q>, cmp: &C) -> AVLChild where T: borrow::Borrow<Q> {
+        /*
+            Returns the node with the largest key satisfying the upper bound, descending once from root.
+        */
+        let mut node = *root;
+        let mut found = None;
+        while !self.get_root_nil(&node) {
+            if self.satisfies_upper_bound(&node, bound, cmp) {
+                let right = self.get_right(&node);
+                found = node;
+                node = right;
+            } else {
+                node = self.get_left(&node);
+            }
+        }
+        found
     }
 
 
-    fn _recurse_node(root: &AVLChild<T>, key: T, insert: bool) -> AVLChild<T> {
+    pub fn get_size(&self, root: &AVLChild) -> usize {
         match root {
-            Some(tree_ptr) => {
-                
-                let mut node_ref = tree_ptr.borrow_mut();
-
-                match key.cmp(&node_ref.key) {
-
-                    Ordering::Less => {
-                        match node_ref.left_child {
-                            Some(_) => AVLTreeNode::_recurse_node(&node_ref.left_child, key, insert).clone(),
-                            None => {
-                                if insert {
-                                    node_ref.left_child = AVLTreeNode::_new(key, node_ref._ptr_self.clone(), false);
-                                    if let Some(insert_ptr) = &node_ref.left_child {
-                                        return Some(insert_ptr.clone());
-                                    } else {todo!("should never reach here")}
-
-                                } else {return None;}
-                            },
-                        }
-                    },
-                    Ordering::Equal => return Some(tree_ptr.clone()),
-                    Ordering::Greater => {
-                        match node_ref.right_child {
-                            Some(_) => AVLTreeNode::_recurse_node(&node_ref.right_child, key, insert).clone(),
-                            None => {
-                                if insert {
-                                    node_ref.right_child = AVLTreeNode::_new(key, node_ref._ptr_self.clone(), false);
-                                    if let Some(insert_ptr) = &node_ref.right_child {
-                                        return Some(insert_ptr.clone());
-                                    } else {todo!("should never reach here")}
-                                } else {return None;}
-                            },
-                        }
-                    },
+            Some(root_ptr) => self.get(*root_ptr).size,
+            None => 0,
+        }
+    }
+
+
+    #[cfg(feature = "unstable-internals")]
+    pub fn set_size(&mut self, root: &AVLChild, size: usize) {
+        if let Some(root_ptr) = root {
+            self.get_mut(*root_ptr).size = size;
+        }
+    }
+
+
+    pub fn update_size(&mut self, root: &AVLChild) {
+        // the summary depends on the same children as the size, so both are kept up to date together
+        if let Some(id) = root {
+            self._refresh(*id);
+        }
+    }
+
+
+    pub fn update_size_to_root(&mut self, root: &AVLChild) {
+        let mut node = *root;
+        while !self.get_root_nil(&node) {
+            self.update_size(&node);
+            node = self.get_parent(&node);
+        }
+    }
+
+
+    pub fn select_node(&self, root: &AVLChild, index: usize) -> AVLChild {
+        /*
+            Returns the node holding the index-th smallest key (counting from 0) using the subtree sizes.
+        */
+        let mut node = *root;
+        let mut index = index;
+        while !self.get_root_nil(&node) {
+            let left_size = self.get_size(&self.get_left(&node));
+            match index.cmp(&left_size) {
+                Ordering::Less => node = self.get_left(&node),
+                Ordering::Equal => return node,
+                Ordering::Greater => {
+                    index -= left_size + 1;
+                    node = self.get_right(&node);
+                },
+            }
+        }
+        None
+    }
+
+
+    pub fn count_below_lower_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &AVLChild, bound: Bound<&Q>, cmp: &C) -> usize where T: borrow::Borrow<Q> {
+        let mut node = *root;
+        let mut count = 0;
+        while !self.get_root_nil(&node) {
+            if self.satisfies_lower_bound(&node, bound, cmp) {
+                node = self.get_left(&node);
+            } else {
+                count += self.get_size(&self.get_left(&node)) + 1;
+                node = self.get_right(&node);
+            }
+        }
+        count
+    }
+
+
+    pub fn count_within_upper_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &AVLChild, bound: Bound<&Q>, cmp: &C) -> usize where T: borrow::Borrow<Q> {
+        let mut node = *root;
+        let mut count = 0;
+        while !self.get_root_nil(&node) {
+            if self.satisfies_upper_bound(&node, bound, cmp) {
+                count += self.get_size(&self.get_left(&node)) + 1;
+                node = self.get_right(&node);
+            } else {
+                node = self.get_left(&node);
+            }
+        }
+        count
+    }
+
+
+    pub fn get_summary(&self, root: &AVLChild) -> A::Summary {
+        self._subtree_totals(root).1
+    }
+
+
+    fn _entry_summary(&self, root: &AVLChild) -> A::Summary {
+        match root {
+            Some(root_ptr) => {
+                let node_ref = self.get(*root_ptr);
+                match (&node_ref.key, &node_ref.value) {
+                    (Some(key), Some(value)) if !node_ref.is_nil => A::summarize(key, value),
+                    _ => A::identity(),
                 }
             },
-            None => {return None;},
+            None => A::identity(),
+        }
+    }
+
+
+    pub fn fold_range<Q: ?Sized, C: Comparator<Q>>(&self, root: &AVLChild, lower: Bound<&Q>, upper: Bound<&Q>, cmp: &C) -> A::Summary where T: borrow::Borrow<Q> {
+        /*
+            Combines the summaries of all entries within the bounds in key order. Once the descent
+            reaches the first node inside both bounds, each side only has one bound left to check, so
+            whole subtrees are taken from their stored summaries and the cost stays O(log n).
+        */
+        let mut node = *root;
+        while !self.get_root_nil(&node) {
+            if !self.satisfies_lower_bound(&node, lower, cmp) {
+                node = self.get_right(&node);
+            } else if !self.satisfies_upper_bound(&node, upper, cmp) {
+                node = self.get_left(&node);
+            } else {
+                let below = self._fold_from(&self.get_left(&node), lower, cmp);
+                let above = self._fold_to(&self.get_right(&node), upper, cmp);
+                return A::combine(&A::combine(&below, &self._entry_summary(&node)), &above);
+            }
+        }
+        A::identity()
+    }
+
+
+    fn _fold_from<Q: ?Sized, C: Comparator<Q>>(&self, root: &AVLChild, lower: Bound<&Q>, cmp: &C) -> A::Summary where T: borrow::Borrow<Q> {
+        // everything folded so far lies to the right of the current subtree
+        let mut node = *root;
+        let mut folded = A::identity();
+        while !self.get_root_nil(&node) {
+            if self.satisfies_lower_bound(&node, lower, cmp) {
+                let right = A::combine(&self._entry_summary(&node), &self.get_summary(&self.get_right(&node)));
+                folded = A::combine(&right, &folded);
+                node = self.get_left(&node);
+            } else {
+                node = self.get_right(&node);
+            }
+        }
+        folded
+    }
+
+
+    fn _fold_to<Q: ?Sized, C: Comparator<Q>>(&self, root: &AVLChild, upper: Bound<&Q>, cmp: &C) -> A::Summary where T: borrow::Borrow<Q> {
+        // everything folded so far lies to the left of the current subtree
+        let mut node = *root;
+        let mut folded = A::identity();
+        while !self.get_root_nil(&node) {
+            if self.satisfies_upper_bound(&node, upper, cmp) {
+                let left = A::combine(&self.get_summary(&self.get_left(&node)), &self._entry_summary(&node));
+                folded = A::combine(&folded, &left);
+                node = self.get_right(&node);
+            } else {
+                node = self.get_left(&node);
+            }
+        }
+        folded
+    }
+
+
+    pub fn validate<C: Comparator<T>>(&self, root: &AVLChild, cmp: &C) -> Result<(), TreeViolation> where T: Debug {
+        /*
+            Checks the whole tree below root: keys in order, parent links, subtree sizes, stored
+            heights and a balance factor within one at every node.
+        */
+        self._validate(root, &None, &None, &None, cmp).map(|_| ())
+    }
+
+
+    fn _validate<C: Comparator<T>>(&self, root: &AVLChild, parent: &AVLChild, lower: &AVLChild, upper: &AVLChild, cmp: &C) -> Result<u128, TreeViolation> where T: Debug {
+        // Returns the actual height of root; lower and upper are the closest ancestors on either side.
+        if self.get_root_nil(root) {
+            return Ok(0);
+        }
+        let key = || format!("{:?}", *self.get_root_key_ref(root));
+        if self.get_parent(root) != *parent {
+            return Err(TreeViolation::BrokenParentLink { key: key() });
+        }
+        let above_lower = self.get_root_nil(lower)
+            || cmp.compare(self.get_root_key_ref(lower), self.get_root_key_ref(root)) == Ordering::Less;
+        let below_upper = self.get_root_nil(upper)
+            || cmp.compare(self.get_root_key_ref(root), self.get_root_key_ref(upper)) == Ordering::Less;
+        if !above_lower || !below_upper {
+            return Err(TreeViolation::OutOfOrder { key: key() });
+        }
+        let (left, right) = (self.get_left(root), self.get_right(root));
+        let left_height = self._validate(&left, root, lower, root, cmp)?;
+        let right_height = self._validate(&right, root, root, upper, cmp)?;
+        let (stored, actual) = (self.get_size(root), self.get_size(&left) + self.get_size(&right) + 1);
+        if stored != actual {
+            return Err(TreeViolation::WrongSize { key: key(), stored, actual });
+        }
+        let (stored, actual) = (self.get_height(root), 1 + max(left_height, right_height));
+        if stored != actual {
+            return Err(TreeViolation::WrongHeight { key: key(), stored, actual });
+        }
+        let balance_factor = right_height as i64 - left_height as i64;
+        if balance_factor.abs() > 1 {
+            return Err(TreeViolation::Unbalanced { key: key(), balance_factor });
+        }
+        Ok(actual)
+    }
+
+
+    pub fn free_entry(&mut self, root: AVLChild) -> Option<(T, V)> {
+        // Takes the entry out of an unlinked node and frees its slot, like `RBArena::free_entry`.
+        let id = root?;
+        let node_mut = self.get_mut(id);
+        let entry = (node_mut.key.take(), node_mut.value.take());
+        self.free(id);
+        match entry {
+            (Some(key), Some(value)) => Some((key, value)),
+            _ => None,
+        }
+    }
+
+
+    pub fn adopt(&mut self, other: &mut Self, root: &AVLChild) -> AVLChild {
+        // Moves a subtree over from another arena with its heights, sizes and summaries.
+        self._adopt(other, root, None)
+    }
+
+
+    fn _adopt(&mut self, other: &mut Self, root: &AVLChild, parent: AVLChild) -> AVLChild {
+        let id = (*root)?;
+        let (left, right) = (other.get_left(root), other.get_right(root));
+        let node = other.get_mut(id);
+        let moved = Some(self.alloc(AVLTreeNode {
+            key: node.key.take(),
+            value: node.value.take(),
+            parent,
+            left_child: None,
+            right_child: None,
+            is_nil: node.is_nil,
+            height: node.height,
+            size: node.size,
+            summary: std::mem::replace(&mut node.summary, A::identity()),
+        }));
+        other.free(id);
+        let (left, right) = (self._adopt(other, &left, moved), self._adopt(other, &right, moved));
+        self.set_child(&moved, left, Direction::Left);
+        self.set_child(&moved, right, Direction::Right);
+        moved
+    }
+
+
+    fn _discard(&mut self, root: &AVLChild) {
+        if root.is_some() {
+            let (left, right) = (self.get_left(root), self.get_right(root));
+            self._discard(&left);
+            self._discard(&right);
+            self.free_entry(*root);
+        }
+    }
+
+
+    fn _detach(&mut self, root: &AVLChild) -> (AVLChild, AVLChild) {
+        let (left, right) = (self.get_left(root), self.get_right(root));
+        self.set_child(root, None, Direction::Left);
+        self.set_child(root, None, Direction::Right);
+        self.set_parent(&left, &None);
+        self.set_parent(&right, &None);
+        self.set_parent(root, &None);
+        self.update_height(root);
+        self.update_size(root);
+        (left, right)
+    }
+
+
+    fn _link(&mut self, root: &AVLChild, left: AVLChild, right: AVLChild) {
+        self.set_parent(&left, root);
+        self.set_parent(&right, root);
+        self.set_child(root, left, Direction::Left);
+        self.set_child(root, right, Direction::Right);
+        self.update_height(root);
+        self.update_size(root);
+    }
+
+
+    fn _rotate_left_at(&mut self, x: &AVLChild) -> AVLChild {
+        let y = self.get_right(x);
+        self._link(x, self.get_left(x), self.get_left(&y));
+        self._link(&y, *x, self.get_right(&y));
+        y
+    }
+
+
+    fn _rotate_right_at(&mut self, x: &AVLChild) -> AVLChild {
+        let y = self.get_left(x);
+        self._link(x, self.get_right(&y), self.get_right(x));
+        self._link(&y, self.get_left(&y), *x);
+        y
+    }
+
+
+    fn _join_right(&mut self, left: &AVLChild, pivot: &AVLChild, right: &AVLChild) -> AVLChild {
+        /*
+            Walks down the right spine of the taller left tree until the subtree there is at most one
+            level taller than right, hangs pivot there and rebalances on the way back up.
+        */
+        let (inner_left, inner_right) = (self.get_left(left), self.get_right(left));
+        if self.get_height(&inner_right) <= self.get_height(right) + 1 {
+            self._link(pivot, inner_right, *right);
+            if self.get_height(pivot) <= self.get_height(&inner_left) + 1 {
+                self._link(left, inner_left, *pivot);
+                return *left;
+            }
+            let rotated = self._rotate_right_at(pivot);
+            self._link(left, inner_left, rotated);
+            return self._rotate_left_at(left);
+        }
+        let joined = self._join_right(&inner_right, pivot, right);
+        self._link(left, inner_left, joined);
+        if self.get_height(&joined) <= self.get_height(&inner_left) + 1 {
+            return *left;
+        }
+        self._rotate_left_at(left)
+    }
+
+
+    fn _join_left(&mut self, left: &AVLChild, pivot: &AVLChild, right: &AVLChild) -> AVLChild {
+        let (inner_left, inner_right) = (self.get_left(right), self.get_right(right));
+        if self.get_height(&inner_left) <= self.get_height(left) + 1 {
+            self._link(pivot, *left, inner_left);
+            if self.get_height(pivot) <= self.get_height(&inner_right) + 1 {
+                self._link(right, *pivot, inner_right);
+                return *right;
+            }
+            let rotated = self._rotate_left_at(pivot);
+            self._link(right, rotated, inner_right);
+            return self._rotate_right_at(right);
+        }
+        let joined = self._join_left(left, pivot, &inner_left);
+        self._link(right, joined, inner_right);
+        if self.get_height(&joined) <= self.get_height(&inner_right) + 1 {
+            return *right;
+        }
+        self._rotate_right_at(right)
+    }
+
+
+    pub fn join(&mut self, left: &AVLChild, pivot: &AVLChild, right: &AVLChild) -> AVLChild {
+        /*
+            Joins two trees and a detached pivot node, where every key of left < pivot < every key of right.
+            The work is proportional to the difference of the stored heights.
+        */
+        let (left_height, right_height) = (self.get_height(left), self.get_height(right));
+        let joined = if left_height > right_height + 1 {
+            self._join_right(left, pivot, right)
+        } else if right_height > left_height + 1 {
+            self._join_left(left, pivot, right)
+        } else {
+            self._link(pivot, *left, *right);
+            *pivot
+        };
+        self.set_parent(&joined, &None);
+        joined
+    }
+
+
+    pub fn join_concat(&mut self, left: &AVLChild, right: &AVLChild) -> AVLChild {
+        /*
+            Joins two trees without a pivot by borrowing the maximum of left as the pivot.
+        */
+        if self.get_root_nil(left) {
+            return *right;
+        }
+        let (rest, last) = self.split_last(left);
+        self.join(&rest, &last, right)
+    }
+
+
+    pub fn split_last(&mut self, root: &AVLChild) -> (AVLChild, AVLChild) {
+        /*
+            Detaches the maximum node of root, returning the remaining tree and the maximum as a single node.
+        */
+        let (left, right) = self._detach(root);
+        if self.get_root_nil(&right) {
+            return (left, *root);
+        }
+        let (rest, last) = self.split_last(&right);
+        (self.join(&left, root, &rest), last)
+    }
+
+
+    pub fn split<Q: ?Sized, C: Comparator<Q>>(&mut self, root: &AVLChild, key: &Q, cmp: &C) -> AVLSplit where T: borrow::Borrow<Q> {
+        /*
+            Splits root into the keys below key and the keys above key. The node holding key itself, if
+            any, is returned detached in the middle.
+        */
+        if self.get_root_nil(root) {
+            return (None, None, None);
+        }
+        let (left, right) = self._detach(root);
+        let ordering = cmp.compare(key, borrow::Borrow::borrow(self.get(root.unwrap())._key()));
+        match ordering {
+            Ordering::Equal => (left, *root, right),
+            Ordering::Less => {
+                let (less, found, greater) = self.split(&left, key, cmp);
+                (less, found, self.join(&greater, root, &right))
+            },
+            Ordering::Greater => {
+                let (less, found, greater) = self.split(&right, key, cmp);
+                (self.join(&left, root, &less), found, greater)
+            },
+        }
+    }
+
+
+    pub fn build_sorted<I: Iterator<Item = (T, V)>>(&mut self, entries: &mut I, len: usize) -> AVLChild {
+        /*
+            Builds a tree from len entries in ascending key order in O(len). The middle entry becomes the
+            root, so the two subtree sizes (and with them the heights) differ by at most one.
+        */
+        if len == 0 {
+            return None;
+        }
+        let left_len = (len - 1) / 2;
+        let left = self.build_sorted(entries, left_len);
+        let (key, value) = entries.next()?;
+        let root = self._new(Some(key), Some(value), None, false);
+        let right = self.build_sorted(entries, len - 1 - left_len);
+        self._link(&root, left, right);
+        root
+    }
+
+
+    fn _split_by<C: Comparator<T>>(&mut self, root: &AVLChild, pivot: &AVLChild, cmp: &C) -> AVLSplit {
+        let pivot_id = pivot.expect("the pivot is a node");
+        let key = self.get_mut(pivot_id).key.take().expect("NIL nodes have no key");
+        let split = self.split(root, &key, cmp);
+        self.get_mut(pivot_id).key = Some(key);
+        split
+    }
+
+
+    /*
+        The set operations below all work the same way: the root of one tree splits the other one,
+        both halves are combined recursively and the results are joined back together. Trees are
+        consumed; nodes that do not make it into the result go back to the arena.
+    */
+    pub fn union<C: Comparator<T>>(&mut self, root: &AVLChild, other: &AVLChild, cmp: &C) -> AVLChild {
+        // keys present in both trees keep the node from root
+        if self.get_root_nil(root) {
+            return *other;
+        }
+        if self.get_root_nil(other) {
+            return *root;
+        }
+        let (left, right) = self._detach(root);
+        let (less, found, greater) = self._split_by(other, root, cmp);
+        self._discard(&found);
+        let left = self.union(&left, &less, cmp);
+        let right = self.union(&right, &greater, cmp);
+        self.join(&left, root, &right)
+    }
+
+
+    pub fn intersection<C: Comparator<T>>(&mut self, root: &AVLChild, other: &AVLChild, cmp: &C) -> AVLChild {
+        if self.get_root_nil(root) || self.get_root_nil(other) {
+            self._discard(root);
+            self._discard(other);
+            return None;
+        }
+        let (left, right) = self._detach(root);
+        let (less, found, greater) = self._split_by(other, root, cmp);
+        let left = self.intersection(&left, &less, cmp);
+        let right = self.intersection(&right, &greater, cmp);
+        if self.get_root_nil(&found) {
+            self._discard(root);
+            return self.join_concat(&left, &right);
+        }
+        self._discard(&found);
+        self.join(&left, root, &right)
+    }
+
+
+    pub fn difference<C: Comparator<T>>(&mut self, root: &AVLChild, other: &AVLChild, cmp: &C) -> AVLChild {
+        if self.get_root_nil(root) || self.get_root_nil(other) {
+            self._discard(other);
+            return *root;
+        }
+        let (other_left, other_right) = self._detach(other);
+        let (less, found, greater) = self._split_by(root, other, cmp);
+        self._discard(&found);
+        self._discard(other);
+        let left = self.difference(&less, &other_left, cmp);
+        let right = self.difference(&greater, &other_right, cmp);
+        self.join_concat(&left, &right)
+    }
+
+
+    pub fn symmetric_difference<C: Comparator<T>>(&mut self, root: &AVLChild, other: &AVLChild, cmp: &C) -> AVLChild {
+        if self.get_root_nil(root) {
+            return *other;
+        }
+        if self.get_root_nil(other) {
+            return *root;
+        }
+        let (left, right) = self._detach(root);
+        let (less, found, greater) = self._split_by(other, root, cmp);
+        let left = self.symmetric_difference(&left, &less, cmp);
+        let right = self.symmetric_difference(&right, &greater, cmp);
+        if self.get_root_nil(&found) {
+            return self.join(&left, root, &right);
+        }
+        self._discard(root);
+        self._discard(&found);
+        self.join_concat(&left, &right)
+    }
+
+
+    pub fn get_right(&self, root: &AVLChild) -> AVLChild {
+        root.and_then(|id| self.get(id).right_child)
+    }
+
+
+    pub fn find_slot<Q: ?Sized, C: Comparator<Q>>(&self, root: &AVLChild, key: &Q, cmp: &C) -> AVLSlot where T: borrow::Borrow<Q> {
+        /*
+            Descends once towards key. Returns the node holding key, or the node (None for an empty tree)
+            whose child slot on the given side is where key would have to be inserted.
+        */
+        let mut node = *root;
+        let mut slot = (None, Direction::Left);
+        while !self.get_root_nil(&node) {
+            let ordering = cmp.compare(key, borrow::Borrow::borrow(self.get(node.unwrap())._key()));
+            match ordering {
+                Ordering::Less => {
+                    let left = self.get_left(&node);
+                    slot = (node, Direction::Left);
+                    node = left;
+                },
+                Ordering::Equal => return Ok(node),
+                Ordering::Greater => {
+                    let right = self.get_right(&node);
+                    slot = (node, Direction::Right);
+                    node = right;
+                },
+            }
+        }
+        Err(slot)
+    }
+
+
+    pub fn attach_entry(&mut self, parent: &AVLChild, key: T, value: V, direction: Direction) -> AVLChild {
+        let node = self._new(Some(key), Some(value), None, false);
+        self.set_parent(&node, parent);
+        self.set_child(parent, node, direction);
+        node
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn insert_entry_node(&mut self, root: &AVLChild, key: T, value: V) -> AVLChild where T: Ord {
+        self._recurse_node(root, key, Some(value), &NaturalOrder)
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn find_node(&self, root: &AVLChild, key: &T) -> AVLChild where T: Ord {
+        self.find_node_by(root, key, &NaturalOrder)
+    }
+
+
+    pub fn find_node_by<Q: ?Sized, C: Comparator<Q>>(&self, root: &AVLChild, key: &Q, cmp: &C) -> AVLChild where T: borrow::Borrow<Q> {
+        self.find_slot(root, key, cmp).ok().flatten()
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    fn _recurse_node<C: Comparator<T>>(&mut self, root: &AVLChild, key: T, insert: Option<V>, cmp: &C) -> AVLChild {
+        let id = (*root)?;
+        let ordering = match &self.get(id).key {
+            Some(node_key) => cmp.compare(&key, node_key),
+            None => return None,
+        };
+        let (child, direction) = match ordering {
+            Ordering::Less => (self.get_left(root), Direction::Left),
+            Ordering::Equal => return Some(id),
+            Ordering::Greater => (self.get_right(root), Direction::Right),
+        };
+        match child {
+            Some(_) => self._recurse_node(&child, key, insert, cmp),
+            None => {
+                insert.as_ref()?;
+                let node = self._new(Some(key), insert, *root, false);
+                self.set_child(root, node, direction);
+                node
+            },
         }
     }
 }
\ No newline at end of file
@@ -1,75 +1,216 @@
 use super::*;
-use std::{fmt::Debug, cmp::Ordering};
-use avl_tree::{AVLTreeNode as AVL, Direction as Dir, AVLChild as TRoot};
-
-#[derive(Clone, Debug)]
-pub struct AVLTree<T: Ord + Clone> {
-    pub root: TRoot<T>,
-    pub num_leaves: u128,
-    pub height: u128,
+use std::{fmt, fmt::Debug, cmp::Ordering};
+use std::borrow::Borrow;
+use std::collections::{VecDeque, vec_deque};
+use std::iter::Peekable;
+use std::ops::{Bound, RangeBounds, BitAnd, BitOr, BitXor, Sub};
+use search_tree::SearchTree;
+use comparator::{Comparator, NaturalOrder};
+use augment::Augment;
+use tree_violation::TreeViolation;
+use tree_error::TreeError;
+use avl_tree::{AVLArena, Direction as Dir, AVLChild as TRoot, AVLSlot};
+
+// `avl_tree` is only public with `unstable-internals`, so its handle types are exported from here.
+pub use avl_tree::{AVLEntryRef, AVLEntryMut, AVLNodeView};
+
+/*
+    Keys are ordered by `C`, which is the key's own `Ord` unless the tree is built with
+    `with_comparator`.
+*/
+pub struct AVLTree<T, V = (), C = NaturalOrder, A: Augment<T, V> = ()> {
+    pub(crate) root: TRoot,
+    pub(crate) nodes: AVLArena<T, V, A>,
+    num_leaves: u128,
+    height: u128,
     _is_num_leaves_available: bool,
     _is_height_available: bool,
+    comparator: C,
+}
+
+impl<T: Debug, C: Comparator<T>, A: Augment<T, ()>> AVLTree<T, (), C, A> {
+
+    // Adds `key` and returns whether it was new; a key already in the tree is kept as it is.
+    pub fn insert(&mut self, key: T) -> bool {
+        match self.find_slot(&key) {
+            Ok(_) => false,
+            Err((parent, direction)) => {
+                self.insert_at(&parent, direction, key, ());
+                true
+            },
+        }
+    }
+
+    // Adds `key`, swapping out an equal key already in the tree, which is returned.
+    pub fn replace(&mut self, key: T) -> Option<T> {
+        match self.find_slot(&key) {
+            Ok(existing) => self.nodes.replace_root_key(&existing, key),
+            Err((parent, direction)) => {
+                self.insert_at(&parent, direction, key, ());
+                None
+            },
+        }
+    }
+
+    // Same as `delete`; named after `BTreeSet::take`.
+    pub fn take<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        self.delete(key)
+    }
+
+    pub fn pop_first(&mut self) -> Option<T> {
+        self.remove_at(0)
+    }
+
+    pub fn pop_last(&mut self) -> Option<T> {
+        let last = self.len().checked_sub(1)?;
+        self.remove_at(last)
+    }
+
+    pub fn remove_at(&mut self, index: usize) -> Option<T> {
+        self.remove_entry_at(index).map(|(key, _)| key)
+    }
+
+    // Like `insert`, but a key that is already present is an error.
+    pub fn try_insert(&mut self, key: T) -> Result<(), TreeError> {
+        match self.insert(key) {
+            true => Ok(()),
+            false => Err(TreeError::DuplicateKey),
+        }
+    }
+
+    pub fn try_remove_at(&mut self, index: usize) -> Result<T, TreeError> {
+        let len = self.len();
+        self.remove_at(index).ok_or(TreeError::IndexOutOfBounds { index, len })
+    }
+
+    pub fn join(left: Self, pivot: T, right: Self) -> Self {
+        Self::join_entry(left, pivot, (), right)
+    }
+
+    pub fn bulk_load<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.load_entries(iter.into_iter().map(|key| (key, ())).collect())
+    }
 }
 
-impl<T: Ord + Clone + Debug> AVLTree<T> {
+impl<T: Ord + Debug, V> AVLTree<T, V> {
+
+    pub fn new() -> AVLTree<T, V> {
+        AVLTree::with_comparator(NaturalOrder)
+    }
+}
 
-    pub fn new() -> AVLTree<T> {
+impl<T: Ord + Debug> AVLTree<T> {
+
+    /*
+        Builds a balanced tree directly in O(n) when the keys arrive in ascending order. Any other
+        input is sorted and deduplicated first.
+    */
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_entries(iter.into_iter().map(|key| (key, ())).collect(), NaturalOrder)
+    }
+}
+
+impl<T: Debug, V, C: Comparator<T>> AVLTree<T, V, C> {
+
+    pub fn with_comparator(comparator: C) -> AVLTree<T, V, C> {
+        AVLTree::augmented(comparator)
+    }
+}
+
+impl<T: Debug, V, C: Comparator<T>, A: Augment<T, V>> AVLTree<T, V, C, A> {
+
+    /*
+        Empty tree that keeps the summaries of `A` for `fold_range`, e.g.
+        `AVLTree::<u64, u64, NaturalOrder, SumOfValues>::augmented(NaturalOrder)`.
+    */
+    pub fn augmented(comparator: C) -> AVLTree<T, V, C, A> {
         AVLTree { 
             root: None, 
+            nodes: AVLArena::new(),
             num_leaves: 0, 
             height: 0,
             _is_num_leaves_available: false,
-            _is_height_available: false
+            _is_height_available: false,
+            comparator,
         }
     }
 
-    pub fn search(&self, key: &T) -> TRoot<T> {
-        AVL::find_node(&self.root, key.clone())
+    pub fn search<Q: ?Sized>(&self, key: &Q) -> Option<AVLNodeView<'_, T, V, A>> where T: Borrow<Q>, C: Comparator<Q> {
+        AVLNodeView::from_node(&self.nodes, &self.find_node(key))
+    }
+
+    pub(crate) fn find_node<Q: ?Sized>(&self, key: &Q) -> TRoot where T: Borrow<Q>, C: Comparator<Q> {
+        self.nodes.find_node_by(&self.root, key, &self.comparator)
+    }
+
+    pub fn root_node(&self) -> Option<AVLNodeView<'_, T, V, A>> {
+        AVLNodeView::from_node(&self.nodes, &self.root)
+    }
+
+    // `unstable-internals` only: hands out the root node itself; heights are not rechecked afterwards.
+    #[cfg(feature = "unstable-internals")]
+    pub fn raw_root(&self) -> &TRoot {
+        &self.root
+    }
+
+    #[cfg(feature = "unstable-internals")]
+    pub fn raw_root_mut(&mut self) -> &mut TRoot {
+        self._is_num_leaves_available = false;
+        self._is_height_available = false;
+        &mut self.root
     }
 
     pub fn get_num_leaves(&mut self) -> u128 {
         if self._is_num_leaves_available {
             return self.num_leaves;
         }
-        self.num_leaves = AVL::count_leaves(&self.root);
+        self.num_leaves = self.nodes.count_leaves(&self.root);
         self._is_num_leaves_available = true;
-        return self.num_leaves;
+        self.num_leaves
     }
 
     pub fn get_height(&mut self) -> u128 {
         if self._is_height_available {
             return self.height;
         }
-        self.height = AVL::get_height(&self.root);
+        self.height = self.nodes.get_height(&self.root);
         self._is_height_available = true;
-        return self.height;
+        self.height
+    }
+
+    /*
+        Checks every structural rule of the tree in O(n) and reports the first broken one, e.g. to
+        call after each operation in tests or debug builds.
+    */
+    pub fn validate(&self) -> Result<(), TreeViolation> {
+        self.nodes.validate(&self.root, &self.comparator)
     }
 
     pub fn is_empty(&self) -> bool {
-        AVL::get_root_nil(&self.root)
+        self.nodes.get_root_nil(&self.root)
     }
 
-    fn asc_print(root: &TRoot<T>) {
-        let left = AVL::get_left(root);
-        if !AVL::get_root_nil(&left) {
-            Self::asc_print(&left);
+    fn asc_print(&self, root: &TRoot) {
+        let left = self.nodes.get_left(root);
+        if !self.nodes.get_root_nil(&left) {
+            self.asc_print(&left);
         }
-        print!("{:?} -> ", AVL::get_root_key(root));
-        let right = AVL::get_right(root);
-        if !AVL::get_root_nil(&right) {
-            Self::asc_print(&right);
+        print!("{:?} -> ", self.nodes.get_root_key_ref(root));
+        let right = self.nodes.get_right(root);
+        if !self.nodes.get_root_nil(&right) {
+            self.asc_print(&right);
         }
     }
 
-    fn desc_print(root: &TRoot<T>) {
-        let right = AVL::get_right(root);
-        if !AVL::get_root_nil(&right) {
-            Self::desc_print(&right);
+    fn desc_print(&self, root: &TRoot) {
+        let right = self.nodes.get_right(root);
+        if !self.nodes.get_root_nil(&right) {
+            self.desc_print(&right);
         }
-        print!("{:?} -> ", AVL::get_root_key(root));
-        let left = AVL::get_left(root);
-        if !AVL::get_root_nil(&left) {
-            Self::desc_print(&left);
+        print!("{:?} -> ", self.nodes.get_root_key_ref(root));
+        let left = self.nodes.get_left(root);
+        if !self.nodes.get_root_nil(&left) {
+            self.desc_print(&left);
         }
     }
 
@@ -80,52 +221,331 @@ impl<T: Ord + Clone + Debug> AVLTree<T> {
         }
         match order {
             Ordering::Less => {
-                Self::asc_print(&self.root);
+                self.asc_print(&self.root);
                 println!("done");
             },
             Ordering::Equal => println!("Choose Less or Greater please."),
             Ordering::Greater => {
-                Self::desc_print(&self.root);
+                self.desc_print(&self.root);
                 println!("done");
             },
         }
     }
 
     pub fn print_tree(&self) {
-        AVL::print_tree(&self.root)
+        self.nodes.print_tree(&self.root)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, V, A> {
+        if self.is_empty() {
+            return Iter { nodes: &self.nodes, front: None, back: None };
+        }
+        Iter {
+            nodes: &self.nodes,
+            front: self.nodes.get_minimum(&self.root),
+            back: self.nodes.get_maximum(&self.root),
+        }
+    }
+
+    pub fn range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> Range<'_, T, V, A> where T: Borrow<Q>, C: Comparator<Q> {
+        let front = self.nodes.find_lower_bound(&self.root, range.start_bound(), &self.comparator);
+        if !self.nodes.satisfies_upper_bound(&front, range.end_bound(), &self.comparator) {
+            return Range { iter: Iter { nodes: &self.nodes, front: None, back: None } };
+        }
+        Range {
+            iter: Iter {
+                nodes: &self.nodes,
+                front,
+                back: self.nodes.find_upper_bound(&self.root, range.end_bound(), &self.comparator),
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.get_size(&self.root)
+    }
+
+    pub fn select(&self, index: usize) -> Option<AVLEntryRef<'_, T, V, A>> {
+        AVLEntryRef::from_node(&self.nodes, &self.nodes.select_node(&self.root, index))
+    }
+
+    pub fn rank<Q: ?Sized>(&self, key: &Q) -> usize where T: Borrow<Q>, C: Comparator<Q> {
+        self.nodes.count_below_lower_bound(&self.root, Bound::Included(key), &self.comparator)
+    }
+
+    pub fn count_range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> usize where T: Borrow<Q>, C: Comparator<Q> {
+        let below = self.nodes.count_below_lower_bound(&self.root, range.start_bound(), &self.comparator);
+        let upto = self.nodes.count_within_upper_bound(&self.root, range.end_bound(), &self.comparator);
+        upto.saturating_sub(below)
+    }
+
+    /*
+        Combines the summaries of all entries within the range in key order, in O(log n). Trees
+        without an augmentation (`A = ()`) just return `()`.
+    */
+    pub fn fold_range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> A::Summary where T: Borrow<Q>, C: Comparator<Q> {
+        self.nodes.fold_range(&self.root, range.start_bound(), range.end_bound(), &self.comparator)
+    }
+
+    pub fn first(&self) -> Option<AVLEntryRef<'_, T, V, A>> {
+        AVLEntryRef::from_node(&self.nodes, &self.nodes.find_lower_bound(&self.root, Bound::Unbounded, &self.comparator))
+    }
+
+    pub fn last(&self) -> Option<AVLEntryRef<'_, T, V, A>> {
+        AVLEntryRef::from_node(&self.nodes, &self.nodes.find_upper_bound(&self.root, Bound::Unbounded, &self.comparator))
+    }
+
+    pub fn floor<Q: ?Sized>(&self, key: &Q) -> Option<AVLEntryRef<'_, T, V, A>> where T: Borrow<Q>, C: Comparator<Q> {
+        AVLEntryRef::from_node(&self.nodes, &self.nodes.find_upper_bound(&self.root, Bound::Included(key), &self.comparator))
+    }
+
+    pub fn ceiling<Q: ?Sized>(&self, key: &Q) -> Option<AVLEntryRef<'_, T, V, A>> where T: Borrow<Q>, C: Comparator<Q> {
+        AVLEntryRef::from_node(&self.nodes, &self.nodes.find_lower_bound(&self.root, Bound::Included(key), &self.comparator))
+    }
+
+    pub fn predecessor<Q: ?Sized>(&self, key: &Q) -> Option<AVLEntryRef<'_, T, V, A>> where T: Borrow<Q>, C: Comparator<Q> {
+        AVLEntryRef::from_node(&self.nodes, &self.nodes.find_upper_bound(&self.root, Bound::Excluded(key), &self.comparator))
     }
 
-    pub fn transplant(&mut self, u: &TRoot<T>, v: &TRoot<T>) {
+    pub fn successor<Q: ?Sized>(&self, key: &Q) -> Option<AVLEntryRef<'_, T, V, A>> where T: Borrow<Q>, C: Comparator<Q> {
+        AVLEntryRef::from_node(&self.nodes, &self.nodes.find_lower_bound(&self.root, Bound::Excluded(key), &self.comparator))
+    }
+
+    pub(crate) fn remove_entry_at(&mut self, index: usize) -> Option<(T, V)> {
+        let node = self.nodes.select_node(&self.root, index);
+        if self.nodes.get_root_nil(&node) {
+            return None;
+        }
+        let node = self.unlink_node(node);
+        self.nodes.free_entry(node)
+    }
+
+    fn from_root(nodes: AVLArena<T, V, A>, root: TRoot, comparator: C) -> Self {
+        let mut tree = AVLTree::augmented(comparator);
+        tree.nodes = nodes;
+        tree.set_root(root);
+        tree
+    }
+
+    // Same as `RedBlackTree::take_roots`: the smaller tree's nodes move into the other arena.
+    fn take_roots(&mut self, other: &mut Self) -> (TRoot, TRoot) {
+        let (mut root, mut other_root) = (self.root.take(), other.root.take());
+        let mut other_nodes = std::mem::take(&mut other.nodes);
+        other.set_root(None);
+        if self.nodes.get_size(&root) < other_nodes.get_size(&other_root) {
+            std::mem::swap(&mut self.nodes, &mut other_nodes);
+            root = self.nodes.adopt(&mut other_nodes, &root);
+        } else {
+            other_root = self.nodes.adopt(&mut other_nodes, &other_root);
+        }
+        (root, other_root)
+    }
+
+    fn set_root(&mut self, root: TRoot) {
+        self.nodes.set_parent(&root, &None);
+        self.root = root;
+        self._is_num_leaves_available = false;
+        self._is_height_available = false;
+    }
+
+    /*
+        Moves every key >= `key` into a new tree and keeps the smaller ones. Splitting is O(log n),
+        then the smaller half is moved out into its own arena.
+    */
+    pub fn split_off<Q: ?Sized>(&mut self, key: &Q) -> Self where T: Borrow<Q>, C: Clone + Comparator<Q> {
+        let root = self.root.take();
+        let (less, found, greater) = self.nodes.split(&root, key, &self.comparator);
+        let greater = if self.nodes.get_root_nil(&found) {
+            greater
+        } else {
+            self.nodes.join(&None, &found, &greater)
+        };
+        let mut moved = AVLArena::new();
+        if self.nodes.get_size(&less) < self.nodes.get_size(&greater) {
+            let less = moved.adopt(&mut self.nodes, &less);
+            let nodes = std::mem::replace(&mut self.nodes, moved);
+            self.set_root(less);
+            Self::from_root(nodes, greater, self.comparator.clone())
+        } else {
+            let greater = moved.adopt(&mut self.nodes, &greater);
+            self.set_root(less);
+            Self::from_root(moved, greater, self.comparator.clone())
+        }
+    }
+
+    /*
+        Moves every entry of `other` into this tree. When all keys of one tree are below those of the
+        other the two are joined in O(log n); overlapping trees are merged with a union in which the
+        entries of `other` win.
+    */
+    pub fn append(&mut self, other: &mut Self) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            std::mem::swap(self, other);
+            return;
+        }
+        let (self_root, other_root) = self.take_roots(other);
+        let (self_min, self_max) = (self.nodes.get_minimum(&self_root), self.nodes.get_maximum(&self_root));
+        let (other_min, other_max) = (self.nodes.get_minimum(&other_root), self.nodes.get_maximum(&other_root));
+        let root = if self.comparator.compare(self.nodes.get_root_key_ref(&self_max), self.nodes.get_root_key_ref(&other_min)) == Ordering::Less {
+            self.nodes.join_concat(&self_root, &other_root)
+        } else if self.comparator.compare(self.nodes.get_root_key_ref(&other_max), self.nodes.get_root_key_ref(&self_min)) == Ordering::Less {
+            self.nodes.join_concat(&other_root, &self_root)
+        } else {
+            self.nodes.union(&other_root, &self_root, &self.comparator)
+        };
+        self.set_root(root);
+    }
+
+    pub fn into_union(mut self, mut other: Self) -> Self {
+        let (root, other_root) = self.take_roots(&mut other);
+        let root = self.nodes.union(&root, &other_root, &self.comparator);
+        self.set_root(root);
+        self
+    }
+
+    pub fn into_intersection(mut self, mut other: Self) -> Self {
+        let (root, other_root) = self.take_roots(&mut other);
+        let root = self.nodes.intersection(&root, &other_root, &self.comparator);
+        self.set_root(root);
+        self
+    }
+
+    pub fn into_difference(mut self, mut other: Self) -> Self {
+        let (root, other_root) = self.take_roots(&mut other);
+        let root = self.nodes.difference(&root, &other_root, &self.comparator);
+        self.set_root(root);
+        self
+    }
+
+    pub fn into_symmetric_difference(mut self, mut other: Self) -> Self {
+        let (root, other_root) = self.take_roots(&mut other);
+        let root = self.nodes.symmetric_difference(&root, &other_root, &self.comparator);
+        self.set_root(root);
+        self
+    }
+
+    pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, T, V, C, A> {
+        Union { merge: MergeIter::new(self.iter(), other.iter(), &self.comparator) }
+    }
+
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, T, V, C, A> {
+        Intersection { merge: MergeIter::new(self.iter(), other.iter(), &self.comparator) }
+    }
+
+    pub fn difference<'a>(&'a self, other: &'a Self) -> Difference<'a, T, V, C, A> {
+        Difference { merge: MergeIter::new(self.iter(), other.iter(), &self.comparator) }
+    }
+
+    pub fn symmetric_difference<'a>(&'a self, other: &'a Self) -> SymmetricDifference<'a, T, V, C, A> {
+        SymmetricDifference { merge: MergeIter::new(self.iter(), other.iter(), &self.comparator) }
+    }
+
+    /*
+        Joins `left`, the entry and `right` into one tree in O(log n) when every key of `left` is below
+        `key` and every key of `right` is above it. Otherwise the trees are merged entry by entry.
+    */
+    pub(crate) fn join_entry(mut left: Self, key: T, value: V, mut right: Self) -> Self {
+        let ordered = (left.is_empty() || left.comparator.compare(left.nodes.get_root_key_ref(&left.nodes.get_maximum(&left.root)), &key) == Ordering::Less)
+            && (right.is_empty() || left.comparator.compare(&key, right.nodes.get_root_key_ref(&right.nodes.get_minimum(&right.root))) == Ordering::Less);
+        if !ordered {
+            left.append(&mut right);
+            left.insert_entry(key, value);
+            return left;
+        }
+        let (left_root, right_root) = left.take_roots(&mut right);
+        let pivot = left.nodes.new_entry(key, value);
+        let root = left.nodes.join(&left_root, &pivot, &right_root);
+        left.set_root(root);
+        left
+    }
+
+    fn sort_entries(entries: &mut Vec<(T, V)>, comparator: &C) {
+        if entries.windows(2).all(|pair| comparator.compare(&pair[0].0, &pair[1].0) == Ordering::Less) {
+            return;
+        }
+        // the sort is stable, so after reversing the last entry given for a key comes first and survives
+        entries.sort_by(|a, b| comparator.compare(&a.0, &b.0));
+        entries.reverse();
+        entries.dedup_by(|a, b| comparator.compare(&a.0, &b.0) == Ordering::Equal);
+        entries.reverse();
+    }
+
+    pub(crate) fn from_entries(mut entries: Vec<(T, V)>, comparator: C) -> Self {
+        Self::sort_entries(&mut entries, &comparator);
+        let len = entries.len();
+        let mut nodes = AVLArena::new();
+        let root = nodes.build_sorted(&mut entries.into_iter(), len);
+        Self::from_root(nodes, root, comparator)
+    }
+
+    /*
+        Builds the new entries into their own tree and merges it in with a union, so loaded values
+        replace existing ones just like `insert` would.
+    */
+    pub(crate) fn load_entries(&mut self, mut entries: Vec<(T, V)>) {
+        Self::sort_entries(&mut entries, &self.comparator);
+        let len = entries.len();
+        let loaded = self.nodes.build_sorted(&mut entries.into_iter(), len);
+        let root = self.nodes.union(&loaded, &self.root.take(), &self.comparator);
+        self.set_root(root);
+    }
+
+    pub(crate) fn into_entries(mut self) -> VecDeque<(T, V)> {
+        let mut entries = VecDeque::new();
+        let root = self.root.take();
+        self.drain_entries(root, &mut entries);
+        entries
+    }
+
+    fn drain_entries(&mut self, root: TRoot, entries: &mut VecDeque<(T, V)>) {
+        if self.nodes.get_root_nil(&root) {
+            return;
+        }
+        let (left, right) = (self.nodes.get_left(&root), self.nodes.get_right(&root));
+        self.nodes.set_child(&root, None, Dir::Left);
+        self.nodes.set_child(&root, None, Dir::Right);
+        self.drain_entries(left, entries);
+        if let Some(entry) = self.nodes.free_entry(root) {
+            entries.push_back(entry);
+        }
+        self.drain_entries(right, entries);
+    }
+
+    pub(crate) fn transplant(&mut self, u: &TRoot, v: &TRoot) {
         /*
             This function effectively removes the node pointed to at u and replaces it with the node pointed to at v.
         */
-        let u_node = u.clone();
-        let v_node = v.clone();
-        match AVL::get_parent(&u_node) {
+        let u_node = *u;
+        let v_node = *v;
+        match self.nodes.get_parent(&u_node) {
             Some(_) => {
-                if AVL::is_node_equal(&u_node, &AVL::get_left(&AVL::get_parent(&u_node))) {  // If the u_node is a left child.
-                    AVL::set_child(&AVL::get_parent(&u_node), v_node.clone(), Dir::Left);  // Set the left child of u's parent
+                if self.nodes.is_node_equal(&u_node, &self.nodes.get_left(&self.nodes.get_parent(&u_node))) {  // If the u_node is a left child.
+                    self.nodes.set_child(&self.nodes.get_parent(&u_node), v_node, Dir::Left);  // Set the left child of u's parent
                     // to be v. In other words, removes u and replaces it with v.
                 } else {  // u_node is a right child
-                    AVL::set_child(&AVL::get_parent(&u_node), v_node.clone(), Dir::Right);  // Replaces u with v
+                    self.nodes.set_child(&self.nodes.get_parent(&u_node), v_node, Dir::Right);  // Replaces u with v
                 }
             },
-            None => self.root = v_node.clone(),
+            None => self.root = v_node,
         };
-        AVL::set_parent(&v_node, &AVL::get_parent(&u_node));  // Set v's parent to be the parent of u.
+        self.nodes.set_parent(&v_node, &self.nodes.get_parent(&u_node));  // Set v's parent to be the parent of u.
     }
 
-    pub fn delete_fixup(&mut self, key: T) {
+    pub(crate) fn delete_fixup(&mut self, start: &TRoot) {
         /*
-            Once a node in the AVL tree got deleted the tree needs to be fixed. This is where tree rotations will be called
-            to ensure the AVL tree properties are satisfied.
+            Once a node in the AVL tree got deleted the tree needs to be fixed. Starting at the parent of the removed
+            node this walks up to the root, updating heights and calling tree rotations wherever the AVL tree
+            properties are violated. `insert_at` calls it too, starting at the parent of the new node.
         */
-        let mut z = AVL::find_node(&self.root, key.clone());
-        loop {  // Infinite loop. Nothing more to say here.
-            z = AVL::get_parent(&z);  // We want to perform the tree rotations at the parent.
-            AVL::update_height(&z);  // After a node gets deleted the height of affected nodes needs to be changed. The left
+        let mut z = *start;
+        while !self.nodes.get_root_nil(&z) {
+            self.nodes.update_height(&z);  // After a node gets deleted the height of affected nodes needs to be changed. The left
             // and right rotation functions already take care of updating the height.
-            let bf: i64 = AVL::get_balance_factor(&z);  // This is the balance factor. This is a measure of how
+            self.nodes.update_size(&z);  // Same for the number of nodes in the subtree.
+            let bf: i64 = self.nodes.get_balance_factor(&z);  // This is the balance factor. This is a measure of how
             // balanced or unbalanced the AVL tree is.
 
             /*
@@ -133,194 +553,471 @@ impl<T: Ord + Clone + Debug> AVLTree<T> {
                 and right-left. Please refer to this video for more information: https://www.youtube.com/watch?v=1QSYxIKXXP4
             */
             if bf == -2 {  // A bf of -2 means that the current tree is very left heavy
-                if AVL::get_balance_factor(&AVL::get_left(&z)) <= 0 {
+                if self.nodes.get_balance_factor(&self.nodes.get_left(&z)) <= 0 {
                     // Left-Left case
-                    self.root = AVL::right_rotate(self.root.clone(), AVL::get_root_key(&z));
-                    z = AVL::get_parent(&z);  // After the rotation z would be moved down one layer. This set's node z
-                    // to be the node that took it's place.
+                    self.root = self.nodes.right_rotate_at(self.root, &z);
                 } else {
                     // Left-Right case
-                    self.root = AVL::left_rotate(self.root.clone(), AVL::get_root_key(&AVL::get_left(&z)));
-                    self.root = AVL::right_rotate(self.root.clone(), AVL::get_root_key(&z));
-                    z = AVL::get_parent(&z);  // After the rotation z would be moved down one layer. This set's node z
-                    // to be the node that took it's place.
+                    self.root = self.nodes.left_rotate_at(self.root, &self.nodes.get_left(&z));
+                    self.root = self.nodes.right_rotate_at(self.root, &z);
                 }
+                z = self.nodes.get_parent(&z);  // After the rotation z would be moved down one layer. This set's node z
+                // to be the node that took it's place.
             } else if bf == 2 {
-                if AVL::get_balance_factor(&AVL::get_right(&z)) >= 0 {
+                if self.nodes.get_balance_factor(&self.nodes.get_right(&z)) >= 0 {
                     // Right-Right case
-                    self.root = AVL::left_rotate(self.root.clone(), AVL::get_root_key(&z));
-                    z = AVL::get_parent(&z);  // After the rotation z would be moved down one layer. This set's node z
-                    // to be the node that took it's place.
+                    self.root = self.nodes.left_rotate_at(self.root, &z);
                 } else {
                     // Right-Left case
-                    self.root = AVL::right_rotate(self.root.clone(), AVL::get_root_key(&AVL::get_right(&z)));
-                    self.root = AVL::left_rotate(self.root.clone(), AVL::get_root_key(&z));
-                    z = AVL::get_parent(&z);  // After the rotation z would be moved down one layer. This set's node z
-                    // to be the node that took it's place.
+                    self.root = self.nodes.right_rotate_at(self.root, &self.nodes.get_right(&z));
+                    self.root = self.nodes.left_rotate_at(self.root, &z);
                 }
+                z = self.nodes.get_parent(&z);  // After the rotation z would be moved down one layer. This set's node z
+                // to be the node that took it's place.
             }
-            if AVL::is_node_equal(&None, &z) {  // This part checks if node z is the root node. In this case we
-            // want to break ouf the infinite loop. If not we need to continue to perform tree rotations to fix
-            // the tree.
-                break;
-            }
+            z = self.nodes.get_parent(&z);  // Continue with the next node on the way to the root.
         }
     }
 
-    pub fn delete(&mut self, key: &T) {
+    // Removes `key` and returns the key that was stored, or `None` if it was not in the tree.
+    pub fn delete<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        self.remove_entry(key).map(|(key, _)| key)
+    }
+
+    pub fn try_delete<Q: ?Sized>(&mut self, key: &Q) -> Result<T, TreeError> where T: Borrow<Q>, C: Comparator<Q> {
+        self.delete(key).ok_or(TreeError::KeyNotFound)
+    }
+
+    pub(crate) fn remove_entry<Q: ?Sized>(&mut self, key: &Q) -> Option<(T, V)> where T: Borrow<Q>, C: Comparator<Q> {
+        let node = self.remove_node(key);
+        self.nodes.free_entry(node)
+    }
+
+    pub(crate) fn remove_node<Q: ?Sized>(&mut self, key: &Q) -> TRoot where T: Borrow<Q>, C: Comparator<Q> {
+        let z = self.nodes.find_node_by(&self.root, key, &self.comparator);
+        self.unlink_node(z)
+    }
+
+    pub(crate) fn unlink_node(&mut self, mut z: TRoot) -> TRoot {
         /*
-            This function simply removes the node with value of key. This function implements the AVL tree delete
-            algorithm by William Fiset shown here: https://www.youtube.com/watch?v=g4y2h70D6Nk
+            This function removes the node z of this tree and returns it fully detached (no parent, no children)
+            so callers can take its value out. A node with both a left and right subtree first trades its key and
+            value with the smallest node in its right subtree; that node has at most one child and is the one that
+            actually gets unlinked.
         */
+        if self.nodes.get_root_nil(&z) {
+            return None;
+        }
         self._is_num_leaves_available = false;
         self._is_height_available = false;
-        let z = AVL::find_node(&self.root, key.clone());
-        match z {
-            None => return,
-            _ => (),
-        };
-        // Special case when the only node in the AVL tree is the root and we want to delete the root.
-        if AVL::is_node_equal(&self.root, &z)
-        && AVL::get_root_nil(&AVL::get_left(&self.root))
-        && AVL::get_root_nil(&AVL::get_right(&self.root)) {
-            self.root = None;
-            return;
-        }
 
-        if AVL::get_root_nil(&AVL::get_left(&z)) {  // Left subtree of z is NIL
-            if AVL::get_root_nil(&AVL::get_right(&z)) {  // Right subtree of z is NIL
-                /*
-                    Node to remove is a leaf node.
-                */
-                let u_node = z.clone();
-                let v_node = AVL::get_parent(&u_node);  // The reason I need to store the parent of u_node is so that I
-                // could perform tree rotations later. The tree rotations need to be performed to keep the AVL tree
-                // properties satisfied.
-                match v_node {
-                    Some(_) => {
-                        if AVL::is_node_equal(&u_node, &AVL::get_left(&v_node)) {  // If the u_node is a left child.
-                            AVL::set_child_nil(&v_node, Dir::Left);  // Set the left child of u's parent
-                            // to be NIL. In other words, removes u.
-                            AVL::virtualize_all_nil(&self.root);
-                            self.delete_fixup(AVL::get_root_key(&v_node));
-                        } else {  // u_node is a right child
-                            AVL::set_child_nil(&v_node, Dir::Right);  // Replaces u with NIL
-                            AVL::virtualize_all_nil(&self.root);
-                            self.delete_fixup(AVL::get_root_key(&v_node));
-                        }
-                    },
-                    None => {},
-                };
-            } else {  // Right subtree of z is not NIL
-                /*
-                    Node to remove has a right subtree but no left subtree.
-                */
-                let u_node = z.clone();
-                let v_node = AVL::get_right(&z);
-                self.transplant(&u_node, &v_node);
-                AVL::virtualize_all_nil(&self.root);
-                self.delete_fixup(AVL::get_root_key(&v_node));
-            }
-        } else {  // Left subtree of z is not NIL
-            if AVL::get_root_nil(&AVL::get_right(&z)) {  // Right subtree of z is NIL
-                /*
-                    Node to remove has a left subtree but no right subtree.
-                */
-                let u_node = z.clone();
-                let v_node = AVL::get_left(&z);
-                self.transplant(&u_node, &v_node);
-                AVL::virtualize_all_nil(&self.root);
-                self.delete_fixup(AVL::get_root_key(&v_node));
-            } else {  // Right subtree of z is not NIL
-                /*
-                    Node to remove has both a left subtree and a right subtree.
-                */
-                let min_node = AVL::get_minimum(&AVL::get_right(&z));
-                AVL::set_root_key(&z, AVL::get_root_key(&min_node));
-                if AVL::get_root_nil(&AVL::get_right(&min_node)) {  // min_node is a leaf node
-                    let u_node = min_node.clone();
-                    let parent_node = AVL::get_parent(&u_node);
-                    match parent_node {
-                        Some(_) => {
-                            AVL::set_child_nil(&parent_node, Dir::Left);  // Set the left child of u's parent
-                            // to be NIL. In other words, removes u.
-                        },
-                        None => {},
-                    };
-                    AVL::virtualize_all_nil(&self.root);
-                    AVL::update_height(&parent_node);
-                    self.delete_fixup(AVL::get_root_key(&parent_node));
-                } else {  // min_node has a right subtree
-                    let u_node = min_node.clone();
-                    let v_node = AVL::get_right(&u_node);
-                    self.transplant(&u_node, &v_node);
-                    AVL::virtualize_all_nil(&self.root);
-                    self.delete_fixup(AVL::get_root_key(&v_node));
-                }
-            }
+        if !self.nodes.get_root_nil(&self.nodes.get_left(&z)) && !self.nodes.get_root_nil(&self.nodes.get_right(&z)) {
+            let min_node = self.nodes.get_minimum(&self.nodes.get_right(&z));
+            self.nodes.swap_root_entry(&z, &min_node);
+            z = min_node;
         }
-        //AVL::virtualize_all_nil(&self.root);  // The set_child_nil function doesn't actually remove the node from the AVL tree.
-        // Instead it sets the value of the node to be the parent node. It also sets the parent pointer to be itself. In order
-        // for the node to be deallocated you need to call the virtualize_all_nil function.
+
+        let child = if self.nodes.get_root_nil(&self.nodes.get_left(&z)) {
+            self.nodes.get_right(&z)
+        } else {
+            self.nodes.get_left(&z)
+        };
+        let parent = self.nodes.get_parent(&z);  // The parent needs to be stored so that the tree rotations can start there.
+        self.transplant(&z, &child);
+        self.delete_fixup(&parent);
+
+        self.nodes.set_child(&z, None, Dir::Left);
+        self.nodes.set_child(&z, None, Dir::Right);
+        self.nodes.set_parent(&z, &None);
+        z
     }
 
-    pub fn insert(&mut self, key: &T) {
+    pub(crate) fn insert_entry(&mut self, key: T, value: V) -> Option<V> {
         /*
-            The match statement below inserts a new node with value of key into the tree. This
-            does not perform any tree rotations to keep the AVL tree properties satisfied.
+            If the key is already in the tree only its value gets replaced and the old value is returned. Otherwise
+            a new node is inserted and the tree is rebalanced from the new node's parent upwards. Either way the
+            tree is descended only once.
         */
+        match self.find_slot(&key) {
+            Ok(existing) => self.nodes.replace_root_value(&existing, value),
+            Err((parent, direction)) => {
+                self.insert_at(&parent, direction, key, value);
+                None
+            },
+        }
+    }
+
+    /*
+        Finds the node holding `key`, or else the parent and side of the empty slot it belongs in.
+    */
+    pub(crate) fn find_slot(&self, key: &T) -> AVLSlot {
+        self.nodes.find_slot(&self.root, key, &self.comparator)
+    }
+
+    /*
+        Hangs a new entry into a slot returned by `find_slot` and rebalances; returns the new node.
+    */
+    pub(crate) fn insert_at(&mut self, parent: &TRoot, direction: Dir, key: T, value: V) -> TRoot {
         self._is_num_leaves_available = false;
         self._is_height_available = false;
-        match &self.root {
-            Some(_) => AVL::insert_node(&self.root, key.clone()),
-            None => self.root = AVL::new(key.clone()),
+        let z = if self.nodes.get_root_nil(parent) {
+            self.root = self.nodes.new_entry(key, value);
+            self.root
+        } else {
+            self.nodes.attach_entry(parent, key, value, direction)
         };
-        let mut z = AVL::find_node(&self.root, key.clone());  // Get the node with value of key
-        
-        loop {  // Infinite loop. Nothing more to say here.
-            z = AVL::get_parent(&z);  // We want to perform the tree rotations at the parent.
-            AVL::update_height(&z);  // After a node gets inserted the height of affected nodes needs to be changed. The left
-            // and right rotation functions already take care of updating the height.
+        self.delete_fixup(parent);  // Inserting unbalances the tree the same way deleting does.
+        z
+    }
+}
 
-            let bf: i64 = AVL::get_balance_factor(&z);  // This is the balance factor. This is a measure of how
-            // balanced or unbalanced the AVL tree is.
+impl<T: Debug, C: Comparator<T>, A: Augment<T, ()>> SearchTree<T> for AVLTree<T, (), C, A> {
+    type Node<'a> = Option<AVLNodeView<'a, T, (), A>> where Self: 'a;
+    type Order = C;
 
-            /*
-                When the AVL tree is unbalanced there are four cases to consider: left-left, left-right, right-right,
-                and right-left. Please refer to this video for more information: https://www.youtube.com/watch?v=1QSYxIKXXP4
-            */
-            if bf == -2 {  // A bf of -2 means that the current tree is very left heavy
-                if AVL::get_balance_factor(&AVL::get_left(&z)) <= 0 {
-                    // Left-Left case
-                    self.root = AVL::right_rotate(self.root.clone(), AVL::get_root_key(&z));
-                    z = AVL::get_parent(&z);  // After the rotation z would be moved down one layer. This set's node z
-                    // to be the node that took it's place.
-                } else {
-                    // Left-Right case
-                    self.root = AVL::left_rotate(self.root.clone(), AVL::get_root_key(&AVL::get_left(&z)));
-                    self.root = AVL::right_rotate(self.root.clone(), AVL::get_root_key(&z));
-                    z = AVL::get_parent(&z);  // After the rotation z would be moved down one layer. This set's node z
-                    // to be the node that took it's place.
-                }
-            } else if bf == 2 {
-                if AVL::get_balance_factor(&AVL::get_right(&z)) >= 0 {
-                    // Right-Right case
-                    self.root = AVL::left_rotate(self.root.clone(), AVL::get_root_key(&z));
-                    z = AVL::get_parent(&z);  // After the rotation z would be moved down one layer. This set's node z
-                    // to be the node that took it's place.
-                } else {
-                    // Right-Left case
-                    self.root = AVL::right_rotate(self.root.clone(), AVL::get_root_key(&AVL::get_right(&z)));
-                    self.root = AVL::left_rotate(self.root.clone(), AVL::get_root_key(&z));
-                    z = AVL::get_parent(&z);  // After the rotation z would be moved down one layer. This set's node z
-                    // to be the node that took it's place.
-                }
+    fn insert(&mut self, key: T) -> bool {
+        AVLTree::insert(self, key)
+    }
+
+    fn delete<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        AVLTree::delete(self, key)
+    }
+
+    fn search<Q: ?Sized>(&self, key: &Q) -> Option<AVLNodeView<'_, T, (), A>> where T: Borrow<Q>, C: Comparator<Q> {
+        AVLTree::search(self, key)
+    }
+
+    fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        AVLTree::search(self, key).is_some()
+    }
+
+    fn is_empty(&self) -> bool {
+        AVLTree::is_empty(self)
+    }
+
+    fn get_height(&mut self) -> u128 {
+        AVLTree::get_height(self)
+    }
+
+    fn get_num_leaves(&mut self) -> u128 {
+        AVLTree::get_num_leaves(self)
+    }
+
+    fn print_tree(&self) {
+        AVLTree::print_tree(self)
+    }
+
+    fn print_traverse(&self, order: Ordering) {
+        AVLTree::print_traverse(self, order)
+    }
+}
+
+// Cloning copies the arena; the root index stays valid in the copy.
+impl<T: Clone + Debug, V: Clone, C: Clone, A: Augment<T, V>> Clone for AVLTree<T, V, C, A> {
+
+    fn clone(&self) -> Self {
+        AVLTree {
+            root: self.root,
+            nodes: self.nodes.clone(),
+            num_leaves: self.num_leaves,
+            height: self.height,
+            _is_num_leaves_available: self._is_num_leaves_available,
+            _is_height_available: self._is_height_available,
+            comparator: self.comparator.clone(),
+        }
+    }
+}
+
+impl<T: Debug, V, C: Comparator<T> + Default, A: Augment<T, V>> Default for AVLTree<T, V, C, A> {
+
+    fn default() -> Self {
+        AVLTree::augmented(C::default())
+    }
+}
+
+/*
+    Two trees are equal when they hold the same entries, whatever shape balancing gave them. Keys are
+    matched with the comparator, so keys it considers the same count as equal.
+*/
+impl<T: Debug, V: PartialEq, C: Comparator<T>, A: Augment<T, V>> PartialEq for AVLTree<T, V, C, A> {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| {
+            self.comparator.compare(a.key(), b.key()) == Ordering::Equal && *a.value() == *b.value()
+        })
+    }
+}
+
+impl<T: Debug, V: Eq, C: Comparator<T>, A: Augment<T, V>> Eq for AVLTree<T, V, C, A> {}
+
+// Prints the keys in order, e.g. `{1, 2, 3}`, like `BTreeSet` does.
+impl<T: Debug, V, C: Comparator<T>, A: Augment<T, V>> Debug for AVLTree<T, V, C, A> {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut set = f.debug_set();
+        for entry in self.iter() {
+            set.entry(entry.key());
+        }
+        set.finish()
+    }
+}
+
+impl<T: Debug, C: Comparator<T> + Default, A: Augment<T, ()>> FromIterator<T> for AVLTree<T, (), C, A> {
+
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        AVLTree::from_entries(iter.into_iter().map(|key| (key, ())).collect(), C::default())
+    }
+}
+
+impl<T: Debug, C: Comparator<T>, A: Augment<T, ()>> Extend<T> for AVLTree<T, (), C, A> {
+
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.bulk_load(iter)
+    }
+}
+
+impl<'a, T: Clone + Debug + 'a, C: Comparator<T>, A: Augment<T, ()>> Extend<&'a T> for AVLTree<T, (), C, A> {
+
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.bulk_load(iter.into_iter().cloned())
+    }
+}
+
+/*
+    In-order iterator over a borrowed tree. It walks the parent pointers from the minimum towards the
+    maximum (and back from the maximum for `next_back`) and stops once both ends meet.
+*/
+pub struct Iter<'a, T, V = (), A: Augment<T, V> = ()> {
+    nodes: &'a AVLArena<T, V, A>,
+    front: TRoot,
+    back: TRoot,
+}
+
+impl<'a, T: Debug, V, A: Augment<T, V>> Iterator for Iter<'a, T, V, A> {
+    type Item = AVLEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.nodes.get_root_nil(&self.front) {
+            return None;
+        }
+        let node = self.front.take();
+        if self.nodes.is_node_equal(&node, &self.back) {
+            self.back = None;
+        } else {
+            self.front = self.nodes.get_successor(&node);
+        }
+        AVLEntryRef::from_node(self.nodes, &node)
+    }
+}
+
+impl<'a, T: Debug, V, A: Augment<T, V>> DoubleEndedIterator for Iter<'a, T, V, A> {
+
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.nodes.get_root_nil(&self.back) {
+            return None;
+        }
+        let node = self.back.take();
+        if self.nodes.is_node_equal(&node, &self.front) {
+            self.front = None;
+        } else {
+            self.back = self.nodes.get_predecessor(&node);
+        }
+        AVLEntryRef::from_node(self.nodes, &node)
+    }
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> IntoIterator for &'a AVLTree<T, V, C, A> {
+    type Item = AVLEntryRef<'a, T, V, A>;
+    type IntoIter = Iter<'a, T, V, A>;
+
+    fn into_iter(self) -> Iter<'a, T, V, A> {
+        self.iter()
+    }
+}
+
+/*
+    Iterator over the keys within a range. Both ends are located with a single descent each, after
+    which it walks in order like `Iter`.
+*/
+pub struct Range<'a, T, V = (), A: Augment<T, V> = ()> {
+    iter: Iter<'a, T, V, A>,
+}
+
+impl<'a, T: Debug, V, A: Augment<T, V>> Iterator for Range<'a, T, V, A> {
+    type Item = AVLEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+impl<'a, T: Debug, V, A: Augment<T, V>> DoubleEndedIterator for Range<'a, T, V, A> {
+
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back()
+    }
+}
+
+pub struct IntoIter<T> {
+    entries: vec_deque::IntoIter<(T, ())>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.entries.next().map(|(key, _)| key)
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+
+    fn next_back(&mut self) -> Option<T> {
+        self.entries.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<T: Debug, C: Comparator<T>, A: Augment<T, ()>> IntoIterator for AVLTree<T, (), C, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { entries: self.into_entries().into_iter() }
+    }
+}
+
+/*
+    Set operators on borrowed trees, like the ones on `BTreeSet`. Both operands are copied and the
+    copies are combined with the join-based `into_*` operations.
+*/
+impl<T: Clone + Debug, V: Clone, C: Comparator<T> + Clone, A: Augment<T, V>> BitOr<&AVLTree<T, V, C, A>> for &AVLTree<T, V, C, A> {
+    type Output = AVLTree<T, V, C, A>;
+
+    fn bitor(self, rhs: &AVLTree<T, V, C, A>) -> AVLTree<T, V, C, A> {
+        self.clone().into_union(rhs.clone())
+    }
+}
+
+impl<T: Clone + Debug, V: Clone, C: Comparator<T> + Clone, A: Augment<T, V>> BitAnd<&AVLTree<T, V, C, A>> for &AVLTree<T, V, C, A> {
+    type Output = AVLTree<T, V, C, A>;
+
+    fn bitand(self, rhs: &AVLTree<T, V, C, A>) -> AVLTree<T, V, C, A> {
+        self.clone().into_intersection(rhs.clone())
+    }
+}
+
+impl<T: Clone + Debug, V: Clone, C: Comparator<T> + Clone, A: Augment<T, V>> Sub<&AVLTree<T, V, C, A>> for &AVLTree<T, V, C, A> {
+    type Output = AVLTree<T, V, C, A>;
+
+    fn sub(self, rhs: &AVLTree<T, V, C, A>) -> AVLTree<T, V, C, A> {
+        self.clone().into_difference(rhs.clone())
+    }
+}
+
+impl<T: Clone + Debug, V: Clone, C: Comparator<T> + Clone, A: Augment<T, V>> BitXor<&AVLTree<T, V, C, A>> for &AVLTree<T, V, C, A> {
+    type Output = AVLTree<T, V, C, A>;
+
+    fn bitxor(self, rhs: &AVLTree<T, V, C, A>) -> AVLTree<T, V, C, A> {
+        self.clone().into_symmetric_difference(rhs.clone())
+    }
+}
+
+/*
+    Walks two trees side by side. Every step takes the entry with the smaller key from either side,
+    or one from each side when the keys are equal; the lazy set iterators filter these steps.
+*/
+type EntryPair<'a, T, V, A> = (Option<AVLEntryRef<'a, T, V, A>>, Option<AVLEntryRef<'a, T, V, A>>);
+
+struct MergeIter<'a, T: Debug, V, C, A: Augment<T, V>> {
+    left: Peekable<Iter<'a, T, V, A>>,
+    right: Peekable<Iter<'a, T, V, A>>,
+    comparator: &'a C,
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> MergeIter<'a, T, V, C, A> {
+
+    fn new(left: Iter<'a, T, V, A>, right: Iter<'a, T, V, A>, comparator: &'a C) -> Self {
+        MergeIter { left: left.peekable(), right: right.peekable(), comparator }
+    }
+
+    fn next_pair(&mut self) -> EntryPair<'a, T, V, A> {
+        let ordering = match (self.left.peek(), self.right.peek()) {
+            (Some(left), Some(right)) => self.comparator.compare(left.key(), right.key()),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return (None, None),
+        };
+        match ordering {
+            Ordering::Less => (self.left.next(), None),
+            Ordering::Equal => (self.left.next(), self.right.next()),
+            Ordering::Greater => (None, self.right.next()),
+        }
+    }
+}
+
+pub struct Union<'a, T: Debug, V = (), C = NaturalOrder, A: Augment<T, V> = ()> {
+    merge: MergeIter<'a, T, V, C, A>,
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> Iterator for Union<'a, T, V, C, A> {
+    type Item = AVLEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (left, right) = self.merge.next_pair();
+        left.or(right)
+    }
+}
+
+pub struct Intersection<'a, T: Debug, V = (), C = NaturalOrder, A: Augment<T, V> = ()> {
+    merge: MergeIter<'a, T, V, C, A>,
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> Iterator for Intersection<'a, T, V, C, A> {
+    type Item = AVLEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.merge.next_pair() {
+                (Some(left), Some(_)) => return Some(left),
+                (None, None) => return None,
+                _ => (),
+            }
+        }
+    }
+}
+
+pub struct Difference<'a, T: Debug, V = (), C = NaturalOrder, A: Augment<T, V> = ()> {
+    merge: MergeIter<'a, T, V, C, A>,
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> Iterator for Difference<'a, T, V, C, A> {
+    type Item = AVLEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.merge.next_pair() {
+                (Some(left), None) => return Some(left),
+                (None, None) => return None,
+                _ => (),
             }
-            if AVL::is_node_equal(&None, &z) {  // This part checks if node z is the root node. In this case we
-            // want to break ouf the infinite loop. If not we need to continue to perform tree rotations to fix
-            // the tree.
-                break;
+        }
+    }
+}
+
+pub struct SymmetricDifference<'a, T: Debug, V = (), C = NaturalOrder, A: Augment<T, V> = ()> {
+    merge: MergeIter<'a, T, V, C, A>,
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> Iterator for SymmetricDifference<'a, T, V, C, A> {
+    type Item = AVLEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.merge.next_pair() {
+                (Some(entry), None) | (None, Some(entry)) => return Some(entry),
+                (None, None) => return None,
+                _ => (),
             }
         }
     }
-}
\ No newline at end of file
+}
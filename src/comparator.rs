@@ -0,0 +1,30 @@
+use std::cmp::Ordering;
+
+/*
+    Decides the order of keys in a tree. Trees use `NaturalOrder` (the key's `Ord`) unless built
+    with `with_comparator`, which also accepts any `Fn(&T, &T) -> Ordering` closure, e.g.
+    `RedBlackTree::with_comparator(|a: &f64, b: &f64| a.total_cmp(b))`.
+    The comparator has to be a total order; keys comparing `Equal` are treated as the same key.
+    Lookups by a borrowed form of the key (`&str` for `String` keys) need `NaturalOrder`, since a
+    closure only knows how to compare whole keys.
+*/
+pub trait Comparator<T: ?Sized> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NaturalOrder;
+
+impl<T: Ord + ?Sized> Comparator<T> for NaturalOrder {
+
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> Comparator<T> for F {
+
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
@@ -0,0 +1,205 @@
+use super::*;
+use std::fmt::{self, Debug};
+use std::ops::Bound;
+use augment::Augment;
+use comparator::NaturalOrder;
+use red_black_tree::{RBArena, RBTChild as TRoot};
+use tree_type::RedBlackTree;
+
+// Subtree summary of an interval tree: the largest end of any interval below a node.
+pub struct MaxEnd;
+
+impl<K: Ord + Clone> Augment<(K, K), ()> for MaxEnd {
+    type Summary = Option<K>;
+
+    fn identity() -> Option<K> {
+        None
+    }
+
+    fn summarize(key: &(K, K), _value: &()) -> Option<K> {
+        Some(key.1.clone())
+    }
+
+    fn combine(left: &Option<K>, right: &Option<K>) -> Option<K> {
+        left.clone().max(right.clone())
+    }
+}
+
+type IntervalNodes<K> = RBArena<(K, K), (), MaxEnd>;
+
+/*
+    Set of half-open intervals `[lo, hi)` stored in a red-black tree ordered by `(lo, hi)`. Every node
+    also keeps the largest `hi` of its subtree, so whole subtrees that end before a query starts are
+    skipped and overlap queries take O(log n + k) for k results. Empty intervals (`lo >= hi`) contain
+    no points and are never stored.
+*/
+pub struct IntervalTree<K: Ord + Clone> {
+    tree: RedBlackTree<(K, K), (), NaturalOrder, MaxEnd>,
+}
+
+impl<K: Ord + Clone + Debug> IntervalTree<K> {
+
+    pub fn new() -> IntervalTree<K> {
+        IntervalTree { tree: RedBlackTree::augmented(NaturalOrder) }
+    }
+
+    // Adds `[lo, hi)` and returns whether it was new.
+    pub fn insert(&mut self, lo: K, hi: K) -> bool {
+        if lo >= hi {
+            return false;
+        }
+        match self.tree.find_slot(&(lo.clone(), hi.clone())) {
+            Ok(_) => false,
+            Err((parent, direction)) => {
+                self.tree.insert_at(&parent, direction, (lo, hi), ());
+                true
+            },
+        }
+    }
+
+    // Removes `[lo, hi)` and returns whether it was present.
+    pub fn remove(&mut self, lo: &K, hi: &K) -> bool {
+        self.tree.remove_entry(&(lo.clone(), hi.clone())).is_some()
+    }
+
+    pub fn contains(&self, lo: &K, hi: &K) -> bool {
+        !self.tree.nodes.get_root_nil(&self.tree.find_node(&(lo.clone(), hi.clone())))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    // Every interval ordered by start, then by end.
+    pub fn iter(&self) -> impl Iterator<Item = (K, K)> + '_ {
+        self.tree.iter().map(|entry| entry.key().clone())
+    }
+
+    // Intervals sharing at least one point with `[lo, hi)`, ordered by start.
+    pub fn overlapping(&self, lo: &K, hi: &K) -> Overlapping<'_, K> {
+        if lo >= hi {
+            return Overlapping::new(&self.tree.nodes, None, lo.clone(), Bound::Excluded(hi.clone()));
+        }
+        Overlapping::new(&self.tree.nodes, self.tree.root, lo.clone(), Bound::Excluded(hi.clone()))
+    }
+
+    // Intervals containing `point`, ordered by start.
+    pub fn stab(&self, point: &K) -> Overlapping<'_, K> {
+        Overlapping::new(&self.tree.nodes, self.tree.root, point.clone(), Bound::Included(point.clone()))
+    }
+
+    // Whether any interval overlaps `[lo, hi)`, e.g. a conflicting booking.
+    pub fn any_overlap(&self, lo: &K, hi: &K) -> bool {
+        self.overlapping(lo, hi).next().is_some()
+    }
+
+    pub fn print_tree(&self) {
+        self.tree.print_tree()
+    }
+}
+
+impl<K: Ord + Clone + Debug> Clone for IntervalTree<K> {
+
+    fn clone(&self) -> Self {
+        IntervalTree { tree: self.tree.clone() }
+    }
+}
+
+impl<K: Ord + Clone + Debug> Default for IntervalTree<K> {
+
+    fn default() -> Self {
+        IntervalTree::new()
+    }
+}
+
+impl<K: Ord + Clone + Debug> PartialEq for IntervalTree<K> {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.tree == other.tree
+    }
+}
+
+impl<K: Ord + Clone + Debug> Eq for IntervalTree<K> {}
+
+// Prints the intervals as ranges, e.g. `{1..3, 2..8}`.
+impl<K: Ord + Clone + Debug> Debug for IntervalTree<K> {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter().map(|(lo, hi)| lo..hi)).finish()
+    }
+}
+
+impl<K: Ord + Clone + Debug> FromIterator<(K, K)> for IntervalTree<K> {
+
+    fn from_iter<I: IntoIterator<Item = (K, K)>>(iter: I) -> Self {
+        let mut intervals = IntervalTree::new();
+        intervals.extend(iter);
+        intervals
+    }
+}
+
+impl<K: Ord + Clone + Debug> Extend<(K, K)> for IntervalTree<K> {
+
+    fn extend<I: IntoIterator<Item = (K, K)>>(&mut self, iter: I) {
+        for (lo, hi) in iter {
+            self.insert(lo, hi);
+        }
+    }
+}
+
+/*
+    In-order walk over the intervals that end after `after` and start within `before`. Subtrees whose
+    largest end is not past `after` are never entered, and the walk stops at the first start beyond
+    `before`, since every later interval starts even further right.
+*/
+pub struct Overlapping<'a, K: Ord + Clone> {
+    nodes: &'a IntervalNodes<K>,
+    stack: Vec<TRoot>,
+    after: K,
+    before: Bound<K>,
+}
+
+impl<'a, K: Ord + Clone + Debug> Overlapping<'a, K> {
+
+    fn new(nodes: &'a IntervalNodes<K>, root: TRoot, after: K, before: Bound<K>) -> Self {
+        let mut overlapping = Overlapping { nodes, stack: Vec::new(), after, before };
+        overlapping.push_left(root);
+        overlapping
+    }
+
+    fn push_left(&mut self, mut node: TRoot) {
+        while !self.nodes.get_root_nil(&node) && self.nodes.get_summary(&node).is_some_and(|end| end > self.after) {
+            let left = self.nodes.get_left(&node);
+            self.stack.push(node);
+            node = left;
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone + Debug> Iterator for Overlapping<'a, K> {
+    type Item = (K, K);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            let (lo, hi) = self.nodes.get_root_key(&node);
+            let starts_before = match &self.before {
+                Bound::Included(before) => lo <= *before,
+                Bound::Excluded(before) => lo < *before,
+                Bound::Unbounded => true,
+            };
+            if !starts_before {
+                self.stack.clear();
+                return None;
+            }
+            self.push_left(self.nodes.get_right(&node));
+            if hi > self.after {
+                return Some((lo, hi));
+            }
+        }
+        None
+    }
+}
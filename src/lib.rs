@@ -1,5 +1,30 @@
-pub mod tree_test;
+#[cfg(test)]
+mod tree_test;
+pub mod search_tree;
+pub mod comparator;
+pub mod augment;
+pub mod tree_violation;
+pub mod tree_error;
+#[cfg(feature = "unstable-internals")]
+pub mod node_arena;
+#[cfg(not(feature = "unstable-internals"))]
+mod node_arena;
+#[cfg(feature = "unstable-internals")]
 pub mod red_black_tree;
+#[cfg(not(feature = "unstable-internals"))]
+mod red_black_tree;
 pub mod tree_type;
+pub mod tree_map_type;
+pub mod tree_multiset_type;
+#[cfg(feature = "unstable-internals")]
 pub mod avl_tree;
+#[cfg(not(feature = "unstable-internals"))]
+mod avl_tree;
 pub mod avl_tree_type;
+pub mod avl_tree_map_type;
+pub mod avl_tree_multiset_type;
+pub mod interval_tree;
+pub mod persistent_tree_type;
+pub mod persistent_avl_tree_type;
+pub mod sync_tree_type;
+pub mod sync_avl_tree_type;
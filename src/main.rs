@@ -1,13 +1,7 @@
-pub mod tree_test;
-pub mod red_black_tree;
-pub mod tree_type;
-pub mod avl_tree;
-pub mod avl_tree_type;
-
-use tree_type::RedBlackTree as RBT;
-use avl_tree_type::AVLTree as AVL;
+use tree::search_tree::SearchTree;
+use tree::tree_type::RedBlackTree as RBT;
+use tree::avl_tree_type::AVLTree as AVL;
 use std::io::{self, Write};
-use std::fmt::Debug;
 use std::str::FromStr;
 
 const LINE: &str = "-----------------------------------------------------";
@@ -36,189 +30,30 @@ fn invalid(cmd: &Vec<&str>, size: usize) -> bool {
     if cmd.len() < size {
         println!("Invalid command: {}", cmd.join(" "));
     }
-    return cmd.len() < size
-}
-
-fn exec_cmd_rb<T: Ord + Clone + Debug + FromStr>(tree: &mut RBT<T>, cmd: &str) where <T as FromStr>::Err: Debug {
-    let c = cmd.trim().split(" ").collect::<Vec<&str>>();
-    match c[0] {
-        "insert" => {
-            if invalid(&c, 2) {return;}
-            for i in 1..c.len() {
-                let mut valid = true;
-                c[i].parse().unwrap_or_else(|_| -> usize {
-                    valid = false; 0
-                });
-                if !valid {
-                    println!("{:?} is not a valid key to insert", c[i]);
-                    continue;
-                }
-                let key = c[i].parse().unwrap();
-                tree.insert(&key);
-            }
-        },
-        "delete" => {
-            if invalid(&c, 2) {return;}
-            for i in 1..c.len() {
-                let mut valid = true;
-                c[i].parse().unwrap_or_else(|_| -> usize {
-                    valid = false; 0
-                });
-                if !valid {
-                    continue;
-                }
-                let key = c[i].parse().unwrap();
-                tree.delete(&key);
-            }
-        },
-        "count-leaves" => {
-            if invalid(&c, 1) {return;}
-            println!("{:?}", tree.get_num_leaves());
-        },
-        "height" => {
-            if invalid(&c, 1) {return;}
-            println!("{:?}", tree.get_height());
-        },
-        "is-empty" => {
-            if invalid(&c, 1) {return;}
-            println!("{:?}", tree.is_empty());
-        },
-        "print" => {
-            if invalid(&c, 1) {return;}
-            tree.print_tree();
-        },
-        "traverse" => {
-            if invalid(&c, 2) {return;}
-            match c[1] {
-                "asc" => tree.print_traverse(std::cmp::Ordering::Less),
-                "desc" => tree.print_traverse(std::cmp::Ordering::Greater),
-                _ => println!("Invalid traverse option."),
-            };
-        },
-        _ => println!("Invalid command: {}", cmd),
-    }
-}
-
-fn exec_cmd_rb_string(tree: &mut RBT<String>, cmd: &str) {
-    let c = cmd.trim().split(" ").collect::<Vec<&str>>();
-    match c[0] {
-        "insert" => {
-            if invalid(&c, 2) {return;}
-            for i in 1..c.len() {
-                let key = c[i].to_string();
-                tree.insert(&key);
-            }
-        },
-        "delete" => {
-            if invalid(&c, 2) {return;}
-            for i in 1..c.len() {
-                let key = c[i].to_string();
-                tree.delete(&key);
-            }
-        },
-        "count-leaves" => {
-            if invalid(&c, 1) {return;}
-            println!("{:?}", tree.get_num_leaves());
-        },
-        "height" => {
-            if invalid(&c, 1) {return;}
-            println!("{:?}", tree.get_height());
-        },
-        "is-empty" => {
-            if invalid(&c, 1) {return;}
-            println!("{:?}", tree.is_empty());
-        },
-        "print" => {
-            if invalid(&c, 1) {return;}
-            tree.print_tree();
-        },
-        "traverse" => {
-            if invalid(&c, 2) {return;}
-            match c[1] {
-                "asc" => tree.print_traverse(std::cmp::Ordering::Less),
-                "desc" => tree.print_traverse(std::cmp::Ordering::Greater),
-                _ => println!("Invalid traverse option."),
-            };
-        },
-        _ => println!("Invalid command: {}", cmd),
-    }
+    cmd.len() < size
 }
 
-fn exec_cmd_avl<T: Ord + Clone + Debug + FromStr>(tree: &mut AVL<T>, cmd: &str) where <T as FromStr>::Err: Debug {
+fn exec_cmd<T: FromStr, S: SearchTree<T>>(tree: &mut S, cmd: &str) {
     let c = cmd.trim().split(" ").collect::<Vec<&str>>();
     match c[0] {
         "insert" => {
             if invalid(&c, 2) {return;}
-            for i in 1..c.len() {
-                let mut valid = true;
-                c[i].parse().unwrap_or_else(|_| -> usize {
-                    valid = false; 0
-                });
-                if !valid {
-                    println!("{:?} is not a valid key to insert", c[i]);
-                    continue;
+            for arg in &c[1..] {
+                match arg.parse::<T>().map(|key| tree.insert(key)) {
+                    Ok(true) => println!("inserted {}", arg),
+                    Ok(false) => println!("{} is already in the tree", arg),
+                    Err(_) => println!("{:?} is not a valid key to insert", arg),
                 }
-                let key = c[i].parse().unwrap();
-                tree.insert(&key);
             }
         },
         "delete" => {
             if invalid(&c, 2) {return;}
-            for i in 1..c.len() {
-                let mut valid = true;
-                c[i].parse().unwrap_or_else(|_| -> usize {
-                    valid = false; 0
-                });
-                if !valid {
-                    continue;
+            for arg in &c[1..] {
+                match arg.parse::<T>().map(|key| tree.delete(&key)) {
+                    Ok(Some(_)) => println!("deleted {}", arg),
+                    Ok(None) => println!("{} is not in the tree", arg),
+                    Err(_) => println!("{:?} is not a valid key to delete", arg),
                 }
-                let key = c[i].parse().unwrap();
-                tree.delete(&key);
-            }
-        },
-        "count-leaves" => {
-            if invalid(&c, 1) {return;}
-            println!("{:?}", tree.get_num_leaves());
-        },
-        "height" => {
-            if invalid(&c, 1) {return;}
-            println!("{:?}", tree.get_height());
-        },
-        "is-empty" => {
-            if invalid(&c, 1) {return;}
-            println!("{:?}", tree.is_empty());
-        },
-        "print" => {
-            if invalid(&c, 1) {return;}
-            tree.print_tree();
-        },
-        "traverse" => {
-            if invalid(&c, 2) {return;}
-            match c[1] {
-                "asc" => tree.print_traverse(std::cmp::Ordering::Less),
-                "desc" => tree.print_traverse(std::cmp::Ordering::Greater),
-                _ => println!("Invalid traverse option."),
-            };
-        },
-        _ => println!("Invalid command: {}", cmd),
-    }
-}
-
-fn exec_cmd_avl_string(tree: &mut AVL<String>, cmd: &str) {
-    let c = cmd.trim().split(" ").collect::<Vec<&str>>();
-    match c[0] {
-        "insert" => {
-            if invalid(&c, 2) {return;}
-            for i in 1..c.len() {
-                let key = c[i].to_string();
-                tree.insert(&key);
-            }
-        },
-        "delete" => {
-            if invalid(&c, 2) {return;}
-            for i in 1..c.len() {
-                let key = c[i].to_string();
-                tree.delete(&key);
             }
         },
         "count-leaves" => {
@@ -253,70 +88,18 @@ fn log(str: &str) {
     println!("| {:?}", str);
 }
 
-fn start_demo_rb<T: Ord + Clone + Debug + FromStr>(rbt: &mut RBT<T>) where <T as FromStr>::Err: Debug {
+fn start_demo<T: FromStr, S: SearchTree<T>>(tree: &mut S) {
     user_manual();
     loop {
         print!(">>> ");
         io::stdout().flush().unwrap();
         let mut line = String::new();
         let _ = io::stdin().read_line(&mut line).unwrap();
-        exec_cmd_rb(rbt, &line);
-    }
-}
-
-fn start_demo_rb_string(rbt: &mut RBT<String>) {
-    user_manual();
-    loop {
-        print!(">>> ");
-        io::stdout().flush().unwrap();
-        let mut line = String::new();
-        let _ = io::stdin().read_line(&mut line).unwrap();
-        exec_cmd_rb_string(rbt, &line);
-    }
-}
-
-fn start_demo_avl<T: Ord + Clone + Debug + FromStr>(avl: &mut AVL<T>) where <T as FromStr>::Err: Debug {
-    user_manual();
-    loop {
-        print!(">>> ");
-        io::stdout().flush().unwrap();
-        let mut line = String::new();
-        let _ = io::stdin().read_line(&mut line).unwrap();
-        exec_cmd_avl(avl, &line);
-    }
-}
-
-fn start_demo_avl_string(avl: &mut AVL<String>) {
-    user_manual();
-    loop {
-        print!(">>> ");
-        io::stdout().flush().unwrap();
-        let mut line = String::new();
-        let _ = io::stdin().read_line(&mut line).unwrap();
-        exec_cmd_avl_string(avl, &line);
-    }
-}
-
-fn rb_main() {
-    let mut key_type = String::new(); 
-    print!("Decide the type of tree key (int/str): ");
-    io::stdout().flush().unwrap();
-    let _ = io::stdin().read_line(&mut key_type).unwrap();
-    
-    match key_type.trim() {
-        "int" => {
-            let mut rbt: RBT<i128> = RBT::new();
-            start_demo_rb(&mut rbt);
-        },
-        "str" => {
-            let mut rbt: RBT<String> = RBT::new();
-            start_demo_rb_string(&mut rbt);
-        }
-        _ => println!("Type not implemented in this demo but should work in real case.")
+        exec_cmd(tree, &line);
     }
 }
 
-fn avl_main() {
+fn tree_main<S: SearchTree<i128> + Default, U: SearchTree<String> + Default>() {
     let mut key_type = String::new(); 
     print!("Decide the type of tree key (int/str): ");
     io::stdout().flush().unwrap();
@@ -324,12 +107,12 @@ fn avl_main() {
     
     match key_type.trim() {
         "int" => {
-            let mut avl: AVL<i128> = AVL::new();
-            start_demo_avl(&mut avl);
+            let mut tree = S::new();
+            start_demo(&mut tree);
         },
         "str" => {
-            let mut avl: AVL<String> = AVL::new();
-            start_demo_avl_string(&mut avl);
+            let mut tree = U::new();
+            start_demo(&mut tree);
         }
         _ => println!("Type not implemented in this demo but should work in real case.")
     }
@@ -344,8 +127,8 @@ fn main() {
     let _ = io::stdin().read_line(&mut tree_type).unwrap();
 
     match tree_type.trim() {
-        "1" => rb_main(),
-        "2" => avl_main(),
+        "1" => tree_main::<RBT<i128>, RBT<String>>(),
+        "2" => tree_main::<AVL<i128>, AVL<String>>(),
         _ => println!("Invalid selection.")
     }
 }
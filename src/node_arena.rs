@@ -0,0 +1,75 @@
+use std::num::NonZeroU32;
+
+/*
+    Index of a node in an `Arena`. It is stored off by one so that `Option<NodeId>`, the type of
+    every child and parent link, still takes four bytes.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(NonZeroU32);
+
+impl NodeId {
+
+    fn new(index: usize) -> NodeId {
+        u32::try_from(index + 1).ok().and_then(NonZeroU32::new).map(NodeId).expect("a tree holds fewer than u32::MAX nodes")
+    }
+
+    fn index(self) -> usize {
+        self.0.get() as usize - 1
+    }
+}
+
+/*
+    The nodes of one tree, kept in a single `Vec` and linked by `NodeId`. Slots given back with
+    `free` go on a free list and are handed out again before the `Vec` grows. The node types clear
+    a slot's entry before freeing it, so a free slot holds nothing but the empty node.
+*/
+#[derive(Clone, Debug)]
+pub struct Arena<N> {
+    nodes: Vec<N>,
+    free: Vec<NodeId>,
+}
+
+impl<N> Arena<N> {
+
+    pub fn new() -> Arena<N> {
+        Arena { nodes: Vec::new(), free: Vec::new() }
+    }
+
+    pub(crate) fn alloc(&mut self, node: N) -> NodeId {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id.index()] = node;
+                id
+            },
+            None => {
+                self.nodes.push(node);
+                NodeId::new(self.nodes.len() - 1)
+            },
+        }
+    }
+
+    pub(crate) fn free(&mut self, id: NodeId) {
+        self.free.push(id);
+    }
+
+    pub(crate) fn get(&self, id: NodeId) -> &N {
+        &self.nodes[id.index()]
+    }
+
+    pub(crate) fn get_mut(&mut self, id: NodeId) -> &mut N {
+        &mut self.nodes[id.index()]
+    }
+
+    // Number of nodes in use, i.e. allocated and not freed again.
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn live(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
+}
+
+impl<N> Default for Arena<N> {
+
+    fn default() -> Self {
+        Arena::new()
+    }
+}
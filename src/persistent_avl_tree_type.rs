@@ -0,0 +1,429 @@
+use super::*;
+use std::fmt::{self, Debug};
+use std::cmp::{Ordering, max};
+use std::sync::Arc;
+use std::borrow::Borrow;
+use search_tree::SearchTree;
+use tree_violation::TreeViolation;
+use comparator::{Comparator, NaturalOrder};
+
+type PRoot<T> = Option<Arc<PNode<T>>>;
+
+struct PNode<T> {
+    key: Arc<T>,
+    left: PRoot<T>,
+    right: PRoot<T>,
+    height: u128,
+    size: usize,
+}
+
+/*
+    Immutable AVL tree. `insert` and `delete` leave `self` untouched and return a new version that
+    copies only the nodes on the search path (plus the few a rotation touches) and shares every
+    other subtree with the old one, so `clone` is an O(1) snapshot. Nodes are never modified after
+    they are built and are shared through `Arc`, so any version can be read from other threads.
+    Keys get an `Arc` of their own too, so rebuilding a node never copies a `T`.
+*/
+pub struct PersistentAVLTree<T, C = NaturalOrder> {
+    root: PRoot<T>,
+    comparator: Arc<C>,
+}
+
+impl<T: Ord> PersistentAVLTree<T> {
+
+    pub fn new() -> PersistentAVLTree<T> {
+        PersistentAVLTree::with_comparator(NaturalOrder)
+    }
+}
+
+impl<T, C: Comparator<T>> PersistentAVLTree<T, C> {
+
+    pub fn with_comparator(comparator: C) -> PersistentAVLTree<T, C> {
+        PersistentAVLTree { root: None, comparator: Arc::new(comparator) }
+    }
+
+    fn with_root(&self, root: PRoot<T>) -> Self {
+        PersistentAVLTree { root, comparator: self.comparator.clone() }
+    }
+
+    // Returns the version with `key` added; inserting a present key shares the whole tree.
+    pub fn insert(&self, key: T) -> Self {
+        if self.contains(&key) {
+            return self.clone();
+        }
+        self.with_root(Self::ins(&self.root, Arc::new(key), &*self.comparator))
+    }
+
+    // Returns the version without `key`; deleting a missing key shares the whole tree.
+    pub fn delete<Q: ?Sized>(&self, key: &Q) -> Self where T: Borrow<Q>, C: Comparator<Q> {
+        if !self.contains(key) {
+            return self.clone();
+        }
+        self.with_root(Self::del(&self.root, key, &*self.comparator))
+    }
+
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&T> where T: Borrow<Q>, C: Comparator<Q> {
+        self.find(key).map(|key| &**key)
+    }
+
+    // Keeps the key alive on its own, independently of this version.
+    pub fn get_shared<Q: ?Sized>(&self, key: &Q) -> Option<Arc<T>> where T: Borrow<Q>, C: Comparator<Q> {
+        self.find(key).cloned()
+    }
+
+    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        self.get(key).is_some()
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        let mut node = self.root.as_ref()?;
+        while let Some(right) = &node.right {
+            node = right;
+        }
+        Some(&*node.key)
+    }
+
+    pub fn len(&self) -> usize {
+        Self::size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn get_height(&self) -> u128 {
+        Self::height(&self.root)
+    }
+
+    // Counts the nodes without children, like `AVLTree::get_num_leaves`.
+    pub fn get_num_leaves(&self) -> u128 {
+        Self::count_leaves(&self.root)
+    }
+
+    pub fn print_tree(&self) where T: Debug {
+        Self::_print_tree(&self.root, "<──", "", None);
+    }
+
+    pub fn print_traverse(&self, order: Ordering) where T: Debug {
+        if self.is_empty() {
+            println!("-> done");
+            return;
+        }
+        let mut keys: Vec<&T> = self.iter().collect();
+        match order {
+            Ordering::Less => (),
+            Ordering::Equal => {
+                println!("Choose Less or Greater please.");
+                return;
+            },
+            Ordering::Greater => keys.reverse(),
+        }
+        for key in keys {
+            print!("{:?} -> ", key);
+        }
+        println!("done");
+    }
+
+    // Whether both versions are the very same tree, e.g. nothing changed between two snapshots.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(root), Some(other_root)) => Arc::ptr_eq(root, other_root),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut iter = Iter { stack: Vec::new(), remaining: self.len() };
+        iter.push_left(&self.root);
+        iter
+    }
+
+    /*
+        Checks the structure of this version: keys in order, subtree sizes, stored heights and a
+        balance factor within one at every node.
+    */
+    pub fn validate(&self) -> Result<(), TreeViolation> where T: Debug {
+        Self::_validate(&self.root, None, None, &*self.comparator).map(|_| ())
+    }
+
+    fn find<Q: ?Sized>(&self, key: &Q) -> Option<&Arc<T>> where T: Borrow<Q>, C: Comparator<Q> {
+        let mut node = &self.root;
+        while let Some(node_ptr) = node {
+            node = match self.comparator.compare(key, (*node_ptr.key).borrow()) {
+                Ordering::Less => &node_ptr.left,
+                Ordering::Greater => &node_ptr.right,
+                Ordering::Equal => return Some(&node_ptr.key),
+            };
+        }
+        None
+    }
+
+    fn size(root: &PRoot<T>) -> usize {
+        root.as_ref().map_or(0, |node| node.size)
+    }
+
+    fn height(root: &PRoot<T>) -> u128 {
+        root.as_ref().map_or(0, |node| node.height)
+    }
+
+    fn count_leaves(root: &PRoot<T>) -> u128 {
+        match root {
+            Some(node) if node.left.is_none() && node.right.is_none() => 1,
+            Some(node) => Self::count_leaves(&node.left) + Self::count_leaves(&node.right),
+            None => 0,
+        }
+    }
+
+    fn _print_tree(root: &PRoot<T>, direction: &str, extra: &str, parent: Option<&T>) where T: Debug {
+        match root {
+            Some(node) => {
+                println!("{}", extra);
+                println!("{}{}(key {:?}, parent {:?})", extra, direction, node.key, parent.unwrap_or(&node.key));
+                let extra = extra.to_owned() + "|\t";
+                Self::_print_tree(&node.left, "<──", &extra, Some(&*node.key));
+                Self::_print_tree(&node.right, "──>", &extra, Some(&*node.key));
+            },
+            None => println!("{}{}NIL", extra, direction),
+        }
+    }
+
+    // Returns the actual height of root; lower and upper are the closest ancestors on either side.
+    fn _validate(root: &PRoot<T>, lower: Option<&T>, upper: Option<&T>, cmp: &C) -> Result<u128, TreeViolation> where T: Debug {
+        let node = match root {
+            Some(node) => node,
+            None => return Ok(0),
+        };
+        let key = || format!("{:?}", node.key);
+        let above_lower = lower.is_none_or(|lower| cmp.compare(lower, &node.key) == Ordering::Less);
+        let below_upper = upper.is_none_or(|upper| cmp.compare(&node.key, upper) == Ordering::Less);
+        if !above_lower || !below_upper {
+            return Err(TreeViolation::OutOfOrder { key: key() });
+        }
+        let left_height = Self::_validate(&node.left, lower, Some(&node.key), cmp)?;
+        let right_height = Self::_validate(&node.right, Some(&node.key), upper, cmp)?;
+        let actual = Self::size(&node.left) + Self::size(&node.right) + 1;
+        if node.size != actual {
+            return Err(TreeViolation::WrongSize { key: key(), stored: node.size, actual });
+        }
+        let actual = 1 + max(left_height, right_height);
+        if node.height != actual {
+            return Err(TreeViolation::WrongHeight { key: key(), stored: node.height, actual });
+        }
+        let balance_factor = right_height as i64 - left_height as i64;
+        if balance_factor.abs() > 1 {
+            return Err(TreeViolation::Unbalanced { key: key(), balance_factor });
+        }
+        Ok(actual)
+    }
+
+    fn node(left: PRoot<T>, key: Arc<T>, right: PRoot<T>) -> PRoot<T> {
+        let height = 1 + max(Self::height(&left), Self::height(&right));
+        let size = Self::size(&left) + Self::size(&right) + 1;
+        Some(Arc::new(PNode { key, left, right, height, size }))
+    }
+
+    /*
+        Builds a node over `left`, `key` and `right`, whose heights differ by at most two, with a
+        single or double rotation when they differ by two.
+    */
+    fn balance(left: PRoot<T>, key: Arc<T>, right: PRoot<T>) -> PRoot<T> {
+        let (left_height, right_height) = (Self::height(&left), Self::height(&right));
+        if left_height > right_height + 1 {
+            let l = left.as_ref().unwrap();
+            if Self::height(&l.left) >= Self::height(&l.right) {
+                return Self::node(l.left.clone(), l.key.clone(), Self::node(l.right.clone(), key, right));
+            }
+            let lr = l.right.as_ref().unwrap();
+            return Self::node(
+                Self::node(l.left.clone(), l.key.clone(), lr.left.clone()),
+                lr.key.clone(),
+                Self::node(lr.right.clone(), key, right));
+        }
+        if right_height > left_height + 1 {
+            let r = right.as_ref().unwrap();
+            if Self::height(&r.right) >= Self::height(&r.left) {
+                return Self::node(Self::node(left, key, r.left.clone()), r.key.clone(), r.right.clone());
+            }
+            let rl = r.left.as_ref().unwrap();
+            return Self::node(
+                Self::node(left, key, rl.left.clone()),
+                rl.key.clone(),
+                Self::node(rl.right.clone(), r.key.clone(), r.right.clone()));
+        }
+        Self::node(left, key, right)
+    }
+
+    fn ins(root: &PRoot<T>, key: Arc<T>, cmp: &C) -> PRoot<T> {
+        let node = match root {
+            Some(node) => node,
+            None => return Self::node(None, key, None),
+        };
+        match cmp.compare(&key, &node.key) {
+            Ordering::Less => Self::balance(Self::ins(&node.left, key, cmp), node.key.clone(), node.right.clone()),
+            Ordering::Greater => Self::balance(node.left.clone(), node.key.clone(), Self::ins(&node.right, key, cmp)),
+            Ordering::Equal => root.clone(),
+        }
+    }
+
+    fn del<Q: ?Sized>(root: &PRoot<T>, key: &Q, cmp: &C) -> PRoot<T> where T: Borrow<Q>, C: Comparator<Q> {
+        let node = root.as_ref()?;
+        match cmp.compare(key, (*node.key).borrow()) {
+            Ordering::Less => Self::balance(Self::del(&node.left, key, cmp), node.key.clone(), node.right.clone()),
+            Ordering::Greater => Self::balance(node.left.clone(), node.key.clone(), Self::del(&node.right, key, cmp)),
+            Ordering::Equal => match (&node.left, &node.right) {
+                (None, _) => node.right.clone(),
+                (_, None) => node.left.clone(),
+                _ => {
+                    let (successor, right) = Self::del_min(&node.right);
+                    Self::balance(node.left.clone(), successor, right)
+                },
+            },
+        }
+    }
+
+    // Removes the smallest key of a non-empty tree and returns it with the rest of the tree.
+    fn del_min(root: &PRoot<T>) -> (Arc<T>, PRoot<T>) {
+        let node = root.as_ref().unwrap();
+        match &node.left {
+            None => (node.key.clone(), node.right.clone()),
+            Some(_) => {
+                let (min, left) = Self::del_min(&node.left);
+                (min, Self::balance(left, node.key.clone(), node.right.clone()))
+            },
+        }
+    }
+}
+
+// Snapshots are free: the new handle shares every node with the old one.
+impl<T, C> Clone for PersistentAVLTree<T, C> {
+
+    fn clone(&self) -> Self {
+        PersistentAVLTree { root: self.root.clone(), comparator: self.comparator.clone() }
+    }
+}
+
+impl<T, C: Comparator<T> + Default> Default for PersistentAVLTree<T, C> {
+
+    fn default() -> Self {
+        PersistentAVLTree::with_comparator(C::default())
+    }
+}
+
+// `insert`/`delete` swap in the new version; a deleted key is copied only if older versions share it.
+impl<T: Clone + Debug, C: Comparator<T>> SearchTree<T> for PersistentAVLTree<T, C> {
+    type Node<'a> = Option<&'a T> where Self: 'a;
+    type Order = C;
+
+    fn insert(&mut self, key: T) -> bool {
+        let next = PersistentAVLTree::insert(self, key);
+        let added = !next.ptr_eq(self);
+        *self = next;
+        added
+    }
+
+    fn delete<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        let removed = self.get_shared(key);
+        *self = PersistentAVLTree::delete(self, key);
+        removed.map(Arc::unwrap_or_clone)
+    }
+
+    fn search<Q: ?Sized>(&self, key: &Q) -> Option<&T> where T: Borrow<Q>, C: Comparator<Q> {
+        self.get(key)
+    }
+
+    fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        PersistentAVLTree::contains(self, key)
+    }
+
+    fn is_empty(&self) -> bool {
+        PersistentAVLTree::is_empty(self)
+    }
+
+    fn get_height(&mut self) -> u128 {
+        PersistentAVLTree::get_height(self)
+    }
+
+    fn get_num_leaves(&mut self) -> u128 {
+        PersistentAVLTree::get_num_leaves(self)
+    }
+
+    fn print_tree(&self) {
+        PersistentAVLTree::print_tree(self)
+    }
+
+    fn print_traverse(&self, order: Ordering) {
+        PersistentAVLTree::print_traverse(self, order)
+    }
+}
+
+impl<T, C: Comparator<T>> PartialEq for PersistentAVLTree<T, C> {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || (self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| {
+            self.comparator.compare(a, b) == Ordering::Equal
+        }))
+    }
+}
+
+impl<T, C: Comparator<T>> Eq for PersistentAVLTree<T, C> {}
+
+impl<T: Debug, C: Comparator<T>> Debug for PersistentAVLTree<T, C> {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T, C: Comparator<T> + Default> FromIterator<T> for PersistentAVLTree<T, C> {
+
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter().fold(PersistentAVLTree::default(), |tree, key| tree.insert(key))
+    }
+}
+
+// In-order iterator; keeps the left spine of the part still to visit on a stack.
+pub struct Iter<'a, T> {
+    stack: Vec<&'a PNode<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iter<'a, T> {
+
+    fn push_left(&mut self, mut root: &'a PRoot<T>) {
+        while let Some(node) = root {
+            self.stack.push(node);
+            root = &node.left;
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left(&node.right);
+        self.remaining -= 1;
+        Some(&*node.key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T, C: Comparator<T>> IntoIterator for &'a PersistentAVLTree<T, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
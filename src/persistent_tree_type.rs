@@ -0,0 +1,533 @@
+use super::*;
+use std::fmt::{self, Debug};
+use std::cmp::{Ordering, max};
+use std::sync::Arc;
+use std::borrow::Borrow;
+use search_tree::SearchTree;
+use tree_violation::TreeViolation;
+use comparator::{Comparator, NaturalOrder};
+use red_black_tree::NodeColor as NC;
+
+type PRoot<T> = Option<Arc<PNode<T>>>;
+
+struct PNode<T> {
+    color: NC,
+    key: Arc<T>,
+    left: PRoot<T>,
+    right: PRoot<T>,
+    size: usize,
+}
+
+/*
+    Immutable red-black tree. `insert` and `delete` leave `self` untouched and return a new version
+    that copies only the nodes on the search path and shares every other subtree, so each update
+    costs O(log n) new nodes and `clone` is an O(1) snapshot. Nodes are never modified after they
+    are built, so they are shared through `Arc` and any version can be read from other threads.
+
+    Keys sit behind their own `Arc`, so copying a path only bumps reference counts and `T` need
+    not be `Clone`. They are ordered by `C`, as in `RedBlackTree`, which every version shares.
+
+    Rebalancing follows Okasaki's insertion and Kahrs' deletion, which restore the colours on the
+    way back up instead of rotating nodes in place.
+*/
+pub struct PersistentRedBlackTree<T, C = NaturalOrder> {
+    root: PRoot<T>,
+    comparator: Arc<C>,
+}
+
+impl<T: Ord> PersistentRedBlackTree<T> {
+
+    pub fn new() -> PersistentRedBlackTree<T> {
+        PersistentRedBlackTree::with_comparator(NaturalOrder)
+    }
+}
+
+impl<T, C: Comparator<T>> PersistentRedBlackTree<T, C> {
+
+    pub fn with_comparator(comparator: C) -> PersistentRedBlackTree<T, C> {
+        PersistentRedBlackTree { root: None, comparator: Arc::new(comparator) }
+    }
+
+    fn with_root(&self, root: PRoot<T>) -> Self {
+        PersistentRedBlackTree { root, comparator: self.comparator.clone() }
+    }
+
+    // Returns the version with `key` added; inserting a present key shares the whole tree.
+    pub fn insert(&self, key: T) -> Self {
+        if self.contains(&key) {
+            return self.clone();
+        }
+        self.with_root(Self::blacken(Self::ins(&self.root, Arc::new(key), &*self.comparator)))
+    }
+
+    // Returns the version without `key`; deleting a missing key shares the whole tree.
+    pub fn delete<Q: ?Sized>(&self, key: &Q) -> Self where T: Borrow<Q>, C: Comparator<Q> {
+        if !self.contains(key) {
+            return self.clone();
+        }
+        self.with_root(Self::blacken(Self::del(&self.root, key, &*self.comparator)))
+    }
+
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&T> where T: Borrow<Q>, C: Comparator<Q> {
+        self.find(key).map(|key| &**key)
+    }
+
+    // The key itself rather than a reference into this version, e.g. to keep it past the tree.
+    pub fn get_shared<Q: ?Sized>(&self, key: &Q) -> Option<Arc<T>> where T: Borrow<Q>, C: Comparator<Q> {
+        self.find(key).cloned()
+    }
+
+    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        self.get(key).is_some()
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        let mut node = self.root.as_ref()?;
+        while let Some(right) = &node.right {
+            node = right;
+        }
+        Some(&*node.key)
+    }
+
+    pub fn len(&self) -> usize {
+        Self::size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn get_height(&self) -> u128 {
+        Self::height(&self.root)
+    }
+
+    // Counts the empty subtrees below the nodes, like `RedBlackTree::get_num_leaves`.
+    pub fn get_num_leaves(&self) -> u128 {
+        self.len() as u128 + 1
+    }
+
+    pub fn print_tree(&self) where T: Debug {
+        Self::_print_tree(&self.root, "<──", "", None);
+    }
+
+    pub fn print_traverse(&self, order: Ordering) where T: Debug {
+        if self.is_empty() {
+            println!("-> done");
+            return;
+        }
+        let mut keys: Vec<&T> = self.iter().collect();
+        match order {
+            Ordering::Less => (),
+            Ordering::Equal => {
+                println!("Choose Less or Greater please.");
+                return;
+            },
+            Ordering::Greater => keys.reverse(),
+        }
+        for key in keys {
+            print!("{:?} -> ", key);
+        }
+        println!("done");
+    }
+
+    // Whether both versions are the very same tree, e.g. nothing changed between two snapshots.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(root), Some(other_root)) => Arc::ptr_eq(root, other_root),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut iter = Iter { stack: Vec::new(), remaining: self.len() };
+        iter.push_left(&self.root);
+        iter
+    }
+
+    /*
+        Checks the structure of this version: keys in order, subtree sizes, a black root, no red
+        node under a red parent and the same black height on every path.
+    */
+    pub fn validate(&self) -> Result<(), TreeViolation> where T: Debug {
+        if let Some(root) = self.root.as_ref().filter(|root| root.color == NC::Red) {
+            return Err(TreeViolation::RedRoot { key: format!("{:?}", root.key) });
+        }
+        Self::_validate(&self.root, &NC::Black, None, None, &*self.comparator).map(|_| ())
+    }
+
+    fn find<Q: ?Sized>(&self, key: &Q) -> Option<&Arc<T>> where T: Borrow<Q>, C: Comparator<Q> {
+        let mut node = &self.root;
+        while let Some(node_ptr) = node {
+            node = match self.comparator.compare(key, (*node_ptr.key).borrow()) {
+                Ordering::Less => &node_ptr.left,
+                Ordering::Greater => &node_ptr.right,
+                Ordering::Equal => return Some(&node_ptr.key),
+            };
+        }
+        None
+    }
+
+    fn size(root: &PRoot<T>) -> usize {
+        root.as_ref().map_or(0, |node| node.size)
+    }
+
+    fn height(root: &PRoot<T>) -> u128 {
+        root.as_ref().map_or(0, |node| 1 + max(Self::height(&node.left), Self::height(&node.right)))
+    }
+
+    fn _print_tree(root: &PRoot<T>, direction: &str, extra: &str, parent: Option<&T>) where T: Debug {
+        match root {
+            Some(node) => {
+                println!("{}", extra);
+                println!("{}{}(key {:?}, color {:?}, parent {:?})", extra, direction, node.key, node.color, parent.unwrap_or(&node.key));
+                let extra = extra.to_owned() + "|\t";
+                Self::_print_tree(&node.left, "<──", &extra, Some(&*node.key));
+                Self::_print_tree(&node.right, "──>", &extra, Some(&*node.key));
+            },
+            None => println!("{}{}NIL", extra, direction),
+        }
+    }
+
+    // Returns the black height of root; lower and upper are the closest ancestors on either side.
+    fn _validate(root: &PRoot<T>, parent_color: &NC, lower: Option<&T>, upper: Option<&T>, cmp: &C) -> Result<usize, TreeViolation> where T: Debug {
+        let node = match root {
+            Some(node) => node,
+            None => return Ok(0),
+        };
+        let key = || format!("{:?}", node.key);
+        let above_lower = lower.is_none_or(|lower| cmp.compare(lower, &node.key) == Ordering::Less);
+        let below_upper = upper.is_none_or(|upper| cmp.compare(&node.key, upper) == Ordering::Less);
+        if !above_lower || !below_upper {
+            return Err(TreeViolation::OutOfOrder { key: key() });
+        }
+        if node.color == NC::Red && *parent_color == NC::Red {
+            return Err(TreeViolation::RedChildOfRed { key: key() });
+        }
+        let left_bh = Self::_validate(&node.left, &node.color, lower, Some(&node.key), cmp)?;
+        let right_bh = Self::_validate(&node.right, &node.color, Some(&node.key), upper, cmp)?;
+        let actual = Self::size(&node.left) + Self::size(&node.right) + 1;
+        if node.size != actual {
+            return Err(TreeViolation::WrongSize { key: key(), stored: node.size, actual });
+        }
+        if left_bh != right_bh {
+            return Err(TreeViolation::BlackHeightMismatch { key: key(), left: left_bh, right: right_bh });
+        }
+        Ok(left_bh + if node.color == NC::Black {1} else {0})
+    }
+
+    fn node(color: NC, left: PRoot<T>, key: Arc<T>, right: PRoot<T>) -> PRoot<T> {
+        let size = Self::size(&left) + Self::size(&right) + 1;
+        Some(Arc::new(PNode { color, key, left, right, size }))
+    }
+
+    fn is_red(root: &PRoot<T>) -> bool {
+        matches!(root, Some(node) if node.color == NC::Red)
+    }
+
+    // non-empty and black; empty subtrees are black as well but never need fixing up
+    fn is_black(root: &PRoot<T>) -> bool {
+        matches!(root, Some(node) if node.color == NC::Black)
+    }
+
+    fn blacken(root: PRoot<T>) -> PRoot<T> {
+        match root {
+            Some(node) if node.color == NC::Red => Self::node(NC::Black, node.left.clone(), node.key.clone(), node.right.clone()),
+            _ => root,
+        }
+    }
+
+    // turns a black node red, lowering its black height by one
+    fn redden(root: PRoot<T>) -> PRoot<T> {
+        match root {
+            Some(node) if node.color == NC::Black => Self::node(NC::Red, node.left.clone(), node.key.clone(), node.right.clone()),
+            _ => unreachable!("only black nodes can give up a black level"),
+        }
+    }
+
+    /*
+        Builds a black node over `left`, `key` and `right`, repairing a red child with a red child
+        of its own (or two red children) by making the middle key a red parent of two black nodes.
+    */
+    fn balance(left: PRoot<T>, key: Arc<T>, right: PRoot<T>) -> PRoot<T> {
+        if Self::is_red(&left) && Self::is_red(&right) {
+            return Self::node(NC::Red, Self::blacken(left), key, Self::blacken(right));
+        }
+        if let Some(l) = left.as_ref().filter(|l| l.color == NC::Red) {
+            if let Some(ll) = l.left.as_ref().filter(|ll| ll.color == NC::Red) {
+                return Self::node(NC::Red,
+                    Self::node(NC::Black, ll.left.clone(), ll.key.clone(), ll.right.clone()),
+                    l.key.clone(),
+                    Self::node(NC::Black, l.right.clone(), key, right));
+            }
+            if let Some(lr) = l.right.as_ref().filter(|lr| lr.color == NC::Red) {
+                return Self::node(NC::Red,
+                    Self::node(NC::Black, l.left.clone(), l.key.clone(), lr.left.clone()),
+                    lr.key.clone(),
+                    Self::node(NC::Black, lr.right.clone(), key, right));
+            }
+        }
+        if let Some(r) = right.as_ref().filter(|r| r.color == NC::Red) {
+            if let Some(rr) = r.right.as_ref().filter(|rr| rr.color == NC::Red) {
+                return Self::node(NC::Red,
+                    Self::node(NC::Black, left, key, r.left.clone()),
+                    r.key.clone(),
+                    Self::node(NC::Black, rr.left.clone(), rr.key.clone(), rr.right.clone()));
+            }
+            if let Some(rl) = r.left.as_ref().filter(|rl| rl.color == NC::Red) {
+                return Self::node(NC::Red,
+                    Self::node(NC::Black, left, key, rl.left.clone()),
+                    rl.key.clone(),
+                    Self::node(NC::Black, rl.right.clone(), r.key.clone(), r.right.clone()));
+            }
+        }
+        Self::node(NC::Black, left, key, right)
+    }
+
+    fn ins(root: &PRoot<T>, key: Arc<T>, cmp: &C) -> PRoot<T> {
+        let node = match root {
+            Some(node) => node,
+            None => return Self::node(NC::Red, None, key, None),
+        };
+        let (left, right) = match cmp.compare(&key, &node.key) {
+            Ordering::Less => (Self::ins(&node.left, key, cmp), node.right.clone()),
+            Ordering::Greater => (node.left.clone(), Self::ins(&node.right, key, cmp)),
+            Ordering::Equal => return root.clone(),
+        };
+        match node.color {
+            NC::Black => Self::balance(left, node.key.clone(), right),
+            NC::Red => Self::node(NC::Red, left, node.key.clone(), right),
+        }
+    }
+
+    /*
+        Removes `key`, which must be present. Deleting below a black child shortens that side by
+        one black level, which `balance_left`/`balance_right` make up for.
+    */
+    fn del<Q: ?Sized>(root: &PRoot<T>, key: &Q, cmp: &C) -> PRoot<T> where T: Borrow<Q>, C: Comparator<Q> {
+        let node = root.as_ref()?;
+        match cmp.compare(key, (*node.key).borrow()) {
+            Ordering::Less if Self::is_black(&node.left) => {
+                Self::balance_left(Self::del(&node.left, key, cmp), node.key.clone(), node.right.clone())
+            },
+            Ordering::Less => Self::node(NC::Red, Self::del(&node.left, key, cmp), node.key.clone(), node.right.clone()),
+            Ordering::Greater if Self::is_black(&node.right) => {
+                Self::balance_right(node.left.clone(), node.key.clone(), Self::del(&node.right, key, cmp))
+            },
+            Ordering::Greater => Self::node(NC::Red, node.left.clone(), node.key.clone(), Self::del(&node.right, key, cmp)),
+            Ordering::Equal => Self::fuse(&node.left, &node.right),
+        }
+    }
+
+    // `left` is one black level shorter than `right`.
+    fn balance_left(left: PRoot<T>, key: Arc<T>, right: PRoot<T>) -> PRoot<T> {
+        if Self::is_red(&left) {
+            return Self::node(NC::Red, Self::blacken(left), key, right);
+        }
+        match right {
+            Some(r) if r.color == NC::Black => {
+                Self::balance(left, key, Self::node(NC::Red, r.left.clone(), r.key.clone(), r.right.clone()))
+            },
+            Some(r) => match &r.left {
+                Some(rl) if rl.color == NC::Black => Self::node(NC::Red,
+                    Self::node(NC::Black, left, key, rl.left.clone()),
+                    rl.key.clone(),
+                    Self::balance(rl.right.clone(), r.key.clone(), Self::redden(r.right.clone()))),
+                _ => unreachable!("a red node has black children"),
+            },
+            None => unreachable!("the taller side has at least one black level"),
+        }
+    }
+
+    // `right` is one black level shorter than `left`.
+    fn balance_right(left: PRoot<T>, key: Arc<T>, right: PRoot<T>) -> PRoot<T> {
+        if Self::is_red(&right) {
+            return Self::node(NC::Red, left, key, Self::blacken(right));
+        }
+        match left {
+            Some(l) if l.color == NC::Black => {
+                Self::balance(Self::node(NC::Red, l.left.clone(), l.key.clone(), l.right.clone()), key, right)
+            },
+            Some(l) => match &l.right {
+                Some(lr) if lr.color == NC::Black => Self::node(NC::Red,
+                    Self::balance(Self::redden(l.left.clone()), l.key.clone(), lr.left.clone()),
+                    lr.key.clone(),
+                    Self::node(NC::Black, lr.right.clone(), key, right)),
+                _ => unreachable!("a red node has black children"),
+            },
+            None => unreachable!("the taller side has at least one black level"),
+        }
+    }
+
+    // Joins the two children of a removed node; every key of `left` is below every key of `right`.
+    fn fuse(left: &PRoot<T>, right: &PRoot<T>) -> PRoot<T> {
+        let (l, r) = match (left, right) {
+            (None, _) => return right.clone(),
+            (_, None) => return left.clone(),
+            (Some(l), Some(r)) => (l, r),
+        };
+        match (&l.color, &r.color) {
+            (NC::Red, NC::Red) => {
+                let middle = Self::fuse(&l.right, &r.left);
+                match middle {
+                    Some(m) if m.color == NC::Red => Self::node(NC::Red,
+                        Self::node(NC::Red, l.left.clone(), l.key.clone(), m.left.clone()),
+                        m.key.clone(),
+                        Self::node(NC::Red, m.right.clone(), r.key.clone(), r.right.clone())),
+                    _ => Self::node(NC::Red, l.left.clone(), l.key.clone(),
+                        Self::node(NC::Red, middle, r.key.clone(), r.right.clone())),
+                }
+            },
+            (NC::Black, NC::Black) => {
+                let middle = Self::fuse(&l.right, &r.left);
+                match middle {
+                    Some(m) if m.color == NC::Red => Self::node(NC::Red,
+                        Self::node(NC::Black, l.left.clone(), l.key.clone(), m.left.clone()),
+                        m.key.clone(),
+                        Self::node(NC::Black, m.right.clone(), r.key.clone(), r.right.clone())),
+                    _ => Self::balance_left(l.left.clone(), l.key.clone(),
+                        Self::node(NC::Black, middle, r.key.clone(), r.right.clone())),
+                }
+            },
+            (NC::Black, NC::Red) => Self::node(NC::Red, Self::fuse(left, &r.left), r.key.clone(), r.right.clone()),
+            (NC::Red, NC::Black) => Self::node(NC::Red, l.left.clone(), l.key.clone(), Self::fuse(&l.right, right)),
+        }
+    }
+}
+
+// Snapshots are free: the new handle shares every node with the old one.
+impl<T, C> Clone for PersistentRedBlackTree<T, C> {
+
+    fn clone(&self) -> Self {
+        PersistentRedBlackTree { root: self.root.clone(), comparator: self.comparator.clone() }
+    }
+}
+
+impl<T, C: Comparator<T> + Default> Default for PersistentRedBlackTree<T, C> {
+
+    fn default() -> Self {
+        PersistentRedBlackTree::with_comparator(C::default())
+    }
+}
+
+/*
+    `insert`/`delete` swap in the new version. `search` lends the key out of the tree, while
+    `delete` has to hand back its own copy unless no other version still holds it.
+*/
+impl<T: Clone + Debug, C: Comparator<T>> SearchTree<T> for PersistentRedBlackTree<T, C> {
+    type Node<'a> = Option<&'a T> where Self: 'a;
+    type Order = C;
+
+    fn insert(&mut self, key: T) -> bool {
+        let next = PersistentRedBlackTree::insert(self, key);
+        let added = !next.ptr_eq(self);
+        *self = next;
+        added
+    }
+
+    fn delete<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        let removed = self.get_shared(key);
+        *self = PersistentRedBlackTree::delete(self, key);
+        removed.map(Arc::unwrap_or_clone)
+    }
+
+    fn search<Q: ?Sized>(&self, key: &Q) -> Option<&T> where T: Borrow<Q>, C: Comparator<Q> {
+        self.get(key)
+    }
+
+    fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        PersistentRedBlackTree::contains(self, key)
+    }
+
+    fn is_empty(&self) -> bool {
+        PersistentRedBlackTree::is_empty(self)
+    }
+
+    fn get_height(&mut self) -> u128 {
+        PersistentRedBlackTree::get_height(self)
+    }
+
+    fn get_num_leaves(&mut self) -> u128 {
+        PersistentRedBlackTree::get_num_leaves(self)
+    }
+
+    fn print_tree(&self) {
+        PersistentRedBlackTree::print_tree(self)
+    }
+
+    fn print_traverse(&self, order: Ordering) {
+        PersistentRedBlackTree::print_traverse(self, order)
+    }
+}
+
+// Keys are matched with the comparator, like `RedBlackTree` does.
+impl<T, C: Comparator<T>> PartialEq for PersistentRedBlackTree<T, C> {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || (self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| {
+            self.comparator.compare(a, b) == Ordering::Equal
+        }))
+    }
+}
+
+impl<T, C: Comparator<T>> Eq for PersistentRedBlackTree<T, C> {}
+
+impl<T: Debug, C: Comparator<T>> Debug for PersistentRedBlackTree<T, C> {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T, C: Comparator<T> + Default> FromIterator<T> for PersistentRedBlackTree<T, C> {
+
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter().fold(PersistentRedBlackTree::default(), |tree, key| tree.insert(key))
+    }
+}
+
+// In-order iterator; keeps the left spine of the part still to visit on a stack.
+pub struct Iter<'a, T> {
+    stack: Vec<&'a PNode<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iter<'a, T> {
+
+    fn push_left(&mut self, mut root: &'a PRoot<T>) {
+        while let Some(node) = root {
+            self.stack.push(node);
+            root = &node.left;
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left(&node.right);
+        self.remaining -= 1;
+        Some(&*node.key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T, C: Comparator<T>> IntoIterator for &'a PersistentRedBlackTree<T, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
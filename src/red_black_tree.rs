@@ -1,7 +1,14 @@
-use std::cell::RefCell;
 use std::fmt::Debug;
-use std::rc::{Rc, Weak};
 use std::cmp::{Ordering, max};
+use std::ops::Bound;
+use std::borrow;
+use crate::comparator::Comparator;
+#[cfg(any(test, feature = "unstable-internals"))]
+use crate::comparator::NaturalOrder;
+use crate::augment::Augment;
+use crate::node_arena::{Arena, NodeId};
+use crate::tree_violation::TreeViolation;
+use crate::tree_error::TreeError;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum NodeColor {
@@ -15,67 +22,282 @@ pub enum Direction {
     Right,
 }
 
-type TChild<T> = Rc<RefCell<RBTreeNode<T>>>;
-type TParent<T> = Weak<RefCell<RBTreeNode<T>>>;
-pub type RBTChild<T> = Option<TChild<T>>;
-type RBTParent<T> = Option<TParent<T>>;
+pub type RBTChild = Option<NodeId>;
+// (keys below, its black height, node with the key, keys above, its black height)
+type RBTSplit = (RBTChild, usize, RBTChild, RBTChild, usize);
+// the node holding a key, or the parent and side of the empty slot where it would go
+pub type RBTSlot = Result<RBTChild, (RBTChild, Direction)>;
 
 #[derive(Debug)]
-pub struct RBTreeNode<T: Ord + Clone> {
+pub struct RBTreeNode<T, V = (), A: Augment<T, V> = ()> {
     pub color: NodeColor,
-    pub key: T,
-    pub parent: RBTParent<T>,
-    left_child: RBTChild<T>,
-    right_child: RBTChild<T>,
-    pub _ptr_self: RBTParent<T>,
-    pub is_nil: bool
+    pub key: Option<T>,
+    value: Option<V>,
+    pub parent: RBTChild,
+    left_child: RBTChild,
+    right_child: RBTChild,
+    pub is_nil: bool,
+    pub size: usize,
+    summary: A::Summary,
 }
 
-impl<T: Ord + Clone + Debug> RBTreeNode<T> {
+/*
+    All nodes of a tree live in one arena and point at each other by `NodeId`. The node functions
+    below are methods of the arena, so every one of them takes the arena it works in along with the
+    node handles.
+*/
+pub type RBArena<T, V = (), A = ()> = Arena<RBTreeNode<T, V, A>>;
+
+impl<T: Clone, V: Clone, A: Augment<T, V>> Clone for RBTreeNode<T, V, A> {
+
+    fn clone(&self) -> Self {
+        RBTreeNode {
+            color: self.color.clone(),
+            key: self.key.clone(),
+            value: self.value.clone(),
+            parent: self.parent,
+            left_child: self.left_child,
+            right_child: self.right_child,
+            is_nil: self.is_nil,
+            size: self.size,
+            summary: self.summary.clone(),
+        }
+    }
+}
+
+/*
+    Handles to a single entry of a tree, e.g. the result of a map lookup. They borrow the arena the
+    entry lives in, so the tree cannot change underneath them, and hand out plain references.
+*/
+pub struct RBEntryRef<'a, T, V = (), A: Augment<T, V> = ()> {
+    nodes: &'a RBArena<T, V, A>,
+    node: NodeId,
+}
+
+pub struct RBEntryMut<'a, T, V = (), A: Augment<T, V> = ()> {
+    nodes: &'a mut RBArena<T, V, A>,
+    node: NodeId,
+}
+
+// Shared handles can be copied freely, just like the `&T` they stand in for.
+impl<'a, T, V, A: Augment<T, V>> Clone for RBEntryRef<'a, T, V, A> {
+
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, V, A: Augment<T, V>> Copy for RBEntryRef<'a, T, V, A> {}
+
+impl<'a, T, V, A: Augment<T, V>> RBEntryRef<'a, T, V, A> {
+
+    pub(crate) fn from_node(nodes: &'a RBArena<T, V, A>, root: &RBTChild) -> Option<Self> {
+        match root {
+            Some(id) if !nodes.get(*id).is_nil => Some(RBEntryRef { nodes, node: *id }),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self) -> &'a T {
+        self.nodes.get(self.node).key.as_ref().expect("NIL nodes are never handed out")
+    }
+
+    pub fn value(&self) -> &'a V {
+        self.nodes.get(self.node).value.as_ref().expect("NIL nodes are never handed out")
+    }
+}
+
+impl<'a, T, V, A: Augment<T, V>> RBEntryMut<'a, T, V, A> {
+
+    pub(crate) fn from_node(nodes: &'a mut RBArena<T, V, A>, root: &RBTChild) -> Option<Self> {
+        match root {
+            Some(id) if !nodes.get(*id).is_nil => Some(RBEntryMut { nodes, node: *id }),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self) -> &T {
+        self.nodes.get(self.node).key.as_ref().expect("NIL nodes are never handed out")
+    }
+
+    pub fn value(&self) -> &V {
+        self.nodes.get(self.node).value.as_ref().expect("NIL nodes are never handed out")
+    }
+
+    pub fn value_mut(&mut self) -> &mut V {
+        self.nodes.get_mut(self.node).value.as_mut().expect("NIL nodes are never handed out")
+    }
+}
+
+// Values may have been changed through the handle, so the summaries above it are brought up to date.
+impl<'a, T, V, A: Augment<T, V>> Drop for RBEntryMut<'a, T, V, A> {
+
+    fn drop(&mut self) {
+        self.nodes.update_size_to_root(&Some(self.node));
+    }
+}
+
+/*
+    Read-only view of a node for walking the shape of a tree, e.g. to draw it or to check it in a
+    test. Like the entry handles it keeps the tree borrowed, and it never hands out the node itself.
+*/
+pub struct RBNodeView<'a, T, V = (), A: Augment<T, V> = ()> {
+    nodes: &'a RBArena<T, V, A>,
+    node: NodeId,
+}
+
+impl<'a, T, V, A: Augment<T, V>> Clone for RBNodeView<'a, T, V, A> {
+
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, V, A: Augment<T, V>> Copy for RBNodeView<'a, T, V, A> {}
+
+impl<'a, T, V, A: Augment<T, V>> RBNodeView<'a, T, V, A> {
+
+    pub(crate) fn from_node(nodes: &'a RBArena<T, V, A>, root: &RBTChild) -> Option<Self> {
+        match root {
+            Some(id) if !nodes.get(*id).is_nil => Some(RBNodeView { nodes, node: *id }),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self) -> &'a T {
+        self.nodes.get(self.node).key.as_ref().expect("NIL nodes are never handed out")
+    }
 
-    pub fn empty() -> RBTChild<T> {
+    pub fn value(&self) -> &'a V {
+        self.nodes.get(self.node).value.as_ref().expect("NIL nodes are never handed out")
+    }
+
+    pub fn color(&self) -> NodeColor {
+        self.nodes.get(self.node).color.clone()
+    }
+
+    // Number of keys in the subtree below and including this node.
+    pub fn size(&self) -> usize {
+        self.nodes.get(self.node).size
+    }
+
+    pub fn left(&self) -> Option<Self> {
+        RBNodeView::from_node(self.nodes, &self.nodes.get(self.node).left_child)
+    }
+
+    pub fn right(&self) -> Option<Self> {
+        RBNodeView::from_node(self.nodes, &self.nodes.get(self.node).right_child)
+    }
+
+    pub fn parent(&self) -> Option<Self> {
+        RBNodeView::from_node(self.nodes, &self.nodes.get(self.node).parent)
+    }
+}
+
+impl<'a, T: Debug, V, A: Augment<T, V>> Debug for RBNodeView<'a, T, V, A> {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RBNodeView").field("key", self.key()).field("color", &self.color()).finish()
+    }
+}
+
+impl<T, V, A: Augment<T, V>> RBTreeNode<T, V, A> {
+
+    // Key of a node that is known not to be NIL.
+    fn _key(&self) -> &T {
+        self.key.as_ref().expect("NIL nodes have no key")
+    }
+}
+
+impl<T, V, A: Augment<T, V>> RBArena<T, V, A> {
+
+    fn _refresh(&mut self, id: NodeId) {
+        /*
+            Recomputes the subtree size and summary of a node from its entry and its two children.
+        */
+        let node_ref = self.get(id);
+        if node_ref.is_nil {
+            return;
+        }
+        let (left_size, left_summary) = self._subtree_totals(&node_ref.left_child);
+        let (right_size, right_summary) = self._subtree_totals(&node_ref.right_child);
+        let entry_summary = match (&node_ref.key, &node_ref.value) {
+            (Some(key), Some(value)) => A::summarize(key, value),
+            _ => A::identity(),
+        };
+        let node_mut = self.get_mut(id);
+        node_mut.size = 1 + left_size + right_size;
+        node_mut.summary = A::combine(&A::combine(&left_summary, &entry_summary), &right_summary);
+    }
+
+
+    fn _subtree_totals(&self, root: &RBTChild) -> (usize, A::Summary) {
+        match root {
+            Some(id) => {
+                let node_ref = self.get(*id);
+                (node_ref.size, node_ref.summary.clone())
+            },
+            None => (0, A::identity()),
+        }
+    }
+}
+
+impl<T: Ord + Debug> RBArena<T> {
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn new_node(&mut self, key: T) -> RBTChild {
+        self.new_entry(key, ())
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn insert_node(&mut self, root: &RBTChild, key: T) {
+        self.insert_entry_node(root, key, ());
+    }
+}
+
+impl<T, V, A: Augment<T, V>> RBArena<T, V, A> {
+
+    #[cfg(feature = "unstable-internals")]
+    pub fn empty(&self) -> RBTChild {
         None
     }
 
 
-    pub fn new(key: T) -> RBTChild<T> {
-        RBTreeNode::_new(key, NodeColor::Black, None, false)
+    pub fn new_entry(&mut self, key: T, value: V) -> RBTChild {
+        self._new(Some(key), Some(value), NodeColor::Black, None, false)
     }
 
 
-    pub fn count_leaves(root: &RBTChild<T>) -> u128 {
-        if RBTreeNode::get_root_nil(root) {return 1;}
-        //if RBTreeNode::is_leaf(root) {return 1;}
-        RBTreeNode::count_leaves(&RBTreeNode::get_left(root)) + RBTreeNode::count_leaves(&RBTreeNode::get_right(root))
+    pub fn count_leaves(&self, root: &RBTChild) -> u128 {
+        if self.get_root_nil(root) {return 1;}
+        //if self.is_leaf(root) {return 1;}
+        self.count_leaves(&self.get_left(root)) + self.count_leaves(&self.get_right(root))
     }
 
 
-    pub fn get_height(root: &RBTChild<T>) -> u128 {
-        if RBTreeNode::get_root_nil(root) {return 0;}
+    pub fn get_height(&self, root: &RBTChild) -> u128 {
+        if self.get_root_nil(root) {return 0;}
         1 + max(
-            RBTreeNode::get_height(&RBTreeNode::get_left(root)),
-            RBTreeNode::get_height(&RBTreeNode::get_right(root))
+            self.get_height(&self.get_left(root)),
+            self.get_height(&self.get_right(root))
         )
     }
 
 
-    fn _new(key: T, color: NodeColor, parent: RBTParent<T>, is_nil: bool) -> RBTChild<T> {
-        let node = Rc::new(RefCell::new(Self { 
-            color, 
-            key, 
-            parent, 
-            left_child: None, 
+    fn _new(&mut self, key: Option<T>, value: Option<V>, color: NodeColor, parent: RBTChild, is_nil: bool) -> RBTChild {
+        let node = self.alloc(RBTreeNode {
+            color,
+            key,
+            value,
+            parent,
+            left_child: None,
             right_child: None,
-            _ptr_self: None,
-            is_nil
-        }));
-        
-        let weak_ptr = Rc::downgrade(&node);
-        {
-            let mut node_ref = node.borrow_mut();
-            node_ref._ptr_self = Some(weak_ptr);
-        }
-        
+            is_nil,
+            size: 0,
+            summary: A::identity(),
+        });
+        self._refresh(node);
         Some(node)
     }
 
@@ -89,7 +311,7 @@ impl<T: Ord + Clone + Debug> RBTreeNode<T> {
     }
 
 
-    fn to_string(color: &NodeColor, direction: &Direction, parent: &RBTChild<T>, key: &T, extra: &str) {
+    fn to_string(&self, color: &NodeColor, direction: &Direction, parent: &RBTChild, key: &T, extra: &str) where T: Debug {
         let color_str = match color {
             NodeColor::Red => "Red",
             NodeColor::Black => "Black",
@@ -98,9 +320,10 @@ impl<T: Ord + Clone + Debug> RBTreeNode<T> {
             Direction::Left => "<──",
             Direction::Right => "──>",
         };
-        let parent_key = if let Some(parent_ptr) = parent {
-            parent_ptr.borrow().key.clone()
-        } else {key.clone()};
+        let parent_key = match parent {
+            Some(parent_id) => self.get(*parent_id).key.as_ref().unwrap_or(key),
+            None => key,
+        };
 
         println!("{}", extra);
         println!(
@@ -110,420 +333,1216 @@ impl<T: Ord + Clone + Debug> RBTreeNode<T> {
     }
 
 
-    fn _print_tree(root: &RBTChild<T>, direction: Direction, extra: &str) {
+    fn _print_tree(&self, root: &RBTChild, direction: Direction, extra: &str) where T: Debug {
 
         match root {
-            Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                RBTreeNode::to_string(
+            Some(tree_ptr) if self.get(*tree_ptr).key.is_some() => {
+                let node_ref = self.get(*tree_ptr);
+                self.to_string(
                     &node_ref.color,
                     &direction, 
-                    &RBTreeNode::get_parent(&root),
-                    &node_ref.key, 
+                    &self.get_parent(root),
+                    node_ref._key(), 
                     extra);
                 
                 let (left_child, right_child) = (&node_ref.left_child, &node_ref.right_child);
                 match left_child {
                     Some(_) => {
-                        if RBTreeNode::get_root_nil(left_child) {
-                            RBTreeNode::<T>::to_string_nil(&Direction::Left, &(extra.to_owned()+"|\t"), &format!("(solidified, parent {:?})", &node_ref.key));
+                        if self.get_root_nil(left_child) {
+                            Self::to_string_nil(&Direction::Left, &(extra.to_owned()+"|\t"), &format!("(solidified, parent {:?})", node_ref._key()));
                         } else {
-                            RBTreeNode::_print_tree(left_child, Direction::Left, &(extra.to_owned()+"|\t"));
+                            self._print_tree(left_child, Direction::Left, &(extra.to_owned()+"|\t"));
                         }
                     },
-                    None => RBTreeNode::<T>::to_string_nil(&Direction::Left, &(extra.to_owned()+"|\t"), ""),
+                    None => Self::to_string_nil(&Direction::Left, &(extra.to_owned()+"|\t"), ""),
                 };
                 match right_child {
                     Some(_) => {
-                        if RBTreeNode::get_root_nil(right_child) {
-                            RBTreeNode::<T>::to_string_nil(&Direction::Left, &(extra.to_owned()+"|\t"), &format!("(solidified, parent {:?})", &node_ref.key));
+                        if self.get_root_nil(right_child) {
+                            Self::to_string_nil(&Direction::Left, &(extra.to_owned()+"|\t"), &format!("(solidified, parent {:?})", node_ref._key()));
                         } else {
-                            RBTreeNode::_print_tree(right_child, Direction::Right, &(extra.to_owned()+"|\t"));
+                            self._print_tree(right_child, Direction::Right, &(extra.to_owned()+"|\t"));
                         }
                     },
-                    None => RBTreeNode::<T>::to_string_nil(&Direction::Right, &(extra.to_owned()+"|\t"), ""),
+                    None => Self::to_string_nil(&Direction::Right, &(extra.to_owned()+"|\t"), ""),
                 };
             },
-            None => RBTreeNode::<T>::to_string_nil(&Direction::Left, "", ""),
+            _ => Self::to_string_nil(&Direction::Left, "", ""),
         }
     }
 
 
-    pub fn print_tree(root: &RBTChild<T>) {
-        RBTreeNode::_print_tree(root, Direction::Left, "");
+    pub fn print_tree(&self, root: &RBTChild) where T: Debug {
+        self._print_tree(root, Direction::Left, "");
     }
 
 
-    pub fn left_rotate(root: RBTChild<T>, key: T) -> RBTChild<T> {
-        let root_copy = root.clone();
-        let x = RBTreeNode::find_node(&root, key.clone());
-        let parent = RBTreeNode::get_parent(&x);
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn left_rotate(&mut self, root: RBTChild, key: &T) -> RBTChild where T: Ord {
+        let x = self.find_node(&root, key);
+        self.left_rotate_at(root, &x)
+    }
+
+
+    pub fn left_rotate_at(&mut self, root: RBTChild, x: &RBTChild) -> RBTChild {
+        /*
+            Rotates left at the node x of the tree rooted at root, without searching for it by key.
+            Returns the root of the whole tree, which changes only when x was the root.
+        */
+        if self.get_root_nil(x) {
+            return root;
+        }
+        let parent = self.get_parent(x);
         match &parent {
             Some(_) => {
-                if RBTreeNode::is_node_equal(&x, &RBTreeNode::get_left(&parent)) {
-                    RBTreeNode::set_child(
-                        &parent, 
-                        RBTreeNode::_left_rotate(&root, key), 
-                        Direction::Left
-                    );
+                if self.is_node_equal(x, &self.get_left(&parent)) {
+                    let rotated = self._left_rotate(x);
+                    self.set_child(&parent, rotated, Direction::Left);
                 } else {
-                    RBTreeNode::set_child(
-                        &parent, 
-                        RBTreeNode::_left_rotate(&root, key), 
-                        Direction::Right
-                    );
+                    let rotated = self._left_rotate(x);
+                    self.set_child(&parent, rotated, Direction::Right);
                 }
-                return root_copy;
+                root
             },
             None => {
-                return RBTreeNode::_left_rotate(&root, key)
+                self._left_rotate(x)
             },
         }
     }
 
 
-    fn _left_rotate(root: &RBTChild<T>, key: T) -> RBTChild<T> {
+    fn _left_rotate(&mut self, x: &RBTChild) -> RBTChild {
 
-        match root {
+        match x {
             Some(_) => {
 
-                let x = RBTreeNode::find_node(root, key);
-                let y = RBTreeNode::get_right(&x);
-                match y {
-                    None => return root.clone(),
-                    _ => (),
+                let y = self.get_right(x);
+                // Nothing to rotate. The caller links the result under x's parent, so it must be x itself
+                // and not the root of the whole tree, which would hang the tree below one of its leaves.
+                if y.is_none() {
+                    return *x;
                 }
                 
-                RBTreeNode::set_child(&x, RBTreeNode::get_left(&y), Direction::Right);
+                self.set_child(x, self.get_left(&y), Direction::Right);
 
-                match RBTreeNode::get_left(&y) {
-                    Some(_) => RBTreeNode::set_parent(&RBTreeNode::get_left(&y), &x),
-                    None => (),
-                };
-                RBTreeNode::set_parent(&y, &RBTreeNode::get_parent(&x));
+                self.set_parent(&self.get_left(&y), x);
+                self.set_parent(&y, &self.get_parent(x));
+
+                self.set_parent(x, &y);
+                self.set_child(&y, *x, Direction::Left);
 
-                RBTreeNode::set_parent(&x, &y);
-                RBTreeNode::set_child(&y, x, Direction::Left);
+                self.update_size(x);
+                self.update_size(&y);
 
-                return y; // this y must be used to set the parent's left or right
+                y  // this y must be used to set the parent's left or right
 
             },
-            None => todo!("Does not support empty root now"),
+            None => None,
         }
     }
 
-    pub fn right_rotate(root: RBTChild<T>, key: T) -> RBTChild<T> {
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn right_rotate(&mut self, root: RBTChild, key: &T) -> RBTChild where T: Ord {
+        let x = self.find_node(&root, key);
+        self.right_rotate_at(root, &x)
+    }
+
+
+    pub fn right_rotate_at(&mut self, root: RBTChild, x: &RBTChild) -> RBTChild {
 
-        let root_copy = root.clone();
-        let x = RBTreeNode::find_node(&root, key.clone());
-        let parent = RBTreeNode::get_parent(&x);
+        if self.get_root_nil(x) {
+            return root;
+        }
+        let parent = self.get_parent(x);
         match &parent {
             Some(_) => {
-                if RBTreeNode::is_node_equal(&x, &RBTreeNode::get_right(&parent)) {
-                    RBTreeNode::set_child(
-                        &parent, 
-                        RBTreeNode::_right_rotate(&root, key), 
-                        Direction::Right
-                    );
+                if self.is_node_equal(x, &self.get_right(&parent)) {
+                    let rotated = self._right_rotate(x);
+                    self.set_child(&parent, rotated, Direction::Right);
                 } else {
-                    RBTreeNode::set_child(
-                        &parent, 
-                        RBTreeNode::_right_rotate(&root, key), 
-                        Direction::Left
-                    );
+                    let rotated = self._right_rotate(x);
+                    self.set_child(&parent, rotated, Direction::Left);
                 }
-                return root_copy;
+                root
             },
             None => {
-                return RBTreeNode::_right_rotate(&root, key)
+                self._right_rotate(x)
             },
         }
     }
 
-    pub fn _right_rotate(root: &RBTChild<T>, key: T) -> RBTChild<T> {
+    fn _right_rotate(&mut self, x: &RBTChild) -> RBTChild {
 
-        match root {
+        match x {
             Some(_) => {
 
-                let x = RBTreeNode::find_node(root, key);
-                let y = RBTreeNode::get_left(&x);
-                match y {
-                    None => return root.clone(),
-                    _ => (),
+                let y = self.get_left(x);
+                if y.is_none() {
+                    return *x;
                 }
                 
-                RBTreeNode::set_child(&x, RBTreeNode::get_right(&y), Direction::Left);
+                self.set_child(x, self.get_right(&y), Direction::Left);
 
-                match RBTreeNode::get_right(&y) {
-                    Some(_) => RBTreeNode::set_parent(&RBTreeNode::get_right(&y), &x),
-                    None => (),
-                };
-                RBTreeNode::set_parent(&y, &RBTreeNode::get_parent(&x));
+                self.set_parent(&self.get_right(&y), x);
+                self.set_parent(&y, &self.get_parent(x));
 
-                RBTreeNode::set_parent(&x, &y);
-                RBTreeNode::set_child(&y, x, Direction::Right);
+                self.set_parent(x, &y);
+                self.set_child(&y, *x, Direction::Right);
 
-                return y; // this y must be used to set the parent's left or right
+                self.update_size(x);
+                self.update_size(&y);
+
+                y  // this y must be used to set the parent's left or right
 
             },
-            None => todo!("Does not support empty root now"),
+            None => None,
         }
     }
 
 
-    pub fn is_node_equal(root1: &RBTChild<T>, root2: &RBTChild<T>) -> bool {
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_left_rotate(&mut self, root: RBTChild, key: &T) -> Result<RBTChild, TreeError> where T: Ord {
+        let x = self.find_node(&root, key);
+        if self.get_root_nil(&x) {
+            return Err(TreeError::KeyNotFound);
+        }
+        self.try_left_rotate_at(root, &x)
+    }
+
 
-        match (root1, root2) {
-            (None, None) => todo!("not supported"),
-            (Some(ptr1), Some(ptr2)) => {
-                return Rc::ptr_eq(ptr1, ptr2);
-            },
-            _ => false
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_right_rotate(&mut self, root: RBTChild, key: &T) -> Result<RBTChild, TreeError> where T: Ord {
+        let x = self.find_node(&root, key);
+        if self.get_root_nil(&x) {
+            return Err(TreeError::KeyNotFound);
         }
+        self.try_right_rotate_at(root, &x)
+    }
+
+
+    // Unlike `left_rotate_at`, reports a rotation that cannot happen instead of leaving the tree as it is.
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_left_rotate_at(&mut self, root: RBTChild, x: &RBTChild) -> Result<RBTChild, TreeError> {
+        if self.get_root_nil(x) {
+            return Err(TreeError::EmptyNode);
+        }
+        if self.get_root_nil(&self.get_right(x)) {
+            return Err(TreeError::MissingChild);
+        }
+        Ok(self.left_rotate_at(root, x))
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_right_rotate_at(&mut self, root: RBTChild, x: &RBTChild) -> Result<RBTChild, TreeError> {
+        if self.get_root_nil(x) {
+            return Err(TreeError::EmptyNode);
+        }
+        if self.get_root_nil(&self.get_left(x)) {
+            return Err(TreeError::MissingChild);
+        }
+        Ok(self.right_rotate_at(root, x))
+    }
+
+
+    pub fn is_node_equal(&self, root1: &RBTChild, root2: &RBTChild) -> bool {
+        root1 == root2
     }
 
 
-    pub fn get_color(root: &RBTChild<T>, key: T) -> NodeColor {
-        let target = RBTreeNode::find_node(root, key);
-        RBTreeNode::get_root_color(&target)
+    #[cfg(feature = "unstable-internals")]
+    pub fn get_color(&self, root: &RBTChild, key: &T) -> NodeColor where T: Ord {
+        let target = self.find_node(root, key);
+        self.get_root_color(&target)
     }
 
 
-    pub fn get_root_color(root: &RBTChild<T>) -> NodeColor {
+    pub fn get_root_color(&self, root: &RBTChild) -> NodeColor {
         match root {
-            Some(target_ptr) => target_ptr.borrow().color.clone(),
+            Some(target_ptr) => self.get(*target_ptr).color.clone(),
             None => NodeColor::Black,
         }
     }
 
 
-    pub fn get_root_nil(root: &RBTChild<T>) -> bool {
+    pub fn get_root_nil(&self, root: &RBTChild) -> bool {
         match root {
-            Some(target_ptr) => target_ptr.borrow().is_nil.clone(),
+            Some(target_ptr) => self.get(*target_ptr).is_nil,
             None => true,
         }
     }
 
 
-    pub fn set_root_color(root: &RBTChild<T>, color: NodeColor) {
-        match root {
-            Some(root_ptr) => root_ptr.borrow_mut().color = color,
-            None => (),
+    pub fn set_root_color(&mut self, root: &RBTChild, color: NodeColor) {
+        if let Some(root_ptr) = root {
+            self.get_mut(*root_ptr).color = color;
         }
     }
 
 
-    pub fn get_root_key(root: &RBTChild<T>) -> T {
+    // The key of a node the caller knows is not NIL. Outside the crate `try_get_root_key` and
+    // `try_get_root_key_ref` are the way to read a key, and they report an empty handle instead.
+    pub(crate) fn get_root_key(&self, root: &RBTChild) -> T where T: Clone {
+        self.get_root_key_ref(root).clone()
+    }
+
+
+    // Borrows the key instead of copying it; the arena stays borrowed for as long as the reference.
+    pub(crate) fn get_root_key_ref(&self, root: &RBTChild) -> &T {
         match root {
-            Some(root_ptr) => root_ptr.borrow().key.clone(),
-            None => todo!("should never happen"),
+            Some(id) => self.get(*id)._key(),
+            None => panic!("get_root_key_ref: {}", TreeError::EmptyNode),
         }
     }
 
 
-    pub fn get_parent(root: &RBTChild<T>) -> RBTChild<T> {
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_get_root_key_ref(&self, root: &RBTChild) -> Result<&T, TreeError> {
+        root.and_then(|id| self.get(id).key.as_ref()).ok_or(TreeError::EmptyNode)
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_get_root_key(&self, root: &RBTChild) -> Result<T, TreeError> where T: Clone {
+        self.try_get_root_key_ref(root).cloned()
+    }
+
+
+    pub fn replace_root_key(&mut self, root: &RBTChild, key: T) -> Option<T> {
+        let old = match root {
+            Some(root_ptr) => self.get_mut(*root_ptr).key.replace(key),
+            None => None,
+        };
+        self.update_size_to_root(root);
+        old
+    }
+
+
+    pub fn replace_root_value(&mut self, root: &RBTChild, value: V) -> Option<V> {
+        let old = match root {
+            Some(root_ptr) => self.get_mut(*root_ptr).value.replace(value),
+            None => None,
+        };
+        self.update_size_to_root(root);
+        old
+    }
+
+
+    pub fn take_root_value(&mut self, root: &RBTChild) -> Option<V> {
         match root {
-            Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                if let Some(parent_ptr) = node_ref.parent.clone() {
-                    return parent_ptr.upgrade();
-                } else {return None;}
-            }
+            Some(root_ptr) => self.get_mut(*root_ptr).value.take(),
             None => None,
         }
     }
 
 
-    pub fn is_leaf(root: &RBTChild<T>) -> bool {
+    pub fn get_parent(&self, root: &RBTChild) -> RBTChild {
+        root.and_then(|id| self.get(id).parent)
+    }
+
+
+    #[cfg(feature = "unstable-internals")]
+    pub fn is_leaf(&self, root: &RBTChild) -> bool {
         match root {
             Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                match (&node_ref.left_child, &node_ref.right_child) {
-                    (None, None) => return true,
-                    _ => return false,
-                };
+                let node_ref = self.get(*tree_ptr);
+                matches!((&node_ref.left_child, &node_ref.right_child), (None, None))
             },
             None => false,
         }
     }
 
 
-    pub fn get_parent_by_key(root: &RBTChild<T>, key: T) -> RBTChild<T> {
-        RBTreeNode::get_parent(&RBTreeNode::find_node(root, key))
+    #[cfg(feature = "unstable-internals")]
+    pub fn get_parent_by_key(&self, root: &RBTChild, key: &T) -> RBTChild where T: Ord {
+        self.get_parent(&self.find_node(root, key))
     }
 
 
-    pub fn set_parent(root: &RBTChild<T>, parent: &RBTChild<T>) {
-        match root {
-            Some(tree_ptr) => {
-                let mut node_ref = tree_ptr.borrow_mut();
-                match parent {
-                    Some(parent_ptr) => node_ref.parent = parent_ptr.borrow()._ptr_self.clone(),
-                    None => node_ref.parent = None,
-                }
-            },
-            None => (),
+    pub fn set_parent(&mut self, root: &RBTChild, parent: &RBTChild) {
+        if let Some(id) = root {
+            self.get_mut(*id).parent = *parent;
         }
     }
 
 
-    pub fn set_child(root: &RBTChild<T>, child: RBTChild<T>, direction: Direction) {
-        match root {
-            Some(tree_ptr) => {
-                let mut node_ref = tree_ptr.borrow_mut();
-                match direction {
-                    Direction::Left => node_ref.left_child = child,
-                    Direction::Right => node_ref.right_child = child,
-                }
-            },
-            None => todo!("not supported"),
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn try_set_child(&mut self, root: &RBTChild, child: RBTChild, direction: Direction) -> Result<(), TreeError> {
+        if self.get_root_nil(root) {
+            return Err(TreeError::EmptyNode);
         }
+        self.set_child(root, child, direction);
+        Ok(())
     }
 
 
-    pub fn set_child_nil(root: &RBTChild<T>, direction: Direction) {
-        match root {
-            Some(_) => {
-                let nil_node = RBTreeNode::_new(RBTreeNode::get_root_key(root).clone(), NodeColor::Black, None, true);
-                match direction {
-                    Direction::Left => {
-                        RBTreeNode::set_child(root, nil_node, direction);
-                        RBTreeNode::set_parent(&RBTreeNode::get_left(root), root);
-                    },
-                    Direction::Right => {
-                        RBTreeNode::set_child(root, nil_node, direction);
-                        RBTreeNode::set_parent(&RBTreeNode::get_right(root), root);
-                    },
-                }
-            },
-            None => todo!("not supported"),
+    pub fn set_child(&mut self, root: &RBTChild, child: RBTChild, direction: Direction) {
+        if let Some(tree_ptr) = root {
+            let node_ref = self.get_mut(*tree_ptr);
+            match direction {
+                Direction::Left => node_ref.left_child = child,
+                Direction::Right => node_ref.right_child = child,
+            }
+        }
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn set_child_nil(&mut self, root: &RBTChild, direction: Direction) {
+        if root.is_some() {
+            let nil_node = self._new(None, None, NodeColor::Black, None, true);
+            match direction {
+                Direction::Left => {
+                    self.set_child(root, nil_node, direction);
+                    self.set_parent(&self.get_left(root), root);
+                },
+                Direction::Right => {
+                    self.set_child(root, nil_node, direction);
+                    self.set_parent(&self.get_right(root), root);
+                },
+            }
         }
     }
 
 
-    pub fn solidify_all_nil(root: &RBTChild<T>) {
-        if let None = RBTreeNode::get_left(root) {
-            RBTreeNode::set_child_nil(root, Direction::Left);
+    #[cfg(feature = "unstable-internals")]
+    pub fn solidify_all_nil(&mut self, root: &RBTChild) where T: Clone {
+        if self.get_left(root).is_none() {
+            self.set_child_nil(root, Direction::Left);
         } else {
-            RBTreeNode::solidify_all_nil(&RBTreeNode::get_left(root));
+            self.solidify_all_nil(&self.get_left(root));
         };
-        if let None = RBTreeNode::get_right(root) {
-            RBTreeNode::set_child_nil(root, Direction::Right);
+        if self.get_right(root).is_none() {
+            self.set_child_nil(root, Direction::Right);
         } else {
-            RBTreeNode::solidify_all_nil(&RBTreeNode::get_right(root));
+            self.solidify_all_nil(&self.get_right(root));
         };
     }
 
 
-    pub fn virtualize_all_nil(root: &RBTChild<T>) {
-        if RBTreeNode::get_root_nil(&RBTreeNode::get_left(root)) {
-            RBTreeNode::set_child(root, None, Direction::Left);
+    #[cfg(feature = "unstable-internals")]
+    pub fn virtualize_all_nil(&mut self, root: &RBTChild) {
+        if self.get_root_nil(&self.get_left(root)) {
+            self.set_child(root, None, Direction::Left);
         } else {
-            RBTreeNode::virtualize_all_nil(&RBTreeNode::get_left(root));
+            self.virtualize_all_nil(&self.get_left(root));
         };
-        if RBTreeNode::get_root_nil(&RBTreeNode::get_right(root)) {
-            RBTreeNode::set_child(root, None, Direction::Right);
+        if self.get_root_nil(&self.get_right(root)) {
+            self.set_child(root, None, Direction::Right);
         } else {
-            RBTreeNode::virtualize_all_nil(&RBTreeNode::get_right(root));
+            self.virtualize_all_nil(&self.get_right(root));
         };
     }
 
 
-    pub fn get_left(root: &RBTChild<T>) -> RBTChild<T> {
+    pub fn get_left(&self, root: &RBTChild) -> RBTChild {
+        root.and_then(|id| self.get(id).left_child)
+    }
+
+
+    pub fn get_minimum(&self, root: &RBTChild) -> RBTChild {
         match root {
             Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                if let Some(left) = &node_ref.left_child {
-                    return Some(left.clone());
-                } else {return None;}
+                let node_ref = self.get(*tree_ptr);
+                match &node_ref.left_child {
+                    Some(_) => {
+                        if self.get_root_nil(&node_ref.left_child) {
+                            return *root;
+                        }
+                        self.get_minimum(&node_ref.left_child)
+                    },
+                    None => *root,
+                }
             },
             None => None,
         }
     }
 
 
-    pub fn get_minimum(root: &RBTChild<T>) -> RBTChild<T> {
+    pub fn get_maximum(&self, root: &RBTChild) -> RBTChild {
         match root {
             Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                match &node_ref.left_child {
+                let node_ref = self.get(*tree_ptr);
+                match &node_ref.right_child {
                     Some(_) => {
-                        if RBTreeNode::get_root_nil(&node_ref.left_child) {
-                            return root.clone();
+                        if self.get_root_nil(&node_ref.right_child) {
+                            return *root;
                         }
-                        return RBTreeNode::get_minimum(&node_ref.left_child)
+                        self.get_maximum(&node_ref.right_child)
                     },
-                    None => return root.clone(),
+                    None => *root,
                 }
             },
-            None => todo!("not supported"),
+            None => None,
+        }
+    }
+
+
+    pub fn get_successor(&self, root: &RBTChild) -> RBTChild {
+        let right = self.get_right(root);
+        if !self.get_root_nil(&right) {
+            return self.get_minimum(&right);
+        }
+        let mut node = *root;
+        let mut parent = self.get_parent(&node);
+        while !self.get_root_nil(&parent) && self.is_node_equal(&node, &self.get_right(&parent)) {
+            node = parent;
+            parent = self.get_parent(&node);
+        }
+        parent
+    }
+
+
+    pub fn get_predecessor(&self, root: &RBTChild) -> RBTChild {
+        let left = self.get_left(root);
+        if !self.get_root_nil(&left) {
+            return self.get_maximum(&left);
         }
+        let mut node = *root;
+        let mut parent = self.get_parent(&node);
+        while !self.get_root_nil(&parent) && self.is_node_equal(&node, &self.get_left(&parent)) {
+            node = parent;
+            parent = self.get_parent(&node);
+        }
+        parent
     }
 
 
-    pub fn get_right(root: &RBTChild<T>) -> RBTChild<T> {
+    pub fn satisfies_lower_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &RBTChild, bound: Bound<&Q>, cmp: &C) -> bool where T: borrow::Borrow<Q> {
         match root {
             Some(tree_ptr) => {
-                let node_ref = tree_ptr.borrow();
-                if let Some(left) = &node_ref.right_child {
-                    return Some(left.clone());
-                } else {return None;}
+                let node_ref = self.get(*tree_ptr);
+                let node_key = match &node_ref.key {
+                    Some(node_key) => node_key,
+                    None => return false,
+                };
+                match bound {
+                    Bound::Included(key) => cmp.compare(borrow::Borrow::borrow(node_key), key) != Ordering::Less,
+                    Bound::Excluded(key) => cmp.compare(borrow::Borrow::borrow(node_key), key) == Ordering::Greater,
+                    Bound::Unbounded => true,
+                }
             },
-            None => None,
+            None => false,
+        }
+    }
+
+
+    pub fn satisfies_upper_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &RBTChild, bound: Bound<&Q>, cmp: &C) -> bool where T: borrow::Borrow<Q> {
+        match root {
+            Some(tree_ptr) => {
+                let node_ref = self.get(*tree_ptr);
+                let node_key = match &node_ref.key {
+                    Some(node_key) => node_key,
+                    None => return false,
+                };
+                match bound {
+                    Bound::Included(key) => cmp.compare(borrow::Borrow::borrow(node_key), key) != Ordering::Greater,
+                    Bound::Excluded(key) => cmp.compare(borrow::Borrow::borrow(node_key), key) == Ordering::Less,
+                    Bound::Unbounded => true,
+                }
+            },
+            None => false,
+        }
+    }
+
+
+    pub fn find_lower_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &RBTChild, bound: Bound<&Q>, cmp: &C) -> RBTChild where T: borrow::Borrow<Q> {
+        /*
+            Returns the node with the smallest key satisfying the lower bound, descending once from root.
+        */
+        let mut node = *root;
+        let mut found = None;
+        while !self.get_root_nil(&node) {
+            if self.satisfies_lower_bound(&node, bound, cmp) {
+                let left = self.get_left(&node);
+                found = node;
+                node = left;
+            } else {
+                node = self.get_right(&node);
+            }
+        }
+        found
+    }
+
+
+    pub fn find_upper_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &RBTChild, bound: Bound<&Q>, cmp: &C) -> RBTChild where T: borrow::Borrow<Q> {
+        /*
+            Returns the node with the largest key satisfying the upper bound, descending once from root.
+        */
+        let mut node = *root;
+        let mut found = None;
+        while !self.get_root_nil(&node) {
+            if self.satisfies_upper_bound(&node, bound, cmp) {
+                let right = self.get_right(&node);
+                found = node;
+                node = right;
+            } else {
+                node = self.get_left(&node);
+            }
+        }
+        found
+    }
+
+
+    pub fn get_size(&self, root: &RBTChild) -> usize {
+        match root {
+            Some(root_ptr) => self.get(*root_ptr).size,
+            None => 0,
+        }
+    }
+
+
+    #[cfg(feature = "unstable-internals")]
+    pub fn set_size(&mut self, root: &RBTChild, size: usize) {
+        if let Some(root_ptr) = root {
+            self.get_mut(*root_ptr).size = size;
+        }
+    }
+
+
+    pub fn update_size(&mut self, root: &RBTChild) {
+        // the summary depends on the same children as the size, so both are kept up to date together
+        if let Some(id) = root {
+            self._refresh(*id);
+        }
+    }
+
+
+    pub fn update_size_to_root(&mut self, root: &RBTChild) {
+        let mut node = *root;
+        while !self.get_root_nil(&node) {
+            self.update_size(&node);
+            node = self.get_parent(&node);
         }
     }
 
 
-    pub fn insert_node(root: &RBTChild<T>, key: T) {
-        let _ = RBTreeNode::_recurse_node(root, key, true);
+    pub fn select_node(&self, root: &RBTChild, index: usize) -> RBTChild {
+        /*
+            Returns the node holding the index-th smallest key (counting from 0) using the subtree sizes.
+        */
+        let mut node = *root;
+        let mut index = index;
+        while !self.get_root_nil(&node) {
+            let left_size = self.get_size(&self.get_left(&node));
+            match index.cmp(&left_size) {
+                Ordering::Less => node = self.get_left(&node),
+                Ordering::Equal => return node,
+                Ordering::Greater => {
+                    index -= left_size + 1;
+                    node = self.get_right(&node);
+                },
+            }
+        }
+        None
     }
 
 
-    pub fn find_node(root: &RBTChild<T>, key: T) -> RBTChild<T> {
-        RBTreeNode::_recurse_node(root, key, false)
+    pub fn count_below_lower_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &RBTChild, bound: Bound<&Q>, cmp: &C) -> usize where T: borrow::Borrow<Q> {
+        let mut node = *root;
+        let mut count = 0;
+        while !self.get_root_nil(&node) {
+            if self.satisfies_lower_bound(&node, bound, cmp) {
+                node = self.get_left(&node);
+            } else {
+                count += self.get_size(&self.get_left(&node)) + 1;
+                node = self.get_right(&node);
+            }
+        }
+        count
+    }
+
+
+    pub fn count_within_upper_bound<Q: ?Sized, C: Comparator<Q>>(&self, root: &RBTChild, bound: Bound<&Q>, cmp: &C) -> usize where T: borrow::Borrow<Q> {
+        let mut node = *root;
+        let mut count = 0;
+        while !self.get_root_nil(&node) {
+            if self.satisfies_upper_bound(&node, bound, cmp) {
+                count += self.get_size(&self.get_left(&node)) + 1;
+                node = self.get_right(&node);
+            } else {
+                node = self.get_left(&node);
+            }
+        }
+        count
     }
 
 
-    fn _recurse_node(root: &RBTChild<T>, key: T, insert: bool) -> RBTChild<T> {
+    pub fn get_summary(&self, root: &RBTChild) -> A::Summary {
+        self._subtree_totals(root).1
+    }
+
 
+    fn _entry_summary(&self, root: &RBTChild) -> A::Summary {
         match root {
-            Some(tree_ptr) => {
-                
-                let mut node_ref = tree_ptr.borrow_mut();
-
-                match key.cmp(&node_ref.key) {
-
-                    Ordering::Less => {
-                        match node_ref.left_child {
-                            Some(_) => RBTreeNode::_recurse_node(&node_ref.left_child, key, insert),
-                            None => {
-                                if insert {
-                                    node_ref.left_child = RBTreeNode::_new(key, NodeColor::Red, node_ref._ptr_self.clone(), false);
-                                    if let Some(insert_ptr) = &node_ref.left_child {
-                                        return Some(insert_ptr.clone());
-                                    } else {todo!("should never reach here")}
-
-                                } else {return None;}
-                            },
-                        }
-                    },
-                    Ordering::Equal => return Some(tree_ptr.clone()),
-                    Ordering::Greater => {
-                        match node_ref.right_child {
-                            Some(_) => RBTreeNode::_recurse_node(&node_ref.right_child, key, insert),
-                            None => {
-                                if insert {
-                                    node_ref.right_child = RBTreeNode::_new(key, NodeColor::Red, node_ref._ptr_self.clone(), false);
-                                    if let Some(insert_ptr) = &node_ref.right_child {
-                                        return Some(insert_ptr.clone());
-                                    } else {todo!("should never reach here")}
-                                } else {return None;}
-                            },
-                        }
-                    },
+            Some(root_ptr) => {
+                let node_ref = self.get(*root_ptr);
+                match (&node_ref.key, &node_ref.value) {
+                    (Some(key), Some(value)) if !node_ref.is_nil => A::summarize(key, value),
+                    _ => A::identity(),
                 }
             },
-            None => {return None;},
+            None => A::identity(),
+        }
+    }
+
+
+    pub fn fold_range<Q: ?Sized, C: Comparator<Q>>(&self, root: &RBTChild, lower: Bound<&Q>, upper: Bound<&Q>, cmp: &C) -> A::Summary where T: borrow::Borrow<Q> {
+        /*
+            Combines the summaries of all entries within the bounds in key order. Once the descent
+            reaches the first node inside both bounds, each side only has one bound left to check, so
+            whole subtrees are taken from their stored summaries and the cost stays O(log n).
+        */
+        let mut node = *root;
+        while !self.get_root_nil(&node) {
+            if !self.satisfies_lower_bound(&node, lower, cmp) {
+                node = self.get_right(&node);
+            } else if !self.satisfies_upper_bound(&node, upper, cmp) {
+                node = self.get_left(&node);
+            } else {
+                let below = self._fold_from(&self.get_left(&node), lower, cmp);
+                let above = self._fold_to(&self.get_right(&node), upper, cmp);
+                return A::combine(&A::combine(&below, &self._entry_summary(&node)), &above);
+            }
+        }
+        A::identity()
+    }
+
+
+    fn _fold_from<Q: ?Sized, C: Comparator<Q>>(&self, root: &RBTChild, lower: Bound<&Q>, cmp: &C) -> A::Summary where T: borrow::Borrow<Q> {
+        // everything folded so far lies to the right of the current subtree
+        let mut node = *root;
+        let mut folded = A::identity();
+        while !self.get_root_nil(&node) {
+            if self.satisfies_lower_bound(&node, lower, cmp) {
+                let right = A::combine(&self._entry_summary(&node), &self.get_summary(&self.get_right(&node)));
+                folded = A::combine(&right, &folded);
+                node = self.get_left(&node);
+            } else {
+                node = self.get_right(&node);
+            }
+        }
+        folded
+    }
+
+
+    fn _fold_to<Q: ?Sized, C: Comparator<Q>>(&self, root: &RBTChild, upper: Bound<&Q>, cmp: &C) -> A::Summary where T: borrow::Borrow<Q> {
+        // everything folded so far lies to the left of the current subtree
+        let mut node = *root;
+        let mut folded = A::identity();
+        while !self.get_root_nil(&node) {
+            if self.satisfies_upper_bound(&node, upper, cmp) {
+                let left = A::combine(&self.get_summary(&self.get_left(&node)), &self._entry_summary(&node));
+                folded = A::combine(&folded, &left);
+                node = self.get_right(&node);
+            } else {
+                node = self.get_left(&node);
+            }
+        }
+        folded
+    }
+
+
+    pub fn free_entry(&mut self, root: RBTChild) -> Option<(T, V)> {
+        /*
+            Moves key and value out of a node that has already been unlinked from its tree and gives
+            its slot back to the arena.
+        */
+        let id = root?;
+        let node_mut = self.get_mut(id);
+        let entry = (node_mut.key.take(), node_mut.value.take());
+        self.free(id);
+        match entry {
+            (Some(key), Some(value)) => Some((key, value)),
+            _ => None,
+        }
+    }
+
+
+    pub fn adopt(&mut self, other: &mut Self, root: &RBTChild) -> RBTChild {
+        /*
+            Moves the subtree below root out of another arena into this one and returns its new root.
+            Colours, sizes and summaries come along unchanged; the slots left behind are freed.
+        */
+        self._adopt(other, root, None)
+    }
+
+
+    fn _adopt(&mut self, other: &mut Self, root: &RBTChild, parent: RBTChild) -> RBTChild {
+        let id = (*root)?;
+        let (left, right) = (other.get_left(root), other.get_right(root));
+        let node = other.get_mut(id);
+        let moved = Some(self.alloc(RBTreeNode {
+            color: node.color.clone(),
+            key: node.key.take(),
+            value: node.value.take(),
+            parent,
+            left_child: None,
+            right_child: None,
+            is_nil: node.is_nil,
+            size: node.size,
+            summary: std::mem::replace(&mut node.summary, A::identity()),
+        }));
+        other.free(id);
+        let (left, right) = (self._adopt(other, &left, moved), self._adopt(other, &right, moved));
+        self.set_child(&moved, left, Direction::Left);
+        self.set_child(&moved, right, Direction::Right);
+        moved
+    }
+
+
+    fn _discard(&mut self, root: &RBTChild) {
+        // a detached subtree that does not make it into the result, e.g. a key both trees had
+        if root.is_some() {
+            let (left, right) = (self.get_left(root), self.get_right(root));
+            self._discard(&left);
+            self._discard(&right);
+            self.free_entry(*root);
+        }
+    }
+
+
+    pub fn get_black_height(&self, root: &RBTChild) -> usize {
+        /*
+            Number of black nodes on any path from root down to a NIL, counting root itself.
+        */
+        let mut node = *root;
+        let mut height = 0;
+        while !self.get_root_nil(&node) {
+            if self.get_root_color(&node) == NodeColor::Black {
+                height += 1;
+            }
+            node = self.get_left(&node);
+        }
+        height
+    }
+
+
+    pub fn validate<C: Comparator<T>>(&self, root: &RBTChild, cmp: &C) -> Result<(), TreeViolation> where T: Debug {
+        /*
+            Checks the whole tree below root: keys in order, parent links, subtree sizes, a black root,
+            no red node under a red parent and the same black height on every path.
+        */
+        if !self.get_root_nil(root) && self.get_root_color(root) == NodeColor::Red {
+            return Err(TreeViolation::RedRoot { key: self._key_name(root) });
+        }
+        self._validate(root, &None, &None, &None, cmp).map(|_| ())
+    }
+
+
+    fn _validate<C: Comparator<T>>(&self, root: &RBTChild, parent: &RBTChild, lower: &RBTChild, upper: &RBTChild, cmp: &C) -> Result<usize, TreeViolation> where T: Debug {
+        // Returns the black height of root; lower and upper are the closest ancestors on either side.
+        if self.get_root_nil(root) {
+            return Ok(0);
+        }
+        let key = || self._key_name(root);
+        if self.get_parent(root) != *parent {
+            return Err(TreeViolation::BrokenParentLink { key: key() });
+        }
+        let above_lower = self.get_root_nil(lower)
+            || cmp.compare(self.get_root_key_ref(lower), self.get_root_key_ref(root)) == Ordering::Less;
+        let below_upper = self.get_root_nil(upper)
+            || cmp.compare(self.get_root_key_ref(root), self.get_root_key_ref(upper)) == Ordering::Less;
+        if !above_lower || !below_upper {
+            return Err(TreeViolation::OutOfOrder { key: key() });
+        }
+        let color = self.get_root_color(root);
+        if color == NodeColor::Red && !self.get_root_nil(parent) && self.get_root_color(parent) == NodeColor::Red {
+            return Err(TreeViolation::RedChildOfRed { key: key() });
+        }
+        let (left, right) = (self.get_left(root), self.get_right(root));
+        let left_bh = self._validate(&left, root, lower, root, cmp)?;
+        let right_bh = self._validate(&right, root, root, upper, cmp)?;
+        let (stored, actual) = (self.get_size(root), self.get_size(&left) + self.get_size(&right) + 1);
+        if stored != actual {
+            return Err(TreeViolation::WrongSize { key: key(), stored, actual });
+        }
+        if left_bh != right_bh {
+            return Err(TreeViolation::BlackHeightMismatch { key: key(), left: left_bh, right: right_bh });
+        }
+        Ok(left_bh + if color == NodeColor::Black {1} else {0})
+    }
+
+
+    fn _key_name(&self, root: &RBTChild) -> String where T: Debug {
+        format!("{:?}", *self.get_root_key_ref(root))
+    }
+
+
+    fn _child_black_height(&self, root: &RBTChild, black_height: usize) -> usize {
+        match self.get_root_color(root) {
+            NodeColor::Black => black_height - 1,
+            NodeColor::Red => black_height,
+        }
+    }
+
+
+    fn _detach(&mut self, root: &RBTChild) -> (RBTChild, RBTChild) {
+        let (left, right) = (self.get_left(root), self.get_right(root));
+        self.set_child(root, None, Direction::Left);
+        self.set_child(root, None, Direction::Right);
+        self.set_parent(&left, &None);
+        self.set_parent(&right, &None);
+        self.set_parent(root, &None);
+        self.update_size(root);
+        (left, right)
+    }
+
+
+    fn _link(&mut self, root: &RBTChild, left: RBTChild, right: RBTChild) {
+        self.set_parent(&left, root);
+        self.set_parent(&right, root);
+        self.set_child(root, left, Direction::Left);
+        self.set_child(root, right, Direction::Right);
+        self.update_size(root);
+    }
+
+
+    fn _rotate_left_at(&mut self, x: &RBTChild) -> RBTChild {
+        let y = self.get_right(x);
+        self._link(x, self.get_left(x), self.get_left(&y));
+        self._link(&y, *x, self.get_right(&y));
+        y
+    }
+
+
+    fn _rotate_right_at(&mut self, x: &RBTChild) -> RBTChild {
+        let y = self.get_left(x);
+        self._link(x, self.get_right(&y), self.get_right(x));
+        self._link(&y, self.get_left(&y), *x);
+        y
+    }
+
+
+    fn _join_right(&mut self, left: &RBTChild, left_bh: usize, pivot: &RBTChild, right: &RBTChild, right_bh: usize) -> RBTChild {
+        if self.get_root_color(left) == NodeColor::Black && left_bh == right_bh {
+            self.set_root_color(pivot, NodeColor::Red);
+            self._link(pivot, *left, *right);
+            return *pivot;
+        }
+        let child_bh = self._child_black_height(left, left_bh);
+        let joined = self._join_right(&self.get_right(left), child_bh, pivot, right, right_bh);
+        self._link(left, self.get_left(left), joined);
+
+        // a red pivot below a red node is pushed up one level by a rotation at the black grandparent
+        let grandchild = self.get_right(&joined);
+        if self.get_root_color(left) == NodeColor::Black
+            && self.get_root_color(&joined) == NodeColor::Red
+            && !self.get_root_nil(&grandchild)
+            && self.get_root_color(&grandchild) == NodeColor::Red {
+            self.set_root_color(&grandchild, NodeColor::Black);
+            return self._rotate_left_at(left);
+        }
+        *left
+    }
+
+
+    fn _join_left(&mut self, left: &RBTChild, left_bh: usize, pivot: &RBTChild, right: &RBTChild, right_bh: usize) -> RBTChild {
+        if self.get_root_color(right) == NodeColor::Black && left_bh == right_bh {
+            self.set_root_color(pivot, NodeColor::Red);
+            self._link(pivot, *left, *right);
+            return *pivot;
+        }
+        let child_bh = self._child_black_height(right, right_bh);
+        let joined = self._join_left(left, left_bh, pivot, &self.get_left(right), child_bh);
+        self._link(right, joined, self.get_right(right));
+
+        let grandchild = self.get_left(&joined);
+        if self.get_root_color(right) == NodeColor::Black
+            && self.get_root_color(&joined) == NodeColor::Red
+            && !self.get_root_nil(&grandchild)
+            && self.get_root_color(&grandchild) == NodeColor::Red {
+            self.set_root_color(&grandchild, NodeColor::Black);
+            return self._rotate_right_at(right);
+        }
+        *right
+    }
+
+
+    pub fn join(&mut self, left: &RBTChild, left_bh: usize, pivot: &RBTChild, right: &RBTChild, right_bh: usize) -> (RBTChild, usize) {
+        /*
+            Joins two trees and a detached pivot node, where every key of left < pivot < every key of right.
+            The black heights of both inputs are passed in so that the cost stays proportional to their
+            difference; the returned tree has a black root and comes with its own black height.
+        */
+        let (mut left_bh, mut right_bh) = (left_bh, right_bh);
+        if !self.get_root_nil(left) && self.get_root_color(left) == NodeColor::Red {
+            self.set_root_color(left, NodeColor::Black);
+            left_bh += 1;
+        }
+        if !self.get_root_nil(right) && self.get_root_color(right) == NodeColor::Red {
+            self.set_root_color(right, NodeColor::Black);
+            right_bh += 1;
+        }
+
+        let (joined, joined_bh) = match left_bh.cmp(&right_bh) {
+            Ordering::Greater => (self._join_right(left, left_bh, pivot, right, right_bh), left_bh),
+            Ordering::Less => (self._join_left(left, left_bh, pivot, right, right_bh), right_bh),
+            Ordering::Equal => {
+                self._link(pivot, *left, *right);
+                self.set_root_color(pivot, NodeColor::Red);
+                (*pivot, left_bh)
+            },
+        };
+
+        // the black count below a red root is unchanged, so painting it black adds one level
+        self.set_parent(&joined, &None);
+        if self.get_root_color(&joined) == NodeColor::Red {
+            self.set_root_color(&joined, NodeColor::Black);
+            return (joined, joined_bh + 1);
+        }
+        (joined, joined_bh)
+    }
+
+
+    pub fn join_concat(&mut self, left: &RBTChild, left_bh: usize, right: &RBTChild, right_bh: usize) -> (RBTChild, usize) {
+        /*
+            Joins two trees without a pivot by borrowing the maximum of left as the pivot.
+        */
+        if self.get_root_nil(left) {
+            return (*right, right_bh);
+        }
+        let (rest, rest_bh, last) = self.split_last(left, left_bh);
+        self.join(&rest, rest_bh, &last, right, right_bh)
+    }
+
+
+    pub fn split_last(&mut self, root: &RBTChild, black_height: usize) -> (RBTChild, usize, RBTChild) {
+        /*
+            Detaches the maximum node of root, returning the remaining tree with its black height and the
+            maximum as a single node.
+        */
+        let child_bh = self._child_black_height(root, black_height);
+        let (left, right) = self._detach(root);
+        if self.get_root_nil(&right) {
+            return (left, child_bh, *root);
+        }
+        let (rest, rest_bh, last) = self.split_last(&right, child_bh);
+        let (joined, joined_bh) = self.join(&left, child_bh, root, &rest, rest_bh);
+        (joined, joined_bh, last)
+    }
+
+
+    pub fn split<Q: ?Sized, C: Comparator<Q>>(&mut self, root: &RBTChild, black_height: usize, key: &Q, cmp: &C) -> RBTSplit where T: borrow::Borrow<Q> {
+        /*
+            Splits root into the keys below key and the keys above key, each with its black height.
+            The node holding key itself, if any, is returned detached in the middle.
+        */
+        if self.get_root_nil(root) {
+            return (None, 0, None, None, 0);
+        }
+        let child_bh = self._child_black_height(root, black_height);
+        let (left, right) = self._detach(root);
+        let ordering = cmp.compare(key, borrow::Borrow::borrow(self.get(root.unwrap())._key()));
+        match ordering {
+            Ordering::Equal => (left, child_bh, *root, right, child_bh),
+            Ordering::Less => {
+                let (less, less_bh, found, greater, greater_bh) = self.split(&left, child_bh, key, cmp);
+                let (joined, joined_bh) = self.join(&greater, greater_bh, root, &right, child_bh);
+                (less, less_bh, found, joined, joined_bh)
+            },
+            Ordering::Greater => {
+                let (less, less_bh, found, greater, greater_bh) = self.split(&right, child_bh, key, cmp);
+                let (joined, joined_bh) = self.join(&left, child_bh, root, &less, less_bh);
+                (joined, joined_bh, found, greater, greater_bh)
+            },
+        }
+    }
+
+
+    pub fn build_sorted<I: Iterator<Item = (T, V)>>(&mut self, entries: &mut I, len: usize) -> RBTChild {
+        /*
+            Builds a tree from len entries in ascending key order in O(len). Subtree sizes never differ by
+            more than one, so every NIL sits on one of the last two levels; colouring only the nodes on
+            the deepest level red keeps the black height equal on all paths.
+        */
+        let levels = (usize::BITS - len.leading_zeros()) as usize;
+        self._build_sorted(entries, len, 0, levels)
+    }
+
+
+    fn _build_sorted<I: Iterator<Item = (T, V)>>(&mut self, entries: &mut I, len: usize, depth: usize, levels: usize) -> RBTChild {
+        if len == 0 {
+            return None;
+        }
+        let left_len = (len - 1) / 2;
+        let left = self._build_sorted(entries, left_len, depth + 1, levels);
+        let (key, value) = entries.next()?;
+        let color = if depth > 0 && depth + 1 == levels {NodeColor::Red} else {NodeColor::Black};
+        let root = self._new(Some(key), Some(value), color, None, false);
+        let right = self._build_sorted(entries, len - 1 - left_len, depth + 1, levels);
+        self._link(&root, left, right);
+        root
+    }
+
+
+    fn _split_by<C: Comparator<T>>(&mut self, root: &RBTChild, black_height: usize, pivot: &RBTChild, cmp: &C) -> RBTSplit {
+        // the pivot is detached from root, so its key can be lent to the split and put back afterwards
+        let pivot_id = pivot.expect("the pivot is a node");
+        let key = self.get_mut(pivot_id).key.take().expect("NIL nodes have no key");
+        let split = self.split(root, black_height, &key, cmp);
+        self.get_mut(pivot_id).key = Some(key);
+        split
+    }
+
+
+    /*
+        The set operations below all work the same way: the root of one tree splits the other one,
+        both halves are combined recursively and the results are joined back together. Trees are
+        consumed; nodes that do not make it into the result go back to the arena.
+    */
+    pub fn union<C: Comparator<T>>(&mut self, root: &RBTChild, black_height: usize, other: &RBTChild, other_bh: usize, cmp: &C) -> (RBTChild, usize) {
+        // keys present in both trees keep the node from root
+        if self.get_root_nil(root) {
+            return (*other, other_bh);
+        }
+        if self.get_root_nil(other) {
+            return (*root, black_height);
+        }
+        let child_bh = self._child_black_height(root, black_height);
+        let (left, right) = self._detach(root);
+        let (less, less_bh, found, greater, greater_bh) = self._split_by(other, other_bh, root, cmp);
+        self._discard(&found);
+        let (left, left_bh) = self.union(&left, child_bh, &less, less_bh, cmp);
+        let (right, right_bh) = self.union(&right, child_bh, &greater, greater_bh, cmp);
+        self.join(&left, left_bh, root, &right, right_bh)
+    }
+
+
+    pub fn intersection<C: Comparator<T>>(&mut self, root: &RBTChild, black_height: usize, other: &RBTChild, other_bh: usize, cmp: &C) -> (RBTChild, usize) {
+        if self.get_root_nil(root) || self.get_root_nil(other) {
+            self._discard(root);
+            self._discard(other);
+            return (None, 0);
+        }
+        let child_bh = self._child_black_height(root, black_height);
+        let (left, right) = self._detach(root);
+        let (less, less_bh, found, greater, greater_bh) = self._split_by(other, other_bh, root, cmp);
+        let (left, left_bh) = self.intersection(&left, child_bh, &less, less_bh, cmp);
+        let (right, right_bh) = self.intersection(&right, child_bh, &greater, greater_bh, cmp);
+        if self.get_root_nil(&found) {
+            self._discard(root);
+            return self.join_concat(&left, left_bh, &right, right_bh);
+        }
+        self._discard(&found);
+        self.join(&left, left_bh, root, &right, right_bh)
+    }
+
+
+    pub fn difference<C: Comparator<T>>(&mut self, root: &RBTChild, black_height: usize, other: &RBTChild, other_bh: usize, cmp: &C) -> (RBTChild, usize) {
+        if self.get_root_nil(root) || self.get_root_nil(other) {
+            self._discard(other);
+            return (*root, black_height);
+        }
+        let other_child_bh = self._child_black_height(other, other_bh);
+        let (other_left, other_right) = self._detach(other);
+        let (less, less_bh, found, greater, greater_bh) = self._split_by(root, black_height, other, cmp);
+        self._discard(&found);
+        self._discard(other);
+        let (left, left_bh) = self.difference(&less, less_bh, &other_left, other_child_bh, cmp);
+        let (right, right_bh) = self.difference(&greater, greater_bh, &other_right, other_child_bh, cmp);
+        self.join_concat(&left, left_bh, &right, right_bh)
+    }
+
+
+    pub fn symmetric_difference<C: Comparator<T>>(&mut self, root: &RBTChild, black_height: usize, other: &RBTChild, other_bh: usize, cmp: &C) -> (RBTChild, usize) {
+        if self.get_root_nil(root) {
+            return (*other, other_bh);
+        }
+        if self.get_root_nil(other) {
+            return (*root, black_height);
+        }
+        let child_bh = self._child_black_height(root, black_height);
+        let (left, right) = self._detach(root);
+        let (less, less_bh, found, greater, greater_bh) = self._split_by(other, other_bh, root, cmp);
+        let (left, left_bh) = self.symmetric_difference(&left, child_bh, &less, less_bh, cmp);
+        let (right, right_bh) = self.symmetric_difference(&right, child_bh, &greater, greater_bh, cmp);
+        if self.get_root_nil(&found) {
+            return self.join(&left, left_bh, root, &right, right_bh);
+        }
+        self._discard(root);
+        self._discard(&found);
+        self.join_concat(&left, left_bh, &right, right_bh)
+    }
+
+
+    pub fn get_right(&self, root: &RBTChild) -> RBTChild {
+        root.and_then(|id| self.get(id).right_child)
+    }
+
+
+    pub fn find_slot<Q: ?Sized, C: Comparator<Q>>(&self, root: &RBTChild, key: &Q, cmp: &C) -> RBTSlot where T: borrow::Borrow<Q> {
+        /*
+            Descends once towards key. Returns the node holding key, or the node (None for an empty tree)
+            whose child slot on the given side is where key would have to be inserted.
+        */
+        let mut node = *root;
+        let mut slot = (None, Direction::Left);
+        while !self.get_root_nil(&node) {
+            let ordering = cmp.compare(key, borrow::Borrow::borrow(self.get(node.unwrap())._key()));
+            match ordering {
+                Ordering::Less => {
+                    let left = self.get_left(&node);
+                    slot = (node, Direction::Left);
+                    node = left;
+                },
+                Ordering::Equal => return Ok(node),
+                Ordering::Greater => {
+                    let right = self.get_right(&node);
+                    slot = (node, Direction::Right);
+                    node = right;
+                },
+            }
+        }
+        Err(slot)
+    }
+
+
+    pub fn attach_entry(&mut self, parent: &RBTChild, key: T, value: V, direction: Direction) -> RBTChild {
+        let node = self._new(Some(key), Some(value), NodeColor::Red, None, false);
+        self.set_parent(&node, parent);
+        self.set_child(parent, node, direction);
+        node
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn insert_entry_node(&mut self, root: &RBTChild, key: T, value: V) -> RBTChild where T: Ord {
+        self._recurse_node(root, key, Some(value), &NaturalOrder)
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    pub fn find_node(&self, root: &RBTChild, key: &T) -> RBTChild where T: Ord {
+        self.find_node_by(root, key, &NaturalOrder)
+    }
+
+
+    pub fn find_node_by<Q: ?Sized, C: Comparator<Q>>(&self, root: &RBTChild, key: &Q, cmp: &C) -> RBTChild where T: borrow::Borrow<Q> {
+        self.find_slot(root, key, cmp).ok().flatten()
+    }
+
+
+    #[cfg(any(test, feature = "unstable-internals"))]
+    fn _recurse_node<C: Comparator<T>>(&mut self, root: &RBTChild, key: T, insert: Option<V>, cmp: &C) -> RBTChild {
+        let id = (*root)?;
+        let ordering = match &self.get(id).key {
+            Some(node_key) => cmp.compare(&key, node_key),
+            None => return None,
+        };
+        let (child, direction) = match ordering {
+            Ordering::Less => (self.get_left(root), Direction::Left),
+            Ordering::Equal => return Some(id),
+            Ordering::Greater => (self.get_right(root), Direction::Right),
+        };
+        match child {
+            Some(_) => self._recurse_node(&child, key, insert, cmp),
+            None => {
+                insert.as_ref()?;
+                let node = self._new(Some(key), insert, NodeColor::Red, *root, false);
+                self.set_child(root, node, direction);
+                node
+            },
         }
     }
 
@@ -0,0 +1,45 @@
+use super::*;
+use std::cmp::Ordering;
+use std::borrow::Borrow;
+use comparator::Comparator;
+
+/*
+    Operations shared by every balanced search tree in this crate. Code written against this trait
+    works with either balancing scheme, e.g. `fn load<S: SearchTree<u64>>(tree: &mut S)` accepts a
+    `RedBlackTree<u64>` as well as an `AVLTree<u64>`.
+*/
+pub trait SearchTree<T> {
+    // Handle returned by `search`; `None` when the key is not in the tree. It may borrow the tree.
+    type Node<'a> where Self: 'a;
+
+    /*
+        The tree's comparator. Lookups take any borrowed form of the key it can compare, e.g. a
+        `&str` for `String` keys ordered by `NaturalOrder`.
+    */
+    type Order: Comparator<T>;
+
+    // Only trees whose comparator has a default, like `NaturalOrder`, can be built without one.
+    fn new() -> Self where Self: Default {
+        Self::default()
+    }
+
+    // Returns whether `key` was new.
+    fn insert(&mut self, key: T) -> bool;
+
+    // Returns the removed key, or `None` if it was not in the tree.
+    fn delete<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, Self::Order: Comparator<Q>;
+
+    fn search<Q: ?Sized>(&self, key: &Q) -> Self::Node<'_> where T: Borrow<Q>, Self::Order: Comparator<Q>;
+
+    fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, Self::Order: Comparator<Q>;
+
+    fn is_empty(&self) -> bool;
+
+    fn get_height(&mut self) -> u128;
+
+    fn get_num_leaves(&mut self) -> u128;
+
+    fn print_tree(&self);
+
+    fn print_traverse(&self, order: Ordering);
+}
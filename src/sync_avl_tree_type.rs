@@ -0,0 +1,170 @@
+use super::*;
+use std::fmt::{self, Debug};
+use std::cmp::Ordering;
+use std::borrow::Borrow;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, PoisonError};
+use search_tree::SearchTree;
+use comparator::{Comparator, NaturalOrder};
+use persistent_avl_tree_type::PersistentAVLTree;
+
+/*
+    AVL counterpart of `SyncRedBlackTree`: the current version of a `PersistentAVLTree` behind a
+    `RwLock`, so it can be shared between threads and read through cheap snapshots.
+*/
+pub struct SyncAVLTree<T, C = NaturalOrder> {
+    current: RwLock<PersistentAVLTree<T, C>>,
+}
+
+impl<T: Ord> SyncAVLTree<T> {
+
+    pub fn new() -> SyncAVLTree<T> {
+        SyncAVLTree::from(PersistentAVLTree::new())
+    }
+}
+
+impl<T, C: Comparator<T>> SyncAVLTree<T, C> {
+
+    pub fn with_comparator(comparator: C) -> SyncAVLTree<T, C> {
+        SyncAVLTree::from(PersistentAVLTree::with_comparator(comparator))
+    }
+
+    // Returns whether `key` was new.
+    pub fn insert(&self, key: T) -> bool {
+        let mut current = self.write();
+        let next = current.insert(key);
+        let added = !next.ptr_eq(&current);
+        *current = next;
+        added
+    }
+
+    // Returns the removed key, or `None` if it was not in the tree.
+    pub fn delete<Q: ?Sized>(&self, key: &Q) -> Option<T> where T: Borrow<Q> + Clone, C: Comparator<Q> {
+        let mut current = self.write();
+        let removed = current.get(key).cloned();
+        *current = current.delete(key);
+        removed
+    }
+
+    /*
+        Applies several changes as one step: other threads see either none or all of them. `f`
+        receives the current version and returns the one to keep.
+    */
+    pub fn update<F: FnOnce(&PersistentAVLTree<T, C>) -> PersistentAVLTree<T, C>>(&self, f: F) {
+        let mut current = self.write();
+        *current = f(&current);
+    }
+
+    // The current version, which stays readable (and unchanged) however the tree is updated later.
+    pub fn snapshot(&self) -> PersistentAVLTree<T, C> {
+        self.read().clone()
+    }
+
+    pub fn search<Q: ?Sized>(&self, key: &Q) -> Option<T> where T: Borrow<Q> + Clone, C: Comparator<Q> {
+        self.read().get(key).cloned()
+    }
+
+    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        self.read().contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    pub fn get_height(&self) -> u128 {
+        self.read().get_height()
+    }
+
+    pub fn get_num_leaves(&self) -> u128 {
+        self.read().get_num_leaves()
+    }
+
+    pub fn print_tree(&self) where T: Debug {
+        self.snapshot().print_tree()
+    }
+
+    pub fn print_traverse(&self, order: Ordering) where T: Debug {
+        self.snapshot().print_traverse(order)
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, PersistentAVLTree<T, C>> {
+        self.current.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, PersistentAVLTree<T, C>> {
+        self.current.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Clone + Debug, C: Comparator<T>> SearchTree<T> for SyncAVLTree<T, C> {
+    type Node<'a> = Option<T> where Self: 'a;
+    type Order = C;
+
+    fn insert(&mut self, key: T) -> bool {
+        SyncAVLTree::insert(self, key)
+    }
+
+    fn delete<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        SyncAVLTree::delete(self, key)
+    }
+
+    fn search<Q: ?Sized>(&self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        SyncAVLTree::search(self, key)
+    }
+
+    fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        SyncAVLTree::contains(self, key)
+    }
+
+    fn is_empty(&self) -> bool {
+        SyncAVLTree::is_empty(self)
+    }
+
+    fn get_height(&mut self) -> u128 {
+        SyncAVLTree::get_height(self)
+    }
+
+    fn get_num_leaves(&mut self) -> u128 {
+        SyncAVLTree::get_num_leaves(self)
+    }
+
+    fn print_tree(&self) {
+        SyncAVLTree::print_tree(self)
+    }
+
+    fn print_traverse(&self, order: Ordering) {
+        SyncAVLTree::print_traverse(self, order)
+    }
+}
+
+impl<T, C: Comparator<T> + Default> Default for SyncAVLTree<T, C> {
+
+    fn default() -> Self {
+        SyncAVLTree::with_comparator(C::default())
+    }
+}
+
+impl<T, C> From<PersistentAVLTree<T, C>> for SyncAVLTree<T, C> {
+
+    fn from(tree: PersistentAVLTree<T, C>) -> Self {
+        SyncAVLTree { current: RwLock::new(tree) }
+    }
+}
+
+impl<T: Debug, C: Comparator<T>> Debug for SyncAVLTree<T, C> {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.read().fmt(f)
+    }
+}
+
+impl<T, C: Comparator<T> + Default> FromIterator<T> for SyncAVLTree<T, C> {
+
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        SyncAVLTree::from(iter.into_iter().collect::<PersistentAVLTree<T, C>>())
+    }
+}
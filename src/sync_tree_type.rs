@@ -0,0 +1,176 @@
+use super::*;
+use std::fmt::{self, Debug};
+use std::cmp::Ordering;
+use std::borrow::Borrow;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, PoisonError};
+use search_tree::SearchTree;
+use comparator::{Comparator, NaturalOrder};
+use persistent_tree_type::PersistentRedBlackTree;
+
+/*
+    Red-black tree that can be shared between threads, e.g. by reference with `std::thread::scope`
+    or inside an `Arc`. It holds the current version of a `PersistentRedBlackTree` behind a
+    `RwLock`: readers only hold the read lock for a lookup, or take a `snapshot` and drop the lock
+    right away, while a writer builds the next version and swaps it in. Snapshots never change, so
+    a long scan does not hold up writers.
+
+    A writer that panics leaves the previous version in place, since the new one is only stored
+    once it is complete, so a poisoned lock is simply taken over.
+*/
+pub struct SyncRedBlackTree<T, C = NaturalOrder> {
+    current: RwLock<PersistentRedBlackTree<T, C>>,
+}
+
+impl<T: Ord> SyncRedBlackTree<T> {
+
+    pub fn new() -> SyncRedBlackTree<T> {
+        SyncRedBlackTree::from(PersistentRedBlackTree::new())
+    }
+}
+
+impl<T, C: Comparator<T>> SyncRedBlackTree<T, C> {
+
+    pub fn with_comparator(comparator: C) -> SyncRedBlackTree<T, C> {
+        SyncRedBlackTree::from(PersistentRedBlackTree::with_comparator(comparator))
+    }
+
+    // Returns whether `key` was new.
+    pub fn insert(&self, key: T) -> bool {
+        let mut current = self.write();
+        let next = current.insert(key);
+        let added = !next.ptr_eq(&current);
+        *current = next;
+        added
+    }
+
+    // Returns the removed key, or `None` if it was not in the tree.
+    pub fn delete<Q: ?Sized>(&self, key: &Q) -> Option<T> where T: Borrow<Q> + Clone, C: Comparator<Q> {
+        let mut current = self.write();
+        let removed = current.get(key).cloned();
+        *current = current.delete(key);
+        removed
+    }
+
+    /*
+        Applies several changes as one step: other threads see either none or all of them. `f`
+        receives the current version and returns the one to keep.
+    */
+    pub fn update<F: FnOnce(&PersistentRedBlackTree<T, C>) -> PersistentRedBlackTree<T, C>>(&self, f: F) {
+        let mut current = self.write();
+        *current = f(&current);
+    }
+
+    // The current version, which stays readable (and unchanged) however the tree is updated later.
+    pub fn snapshot(&self) -> PersistentRedBlackTree<T, C> {
+        self.read().clone()
+    }
+
+    pub fn search<Q: ?Sized>(&self, key: &Q) -> Option<T> where T: Borrow<Q> + Clone, C: Comparator<Q> {
+        self.read().get(key).cloned()
+    }
+
+    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        self.read().contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    pub fn get_height(&self) -> u128 {
+        self.read().get_height()
+    }
+
+    pub fn get_num_leaves(&self) -> u128 {
+        self.read().get_num_leaves()
+    }
+
+    pub fn print_tree(&self) where T: Debug {
+        self.snapshot().print_tree()
+    }
+
+    pub fn print_traverse(&self, order: Ordering) where T: Debug {
+        self.snapshot().print_traverse(order)
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, PersistentRedBlackTree<T, C>> {
+        self.current.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, PersistentRedBlackTree<T, C>> {
+        self.current.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Clone + Debug, C: Comparator<T>> SearchTree<T> for SyncRedBlackTree<T, C> {
+    type Node<'a> = Option<T> where Self: 'a;
+    type Order = C;
+
+    fn insert(&mut self, key: T) -> bool {
+        SyncRedBlackTree::insert(self, key)
+    }
+
+    fn delete<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        SyncRedBlackTree::delete(self, key)
+    }
+
+    fn search<Q: ?Sized>(&self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        SyncRedBlackTree::search(self, key)
+    }
+
+    fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        SyncRedBlackTree::contains(self, key)
+    }
+
+    fn is_empty(&self) -> bool {
+        SyncRedBlackTree::is_empty(self)
+    }
+
+    fn get_height(&mut self) -> u128 {
+        SyncRedBlackTree::get_height(self)
+    }
+
+    fn get_num_leaves(&mut self) -> u128 {
+        SyncRedBlackTree::get_num_leaves(self)
+    }
+
+    fn print_tree(&self) {
+        SyncRedBlackTree::print_tree(self)
+    }
+
+    fn print_traverse(&self, order: Ordering) {
+        SyncRedBlackTree::print_traverse(self, order)
+    }
+}
+
+impl<T, C: Comparator<T> + Default> Default for SyncRedBlackTree<T, C> {
+
+    fn default() -> Self {
+        SyncRedBlackTree::with_comparator(C::default())
+    }
+}
+
+impl<T, C> From<PersistentRedBlackTree<T, C>> for SyncRedBlackTree<T, C> {
+
+    fn from(tree: PersistentRedBlackTree<T, C>) -> Self {
+        SyncRedBlackTree { current: RwLock::new(tree) }
+    }
+}
+
+impl<T: Debug, C: Comparator<T>> Debug for SyncRedBlackTree<T, C> {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.read().fmt(f)
+    }
+}
+
+impl<T, C: Comparator<T> + Default> FromIterator<T> for SyncRedBlackTree<T, C> {
+
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        SyncRedBlackTree::from(iter.into_iter().collect::<PersistentRedBlackTree<T, C>>())
+    }
+}
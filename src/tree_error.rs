@@ -0,0 +1,32 @@
+use std::error::Error;
+use std::fmt;
+
+/*
+    Why a fallible (`try_`) operation could not be carried out. The tree is left unchanged whenever
+    one of these is returned.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeError {
+    // A node-level operation got an empty or NIL handle where it needs a node.
+    EmptyNode,
+    // A rotation at a node without a child on the side that would have to move up.
+    MissingChild,
+    KeyNotFound,
+    DuplicateKey,
+    IndexOutOfBounds { index: usize, len: usize },
+}
+
+impl fmt::Display for TreeError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeError::EmptyNode => write!(f, "the node handle is empty"),
+            TreeError::MissingChild => write!(f, "the node has no child to rotate up"),
+            TreeError::KeyNotFound => write!(f, "the key is not in the tree"),
+            TreeError::DuplicateKey => write!(f, "the key is already in the tree"),
+            TreeError::IndexOutOfBounds { index, len } => write!(f, "index {} is out of bounds for {} keys", index, len),
+        }
+    }
+}
+
+impl Error for TreeError {}
@@ -0,0 +1,249 @@
+use super::*;
+use std::fmt::{self, Debug};
+use std::borrow::Borrow;
+use std::ops::RangeBounds;
+use red_black_tree::{RBEntryRef, RBEntryMut, RBTChild, Direction};
+use tree_type::{RedBlackTree, Iter, Range};
+use comparator::{Comparator, NaturalOrder};
+use augment::Augment;
+
+/*
+    Ordered map backed by a red-black tree. Every node carries its value next to the key, so the
+    rotations and fixups of `RedBlackTree` move both together.
+*/
+pub struct RedBlackTreeMap<K, V, C = NaturalOrder, A: Augment<K, V> = ()> {
+    tree: RedBlackTree<K, V, C, A>,
+}
+
+impl<K: Ord + Debug, V> RedBlackTreeMap<K, V> {
+
+    pub fn new() -> RedBlackTreeMap<K, V> {
+        RedBlackTreeMap { tree: RedBlackTree::new() }
+    }
+}
+
+impl<K: Debug, V, C: Comparator<K>> RedBlackTreeMap<K, V, C> {
+
+    pub fn with_comparator(comparator: C) -> RedBlackTreeMap<K, V, C> {
+        RedBlackTreeMap::augmented(comparator)
+    }
+}
+
+impl<K: Debug, V, C: Comparator<K>, A: Augment<K, V>> RedBlackTreeMap<K, V, C, A> {
+
+    /*
+        Empty tree that keeps the summaries of `A` for `fold_range`, e.g.
+        `RedBlackTreeMap::<u64, u64, NaturalOrder, SumOfValues>::augmented(NaturalOrder)`.
+    */
+    pub fn augmented(comparator: C) -> RedBlackTreeMap<K, V, C, A> {
+        RedBlackTreeMap { tree: RedBlackTree::augmented(comparator) }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.tree.insert_entry(key, value)
+    }
+
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<RBEntryRef<'_, K, V, A>> where K: Borrow<Q>, C: Comparator<Q> {
+        RBEntryRef::from_node(&self.tree.nodes, &self.tree.find_node(key))
+    }
+
+    pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<RBEntryMut<'_, K, V, A>> where K: Borrow<Q>, C: Comparator<Q> {
+        let node = self.tree.find_node(key);
+        RBEntryMut::from_node(&mut self.tree.nodes, &node)
+    }
+
+    /*
+        Looks `key` up with a single descent. A vacant entry remembers the slot where the descent
+        ended, so inserting through it does not search again.
+    */
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, C, A> {
+        match self.tree.find_slot(&key) {
+            Ok(node) => Entry::Occupied(OccupiedEntry { tree: &mut self.tree, node }),
+            Err((parent, direction)) => Entry::Vacant(VacantEntry { tree: &mut self.tree, key, parent, direction }),
+        }
+    }
+
+    pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<V> where K: Borrow<Q>, C: Comparator<Q> {
+        self.tree.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /*
+        Combines the summaries of the entries whose keys fall within the range, in key order.
+    */
+    pub fn fold_range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> A::Summary where K: Borrow<Q>, C: Comparator<Q> {
+        self.tree.fold_range(range)
+    }
+
+    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool where K: Borrow<Q>, C: Comparator<Q> {
+        !self.tree.nodes.get_root_nil(&self.tree.find_node(key))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    // Entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V, A> {
+        self.tree.iter()
+    }
+
+    pub fn range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> Range<'_, K, V, A> where K: Borrow<Q>, C: Comparator<Q> {
+        self.tree.range(range)
+    }
+
+    pub fn print_tree(&self) {
+        self.tree.print_tree()
+    }
+}
+
+pub enum Entry<'a, K: Debug, V, C = NaturalOrder, A: Augment<K, V> = ()> {
+    Occupied(OccupiedEntry<'a, K, V, C, A>),
+    Vacant(VacantEntry<'a, K, V, C, A>),
+}
+
+pub struct OccupiedEntry<'a, K: Debug, V, C = NaturalOrder, A: Augment<K, V> = ()> {
+    tree: &'a mut RedBlackTree<K, V, C, A>,
+    node: RBTChild,
+}
+
+pub struct VacantEntry<'a, K: Debug, V, C = NaturalOrder, A: Augment<K, V> = ()> {
+    tree: &'a mut RedBlackTree<K, V, C, A>,
+    key: K,
+    parent: RBTChild,
+    direction: Direction,
+}
+
+impl<'a, K: Debug, V, C: Comparator<K>, A: Augment<K, V>> Entry<'a, K, V, C, A> {
+
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> RBEntryMut<'a, K, V, A> {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> RBEntryMut<'a, K, V, A> {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> RBEntryMut<'a, K, V, A> where V: Default {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut().value_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Debug, V, C: Comparator<K>, A: Augment<K, V>> OccupiedEntry<'a, K, V, C, A> {
+
+    // The key stored in the map, not the one passed to `entry`, as with `BTreeMap`.
+    pub fn key(&self) -> &K {
+        self.tree.nodes.get_root_key_ref(&self.node)
+    }
+
+    pub fn get(&self) -> &V {
+        RBEntryRef::from_node(&self.tree.nodes, &self.node).expect("occupied entries hold a node").value()
+    }
+
+    // Like the map's `get_mut`, the handle brings the summaries above the entry up to date once dropped.
+    pub fn get_mut(&mut self) -> RBEntryMut<'_, K, V, A> {
+        RBEntryMut::from_node(&mut self.tree.nodes, &self.node).expect("occupied entries hold a node")
+    }
+
+    pub fn into_mut(self) -> RBEntryMut<'a, K, V, A> {
+        RBEntryMut::from_node(&mut self.tree.nodes, &self.node).expect("occupied entries hold a node")
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut().value_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        let node = self.tree.unlink_node(self.node);
+        self.tree.nodes.free_entry(node).map(|(_, value)| value).expect("occupied entries hold a value")
+    }
+}
+
+impl<'a, K: Debug, V, C: Comparator<K>, A: Augment<K, V>> VacantEntry<'a, K, V, C, A> {
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> RBEntryMut<'a, K, V, A> {
+        let node = self.tree.insert_at(&self.parent, self.direction, self.key, value);
+        RBEntryMut::from_node(&mut self.tree.nodes, &node).expect("a freshly inserted node is never NIL")
+    }
+}
+
+impl<K: Clone + Debug, V: Clone, C: Clone, A: Augment<K, V>> Clone for RedBlackTreeMap<K, V, C, A> {
+
+    fn clone(&self) -> Self {
+        RedBlackTreeMap { tree: self.tree.clone() }
+    }
+}
+
+impl<K: Debug, V, C: Comparator<K> + Default, A: Augment<K, V>> Default for RedBlackTreeMap<K, V, C, A> {
+
+    fn default() -> Self {
+        RedBlackTreeMap::augmented(C::default())
+    }
+}
+
+impl<K: Debug, V: PartialEq, C: Comparator<K>, A: Augment<K, V>> PartialEq for RedBlackTreeMap<K, V, C, A> {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.tree == other.tree
+    }
+}
+
+impl<K: Debug, V: Eq, C: Comparator<K>, A: Augment<K, V>> Eq for RedBlackTreeMap<K, V, C, A> {}
+
+// Prints the entries in key order, e.g. `{1: "a", 2: "b"}`.
+impl<K: Debug, V: Debug, C: Comparator<K>, A: Augment<K, V>> Debug for RedBlackTreeMap<K, V, C, A> {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for entry in self.tree.iter() {
+            map.entry(entry.key(), entry.value());
+        }
+        map.finish()
+    }
+}
+
+// Later values win over earlier ones for the same key, as with repeated `insert` calls.
+impl<K: Debug, V, C: Comparator<K> + Default, A: Augment<K, V>> FromIterator<(K, V)> for RedBlackTreeMap<K, V, C, A> {
+
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        RedBlackTreeMap { tree: RedBlackTree::from_entries(iter.into_iter().collect(), C::default()) }
+    }
+}
+
+impl<K: Debug, V, C: Comparator<K>, A: Augment<K, V>> Extend<(K, V)> for RedBlackTreeMap<K, V, C, A> {
+
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.tree.load_entries(iter.into_iter().collect())
+    }
+}
@@ -0,0 +1,215 @@
+use super::*;
+use std::fmt::{self, Debug};
+use std::borrow::Borrow;
+use red_black_tree::RBEntryRef;
+use tree_type::RedBlackTree;
+use comparator::{Comparator, NaturalOrder};
+
+/*
+    Multiset backed by a red-black tree. Each distinct key is stored once together with the number
+    of times it was inserted, so repeated keys cost no extra nodes or rebalancing.
+*/
+pub struct RedBlackTreeMultiset<T, C = NaturalOrder> {
+    tree: RedBlackTree<T, usize, C>,
+    len: usize,
+}
+
+impl<T: Ord + Debug> RedBlackTreeMultiset<T> {
+
+    pub fn new() -> RedBlackTreeMultiset<T> {
+        RedBlackTreeMultiset { tree: RedBlackTree::new(), len: 0 }
+    }
+}
+
+impl<T: Debug, C: Comparator<T>> RedBlackTreeMultiset<T, C> {
+
+    pub fn with_comparator(comparator: C) -> RedBlackTreeMultiset<T, C> {
+        RedBlackTreeMultiset { tree: RedBlackTree::with_comparator(comparator), len: 0 }
+    }
+
+    // Adds one occurrence of key and returns its new count.
+    pub fn insert(&mut self, key: T) -> usize {
+        self.len += 1;
+        match self.tree.find_slot(&key) {
+            Ok(node) => {
+                let count = self.tree.nodes.take_root_value(&node).unwrap_or(0) + 1;
+                self.tree.nodes.replace_root_value(&node, count);
+                count
+            },
+            Err((parent, direction)) => {
+                self.tree.insert_at(&parent, direction, key, 1);
+                1
+            },
+        }
+    }
+
+    // Removes one occurrence of key and returns whether there was one.
+    pub fn remove_one<Q: ?Sized>(&mut self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        let node = self.tree.find_node(key);
+        match self.tree.nodes.take_root_value(&node) {
+            Some(count) if count > 1 => {
+                self.tree.nodes.replace_root_value(&node, count - 1);
+            },
+            Some(_) => {
+                self.tree.remove_entry(key);
+            },
+            None => return false,
+        }
+        self.len -= 1;
+        true
+    }
+
+    // Removes every occurrence of key and returns how many there were.
+    pub fn remove_all<Q: ?Sized>(&mut self, key: &Q) -> usize where T: Borrow<Q>, C: Comparator<Q> {
+        let count = self.tree.remove_entry(key).map_or(0, |(_, count)| count);
+        self.len -= count;
+        count
+    }
+
+    pub fn count<Q: ?Sized>(&self, key: &Q) -> usize where T: Borrow<Q>, C: Comparator<Q> {
+        RBEntryRef::from_node(&self.tree.nodes, &self.tree.find_node(key)).map_or(0, |entry| *entry.value())
+    }
+
+    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        !self.tree.nodes.get_root_nil(&self.tree.find_node(key))
+    }
+
+    // Number of occurrences, counting every repeat.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn distinct_len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { entries: self.tree.iter(), front: None, front_remaining: 0, back: None, back_remaining: 0 }
+    }
+
+    pub fn print_tree(&self) {
+        self.tree.print_tree()
+    }
+}
+
+impl<T: Clone + Debug, C: Clone> Clone for RedBlackTreeMultiset<T, C> {
+
+    fn clone(&self) -> Self {
+        RedBlackTreeMultiset { tree: self.tree.clone(), len: self.len }
+    }
+}
+
+impl<T: Debug, C: Comparator<T> + Default> Default for RedBlackTreeMultiset<T, C> {
+
+    fn default() -> Self {
+        RedBlackTreeMultiset::with_comparator(C::default())
+    }
+}
+
+impl<T: Debug, C: Comparator<T>> PartialEq for RedBlackTreeMultiset<T, C> {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.tree == other.tree
+    }
+}
+
+impl<T: Debug, C: Comparator<T>> Eq for RedBlackTreeMultiset<T, C> {}
+
+// Prints every occurrence in order, e.g. `{1, 1, 2}`.
+impl<T: Debug, C: Comparator<T>> Debug for RedBlackTreeMultiset<T, C> {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut set = f.debug_set();
+        for entry in self.iter() {
+            set.entry(entry.key());
+        }
+        set.finish()
+    }
+}
+
+impl<T: Debug, C: Comparator<T> + Default> FromIterator<T> for RedBlackTreeMultiset<T, C> {
+
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut multiset = RedBlackTreeMultiset::default();
+        multiset.extend(iter);
+        multiset
+    }
+}
+
+impl<T: Debug, C: Comparator<T>> Extend<T> for RedBlackTreeMultiset<T, C> {
+
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for key in iter {
+            self.insert(key);
+        }
+    }
+}
+
+/*
+    In-order iterator that hands out each key as many times as it was inserted, from either end. The
+    items are entry handles whose `value()` is the key's total count.
+*/
+pub struct Iter<'a, T> {
+    entries: tree_type::Iter<'a, T, usize>,
+    front: Option<RBEntryRef<'a, T, usize>>,
+    front_remaining: usize,
+    back: Option<RBEntryRef<'a, T, usize>>,
+    back_remaining: usize,
+}
+
+impl<'a, T: Debug> Iterator for Iter<'a, T> {
+    type Item = RBEntryRef<'a, T, usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front_remaining == 0 {
+            match self.entries.next() {
+                Some(entry) => {
+                    self.front_remaining = *entry.value();
+                    self.front = Some(entry);
+                },
+                // the back end already holds the last key, so keep handing out its repeats
+                None if self.back_remaining > 0 => {
+                    self.back_remaining -= 1;
+                    return self.back;
+                },
+                None => return None,
+            }
+        }
+        self.front_remaining -= 1;
+        self.front
+    }
+}
+
+impl<'a, T: Debug> DoubleEndedIterator for Iter<'a, T> {
+
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.back_remaining == 0 {
+            match self.entries.next_back() {
+                Some(entry) => {
+                    self.back_remaining = *entry.value();
+                    self.back = Some(entry);
+                },
+                None if self.front_remaining > 0 => {
+                    self.front_remaining -= 1;
+                    return self.front;
+                },
+                None => return None,
+            }
+        }
+        self.back_remaining -= 1;
+        self.back
+    }
+}
+
+impl<'a, T: Debug, C: Comparator<T>> IntoIterator for &'a RedBlackTreeMultiset<T, C> {
+    type Item = RBEntryRef<'a, T, usize>;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
@@ -1,75 +1,97 @@
 use super::*;
-use red_black_tree::{RBTreeNode as RB, NodeColor};
+use red_black_tree::{RBArena, NodeColor};
 use tree_type::RedBlackTree as RBT;
-use avl_tree::{AVLTreeNode as AVL};
+use avl_tree::AVLArena;
 use avl_tree_type::AVLTree as AVLT;
-use rand::Rng;
-use rand::seq::SliceRandom;
-use std::{fmt::Debug, cmp::Ordering};
+use tree_map_type::RedBlackTreeMap as RBTMap;
+use avl_tree_map_type::AVLTreeMap as AVLTMap;
+use tree_multiset_type::RedBlackTreeMultiset as RBTMultiset;
+use avl_tree_multiset_type::AVLTreeMultiset as AVLTMultiset;
+use interval_tree::IntervalTree;
+use persistent_tree_type::PersistentRedBlackTree as PRBT;
+use persistent_avl_tree_type::PersistentAVLTree as PAVLT;
+use sync_tree_type::SyncRedBlackTree as SyncRBT;
+use sync_avl_tree_type::SyncAVLTree as SyncAVLT;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use search_tree::SearchTree;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::ops::Bound;
+use augment::Augment;
+use comparator::NaturalOrder;
+use tree_violation::TreeViolation;
+use tree_error::TreeError;
 
 #[test]
 fn test_rb_rotate() {
-    let root = RB::new(5);
+    let mut nodes = RBArena::new();
+    let root = nodes.new_node(5);
     for i in [5, 2, 10, 8, 6, 9, 12] {
-        RB::insert_node(&root, i);
+        nodes.insert_node(&root, i);
     }
-    RB::print_tree(&root);
+    nodes.print_tree(&root);
     
     // perform right rotate at 10
-    let root = RB::left_rotate(root, 12);
-    RB::print_tree(&root);
+    let root = nodes.left_rotate(root, &12);
+    nodes.print_tree(&root);
 }
 
 #[test]
 fn test_avl_left_rotate() {
-    let root = AVL::new(5);
+    let mut nodes = AVLArena::new();
+    let root = nodes.new_node(5);
     for i in [5, 2, 10, 8, 6, 9, 12] {
-        AVL::insert_node(&root, i);
+        nodes.insert_node(&root, i);
     }
-    AVL::print_tree(&root);
+    nodes.print_tree(&root);
 
     // perform right rotate at 10
-    let root = AVL::left_rotate(root, 10);
-    AVL::print_tree(&root);
+    let root = nodes.left_rotate(root, &10);
+    nodes.print_tree(&root);
 }
 
 #[test]
 fn test_avl_right_rotate() {
-    let root = AVL::new(5);
+    let mut nodes = AVLArena::new();
+    let root = nodes.new_node(5);
     for i in [5, 2, 10, 8, 6, 9, 12] {
-        AVL::insert_node(&root, i);
+        nodes.insert_node(&root, i);
     }
-    AVL::print_tree(&root);
+    nodes.print_tree(&root);
 }
 
 #[test]
 fn test_avl_set_root_key() {
-    let root = AVL::new(15);
+    let mut nodes = AVLArena::new();
+    let root = nodes.new_node(15);
     for i in [12, 19, 8, 23] {
-        AVL::insert_node(&root, i);
+        nodes.insert_node(&root, i);
     }
-    AVL::print_tree(&root);
-    AVL::set_root_key(&AVL::find_node(&root, 15), 16);
-    AVL::print_tree(&root);
+    nodes.print_tree(&root);
+    nodes.set_root_key(&nodes.find_node(&root, &15), 16);
+    nodes.print_tree(&root);
 }
 
 #[test]
 fn test_avl_node_get_height() {
-    let root = AVL::new(15);
-    assert_eq!(1, AVL::get_height(&root));
+    let mut nodes = AVLArena::new();
+    let root = nodes.new_node(15);
+    assert_eq!(1, nodes.get_height(&root));
 }
 
 #[test]
 fn test_avl_node_set_height() {
-    let root = AVL::new(15);
-    AVL::set_height(&root, 3);
-    assert_eq!(3, AVL::get_height(&root));
+    let mut nodes = AVLArena::new();
+    let root = nodes.new_node(15);
+    nodes.set_height(&root, 3);
+    assert_eq!(3, nodes.get_height(&root));
 }
 
 #[test]
 fn test_avl_node_update_height() {
-    let root = AVL::new(15);
-    assert_eq!(1, AVL::get_height(&root));
+    let mut nodes = AVLArena::new();
+    let root = nodes.new_node(15);
+    assert_eq!(1, nodes.get_height(&root));
 }
 
 
@@ -77,7 +99,7 @@ fn test_avl_node_update_height() {
 fn test_rb_insert() {
     let mut rbt = RBT::<u64>::new();
     for i in [5, 2, 10, 8, 6, 9, 12, 13] {
-        rbt.insert(&i);
+        rbt.insert(i);
     }
     rbt.print_tree();
 }
@@ -86,7 +108,7 @@ fn test_rb_insert() {
 fn test_avl_insert() {
     let mut avl = AVLT::<u64>::new();
     for i in [5, 2, 10, 8, 6, 9, 12, 13] {
-        avl.insert(&i);
+        avl.insert(i);
     }
     avl.print_tree();
 }
@@ -96,7 +118,7 @@ fn test_rb_delete1() {
     let mut rbt = RBT::<u64>::new();
     println!("{:?}", rbt.is_empty());
     for i in [12, 8, 15, 5, 9, 13, 19, 10, 23] {
-        rbt.insert(&i);
+        rbt.insert(i);
     }
     //RB::solidify_all_nil(&rbt.root);
     //RB::virtualize_all_nil(&rbt.root2;
@@ -114,7 +136,7 @@ fn test_pressure() {
     let mut inserted = vec![];
     for _ in 0..1000000 {
         let a = rng.gen_range(0..10000000);
-        rbt.insert(&a);
+        rbt.insert(a);
         inserted.append(&mut vec![a]);
         //println!("{:?}", a);
     }
@@ -124,6 +146,7 @@ fn test_pressure() {
     for i in inserted {
         rbt.delete(&i);
     }
+    assert!(rbt.is_empty());
     rbt.print_traverse(Ordering::Less);
     rbt.print_tree();
 }
@@ -132,7 +155,7 @@ fn test_pressure() {
 fn test_rb_delete2() {
     let mut rbt = RBT::<u64>::new();
     for i in [12, 8, 15, 5, 9, 13, 23, 1, 10] {
-        rbt.insert(&i);
+        rbt.insert(i);
     }
     rbt.delete(&5);
     rbt.print_tree();
@@ -142,7 +165,7 @@ fn test_rb_delete2() {
 fn test_rb_delete3() {
     let mut rbt = RBT::<u64>::new();
     for i in [12, 8, 15, 1, 9, 13, 23, 10] {
-        rbt.insert(&i);
+        rbt.insert(i);
     }
     rbt.delete(&12);
     rbt.print_tree();
@@ -152,25 +175,25 @@ fn test_rb_delete3() {
 fn test_rb_transplant1() {
     let mut rbt = RBT::<u64>::new();
     for i in [15, 12, 19, 9, 13, 23] {
-        rbt.insert(&i);
+        rbt.insert(i);
     }
     rbt.transplant(
-        &RB::find_node(&rbt.root, 15),
-        &RB::find_node(&rbt.root, 19)
+        &rbt.nodes.find_node(&rbt.root, &15),
+        &rbt.nodes.find_node(&rbt.root, &19)
     );
     rbt.print_tree();
-    //RB::print_tree(&rbt.get_minimum());
+    //rbt.nodes.print_tree(&rbt.get_minimum());
 }
 
 #[test]
 fn test_rb_transplant2() {
     let mut rbt = RBT::<u64>::new();
     for i in [15, 12, 19, 13, 23] {
-        rbt.insert(&i);
+        rbt.insert(i);
     }
     rbt.transplant(
-        &RB::find_node(&rbt.root, 12),
-        &RB::find_node(&rbt.root, 13)
+        &rbt.nodes.find_node(&rbt.root, &12),
+        &rbt.nodes.find_node(&rbt.root, &13)
     );
     rbt.print_tree();
 }
@@ -179,11 +202,11 @@ fn test_rb_transplant2() {
 fn test_rb_transplant3() {
     let mut rbt = RBT::<u64>::new();
     for i in [15, 12, 19, 8, 23] {
-        rbt.insert(&i);
+        rbt.insert(i);
     }
     rbt.transplant(
-        &RB::find_node(&rbt.root, 19),
-        &RB::find_node(&rbt.root, 23)
+        &rbt.nodes.find_node(&rbt.root, &19),
+        &rbt.nodes.find_node(&rbt.root, &23)
     );
     rbt.print_tree();
 }
@@ -192,10 +215,10 @@ fn test_rb_transplant3() {
 fn test_rb_transplant4() {
     let mut rbt = RBT::<u64>::new();
     for i in [15, 12, 19, 8, 23] {
-        rbt.insert(&i);
+        rbt.insert(i);
     }
     rbt.transplant(
-        &RB::find_node(&rbt.root, 23),
+        &rbt.nodes.find_node(&rbt.root, &23),
         &None
     );
     rbt.print_tree();
@@ -208,7 +231,7 @@ fn test_avl_delete_case1() {
     */
     let mut avl = AVLT::<u64>::new();
     for i in [11, 31, 18, 2, 1, 33, 28, 4, 3, 7, 5, 25, 20, 19, 15, 12, 14, 29] {
-        avl.insert(&i);
+        avl.insert(i);
     }
     avl.print_tree();
     avl.delete(&19);
@@ -222,7 +245,7 @@ fn test_avl_delete_case2() {
     */
     let mut avl = AVLT::<u64>::new();
     for i in [11, 31, 18, 2, 1, 33, 28, 4, 3, 7, 5, 25, 20, 19, 15, 12, 14, 29] {
-        avl.insert(&i);
+        avl.insert(i);
     }
     avl.print_tree();
     avl.delete(&28);
@@ -236,7 +259,7 @@ fn test_avl_delete_case3() {
     */
     let mut avl = AVLT::<u64>::new();
     for i in [11, 31, 18, 2, 1, 33, 28, 4, 3, 7, 5, 25, 20, 19, 15, 12, 14, 29] {
-        avl.insert(&i);
+        avl.insert(i);
     }
     avl.print_tree();
     avl.delete(&20);
@@ -253,7 +276,7 @@ fn test_avl_delete_case4_min_is_leaf() {
     */
     let mut avl = AVLT::<u64>::new();
     for i in [11, 31, 18, 2, 1, 33, 28, 4, 3, 7, 5, 25, 20, 19, 15, 12, 14, 29] {
-        avl.insert(&i);
+        avl.insert(i);
     }
     avl.print_tree();
     avl.delete(&18);
@@ -270,7 +293,7 @@ fn test_avl_delete_case4_min_has_right_tree() {
     */
     let mut avl = AVLT::<u64>::new();
     for i in [11, 31, 18, 2, 1, 33, 28, 4, 3, 7, 5, 25, 20, 19, 15, 12, 14, 29] {
-        avl.insert(&i);
+        avl.insert(i);
     }
     avl.print_tree();
     avl.delete(&25);
@@ -280,7 +303,7 @@ fn test_avl_delete_case4_min_has_right_tree() {
 #[test]
 fn test_avl_delete_only_root_left() {
     let mut avl = AVLT::<u64>::new();
-    avl.insert(&1);
+    avl.insert(1);
     avl.print_tree();
     avl.delete(&1);
     avl.print_tree();
@@ -296,7 +319,7 @@ fn test_avl_delete_empty() {
 fn test_avl_count_leaves() {
     let mut avl = AVLT::<u64>::new();
     for i in [15, 12, 19, 8, 23] {
-        avl.insert(&i);
+        avl.insert(i);
     }
     assert_eq!(2, avl.get_num_leaves());
 }
@@ -305,7 +328,7 @@ fn test_avl_count_leaves() {
 fn test_avl_get_height() {
     let mut avl = AVLT::<u64>::new();
     for i in [15, 12, 19, 8, 23] {
-        avl.insert(&i);
+        avl.insert(i);
     }
     assert_eq!(3, avl.get_height());
 }
@@ -314,23 +337,2119 @@ fn test_avl_get_height() {
 fn test_avl_print_traverse() {
     let mut avl = AVLT::<u64>::new();
     for i in [15, 12, 19, 8, 23] {
-        avl.insert(&i);
+        avl.insert(i);
     }
     avl.print_traverse(Ordering::Greater);
     avl.print_traverse(Ordering::Less);
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn test_avl_check_empty() {
     let mut avl = AVLT::<u64>::new();
     assert_eq!(true, avl.is_empty());
 
     for i in [15, 12, 19, 8, 23] {
-        avl.insert(&i);
+        avl.insert(i);
     }
     assert_eq!(false, avl.is_empty());
 }
 
+fn search_tree_roundtrip<S: SearchTree<u64> + Default>() {
+    let mut tree = S::new();
+    assert!(tree.is_empty());
+    for i in [15, 12, 19, 8, 23] {
+        tree.insert(i);
+    }
+    assert!(tree.contains(&19));
+    assert_eq!(3, tree.get_height());
+    tree.delete(&19);
+    assert!(!tree.contains(&19));
+    assert!(!tree.is_empty());
+}
+
+#[test]
+fn test_search_tree_generic() {
+    search_tree_roundtrip::<RBT<u64>>();
+    search_tree_roundtrip::<AVLT<u64>>();
+    search_tree_roundtrip::<PRBT<u64>>();
+    search_tree_roundtrip::<PAVLT<u64>>();
+    search_tree_roundtrip::<SyncRBT<u64>>();
+    search_tree_roundtrip::<SyncAVLT<u64>>();
+}
+
+fn search_tree_by_name<S: SearchTree<String, Order = NaturalOrder>>(mut tree: S) {
+    for name in ["delta", "alpha", "charlie"] {
+        tree.insert(name.to_string());
+    }
+    assert!(tree.contains("alpha") && !tree.contains("bravo"));
+    assert_eq!(Some("alpha".to_string()), tree.delete("alpha"));
+    assert_eq!(None, tree.delete("alpha"));
+    assert!(!tree.contains("alpha"));
+}
+
+// Keys in descending order, through a comparator that has no `Default`.
+fn search_tree_descending<S: SearchTree<u64>>(mut tree: S) {
+    for i in [15, 12, 19, 8, 23] {
+        assert!(tree.insert(i));
+    }
+    assert!(!tree.insert(12));
+    assert!(tree.contains(&8));
+    assert_eq!(Some(19), tree.delete(&19));
+    assert!(!tree.contains(&19));
+}
+
+#[test]
+fn test_search_tree_generic_keys() {
+    search_tree_by_name(RBT::<String>::new());
+    search_tree_by_name(AVLT::<String>::new());
+    search_tree_by_name(PRBT::<String>::new());
+    search_tree_by_name(PAVLT::<String>::new());
+    search_tree_by_name(SyncRBT::<String>::new());
+    search_tree_by_name(SyncAVLT::<String>::new());
+    let descending = |a: &u64, b: &u64| b.cmp(a);
+    search_tree_descending(RBT::with_comparator(descending));
+    search_tree_descending(AVLT::with_comparator(descending));
+    search_tree_descending(PRBT::with_comparator(descending));
+    search_tree_descending(PAVLT::with_comparator(descending));
+    search_tree_descending(SyncRBT::with_comparator(descending));
+    search_tree_descending(SyncAVLT::with_comparator(descending));
+}
+
+#[test]
+fn test_rb_map() {
+    let mut map = RBTMap::<u64, String>::new();
+    for i in [12, 8, 15, 5, 9, 13, 19, 10, 23] {
+        assert_eq!(None, map.insert(i, format!("v{}", i)));
+    }
+    assert_eq!(Some("v9".to_string()), map.insert(9, "nine".to_string()));
+    assert_eq!("nine", *map.get(&9).unwrap().value());
+    map.get_mut(&13).unwrap().value_mut().push('!');
+    assert_eq!("v13!", *map.get(&13).unwrap().value());
+    for i in [12, 8, 5] {
+        assert_eq!(Some(format!("v{}", i)), map.remove(&i));
+    }
+    assert_eq!(None, map.remove(&12));
+    assert!(!map.contains_key(&8));
+    for i in [9, 10, 13, 15, 19, 23] {
+        assert!(map.contains_key(&i));
+    }
+    assert_eq!(6, map.len());
+    assert_eq!(vec![9, 10, 13, 15, 19, 23], map.iter().map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(vec!["v15", "v13!", "v10"], map.range(10..=15).rev().map(|entry| entry.value().as_str()).collect::<Vec<&str>>());
+    assert_eq!(0, map.range(16..19).count());
+}
+
+#[test]
+fn test_avl_map() {
+    let mut map = AVLTMap::<u64, String>::new();
+    for i in [11, 31, 18, 2, 1, 33, 28, 4, 3, 7, 5, 25, 20, 19, 15, 12, 14, 29] {
+        assert_eq!(None, map.insert(i, format!("v{}", i)));
+    }
+    assert_eq!(Some("v7".to_string()), map.insert(7, "seven".to_string()));
+    assert_eq!("seven", *map.get(&7).unwrap().value());
+    map.get_mut(&20).unwrap().value_mut().push('!');
+    assert_eq!("v20!", *map.get(&20).unwrap().value());
+    // 18 and 11 have both subtrees, so their entries trade places with their successors.
+    for i in [18, 11, 28] {
+        assert_eq!(Some(format!("v{}", i)), map.remove(&i));
+    }
+    assert_eq!(None, map.remove(&18));
+    for i in [19, 12, 29] {
+        assert_eq!(format!("v{}", i), *map.get(&i).unwrap().value());
+    }
+    assert_eq!(15, map.len());
+    assert_eq!(vec![1, 2, 3, 4, 5], map.iter().take(5).map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(vec!["v19", "v20!", "v25"], map.range(19..28).map(|entry| entry.value().as_str()).collect::<Vec<&str>>());
+    assert_eq!(0, map.range(8..11).count());
+}
+
+#[test]
+fn test_rb_iter() {
+    let mut rbt = RBT::<u64>::new();
+    assert_eq!(0, rbt.iter().count());
+    for i in [12, 8, 15, 5, 9, 13, 19, 10, 23] {
+        rbt.insert(i);
+    }
+    let asc: Vec<u64> = rbt.iter().map(|entry| *entry.key()).collect();
+    assert_eq!(vec![5, 8, 9, 10, 12, 13, 15, 19, 23], asc);
+    let desc: Vec<u64> = rbt.iter().rev().map(|entry| *entry.key()).collect();
+    assert_eq!(vec![23, 19, 15, 13, 12, 10, 9, 8, 5], desc);
+
+    let mut iter = rbt.iter();
+    assert_eq!(5, *iter.next().unwrap().key());
+    assert_eq!(23, *iter.next_back().unwrap().key());
+    assert_eq!(7, iter.count());
+
+    let copy = rbt.clone();
+    rbt.delete(&12);
+    assert_eq!(vec![5, 8, 9, 10, 12, 13, 15, 19, 23], copy.into_iter().collect::<Vec<u64>>());
+    assert_eq!(vec![23, 19, 15, 13, 10, 9, 8, 5], rbt.into_iter().rev().collect::<Vec<u64>>());
+}
+
+#[test]
+fn test_avl_iter() {
+    let mut avl = AVLT::<u64>::new();
+    assert_eq!(0, avl.iter().count());
+    for i in [11, 31, 18, 2, 1, 33, 28, 4, 3, 7, 5, 25, 20, 19, 15, 12, 14, 29] {
+        avl.insert(i);
+    }
+    let mut expected = vec![1, 2, 3, 4, 5, 7, 11, 12, 14, 15, 18, 19, 20, 25, 28, 29, 31, 33];
+    let mut asc = vec![];
+    for entry in &avl {
+        asc.push(*entry.key());
+    }
+    assert_eq!(expected, asc);
+
+    let mut iter = avl.iter();
+    assert_eq!(33, *iter.next_back().unwrap().key());
+    assert_eq!(1, *iter.next().unwrap().key());
+    assert_eq!(16, iter.count());
+
+    let copy = avl.clone();
+    avl.delete(&18);
+    assert_eq!(expected, copy.into_iter().collect::<Vec<u64>>());
+    expected.retain(|k| *k != 18);
+    expected.reverse();
+    assert_eq!(expected, avl.into_iter().rev().collect::<Vec<u64>>());
+}
+
+#[test]
+fn test_rb_range() {
+    let mut rbt = RBT::<u64>::new();
+    for i in (0..100).step_by(10) {
+        rbt.insert(i);
+    }
+    let keys = |range: tree_type::Range<u64>| range.map(|entry| *entry.key()).collect::<Vec<u64>>();
+    assert_eq!(vec![20, 30, 40], keys(rbt.range(15..50)));
+    assert_eq!(vec![20, 30, 40, 50], keys(rbt.range(20..=50)));
+    assert_eq!(vec![0, 10], keys(rbt.range(..20)));
+    assert_eq!(vec![80, 90], keys(rbt.range(75..)));
+    assert_eq!(vec![30, 40], keys(rbt.range((Bound::Excluded(20), Bound::Excluded(50)))));
+    assert_eq!(Vec::<u64>::new(), keys(rbt.range(41..49)));
+    assert_eq!(Vec::<u64>::new(), keys(rbt.range(200..)));
+    assert_eq!(vec![60, 50, 40], rbt.range(40..=60).rev().map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(10, rbt.range(..).count());
+}
+
+#[test]
+fn test_avl_range() {
+    let mut avl = AVLT::<u64>::new();
+    for i in (0..100).step_by(10) {
+        avl.insert(i);
+    }
+    let keys = |range: avl_tree_type::Range<u64>| range.map(|entry| *entry.key()).collect::<Vec<u64>>();
+    assert_eq!(vec![20, 30, 40], keys(avl.range(15..50)));
+    assert_eq!(vec![20, 30, 40, 50], keys(avl.range(20..=50)));
+    assert_eq!(vec![0, 10], keys(avl.range(..20)));
+    assert_eq!(vec![80, 90], keys(avl.range(75..)));
+    assert_eq!(vec![30, 40], keys(avl.range((Bound::Excluded(20), Bound::Excluded(50)))));
+    assert_eq!(Vec::<u64>::new(), keys(avl.range(41..49)));
+    assert_eq!(Vec::<u64>::new(), keys(avl.range(200..)));
+    assert_eq!(vec![60, 50, 40], avl.range(40..=60).rev().map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(10, avl.range(..).count());
+}
+
+#[test]
+fn test_rb_order_statistics() {
+    let mut rbt = RBT::<u64>::new();
+    for i in [12, 8, 15, 5, 9, 13, 19, 10, 23] {
+        rbt.insert(i);
+    }
+    rbt.delete(&15);
+    assert_eq!(8, rbt.len());
+    assert_eq!(5, *rbt.select(0).unwrap().key());
+    assert_eq!(13, *rbt.select(5).unwrap().key());
+    assert!(rbt.select(8).is_none());
+    assert_eq!(0, rbt.rank(&1));
+    assert_eq!(4, rbt.rank(&12));
+    assert_eq!(5, rbt.rank(&13));
+    assert_eq!(8, rbt.rank(&100));
+    assert_eq!(3, rbt.count_range(9..13));
+    assert_eq!(4, rbt.count_range(9..=13));
+    assert_eq!(0, rbt.count_range(14..15));
+    assert_eq!(Some(12), rbt.remove_at(4));
+    assert_eq!(None, rbt.remove_at(7));
+    assert_eq!(vec![5, 8, 9, 10, 13, 19, 23], rbt.into_iter().collect::<Vec<u64>>());
+}
+
+#[test]
+fn test_avl_order_statistics() {
+    let mut avl = AVLT::<u64>::new();
+    for i in [11, 31, 18, 2, 1, 33, 28, 4, 3, 7, 5, 25, 20, 19, 15, 12, 14, 29] {
+        avl.insert(i);
+    }
+    avl.delete(&18);
+    assert_eq!(17, avl.len());
+    assert_eq!(1, *avl.select(0).unwrap().key());
+    assert_eq!(19, *avl.select(10).unwrap().key());
+    assert!(avl.select(17).is_none());
+    assert_eq!(6, avl.rank(&11));
+    assert_eq!(10, avl.rank(&18));
+    assert_eq!(17, avl.rank(&40));
+    assert_eq!(5, avl.count_range(10..20));
+    assert_eq!(Some(33), avl.remove_at(16));
+    assert_eq!(Some(1), avl.remove_at(0));
+    assert_eq!(15, avl.len());
+}
+
+#[test]
+fn test_rb_nearest_keys() {
+    let mut tree = RBT::<u64>::new();
+    assert!(tree.first().is_none());
+    assert!(tree.floor(&10).is_none());
+    for i in [40, 20, 60, 10, 30, 50, 70] {
+        tree.insert(i);
+    }
+    let key = |entry: Option<red_black_tree::RBEntryRef<u64>>| entry.map(|entry| *entry.key());
+    assert_eq!(Some(10), key(tree.first()));
+    assert_eq!(Some(70), key(tree.last()));
+    assert_eq!(Some(30), key(tree.floor(&35)));
+    assert_eq!(Some(30), key(tree.floor(&30)));
+    assert_eq!(None, key(tree.floor(&5)));
+    assert_eq!(Some(40), key(tree.ceiling(&35)));
+    assert_eq!(Some(40), key(tree.ceiling(&40)));
+    assert_eq!(None, key(tree.ceiling(&75)));
+    assert_eq!(Some(30), key(tree.predecessor(&40)));
+    assert_eq!(None, key(tree.predecessor(&10)));
+    assert_eq!(Some(50), key(tree.successor(&40)));
+    assert_eq!(Some(10), key(tree.successor(&0)));
+    assert_eq!(None, key(tree.successor(&70)));
+}
+
+#[test]
+fn test_avl_nearest_keys() {
+    let mut tree = AVLT::<u64>::new();
+    assert!(tree.first().is_none());
+    assert!(tree.floor(&10).is_none());
+    for i in [40, 20, 60, 10, 30, 50, 70] {
+        tree.insert(i);
+    }
+    let key = |entry: Option<avl_tree::AVLEntryRef<u64>>| entry.map(|entry| *entry.key());
+    assert_eq!(Some(10), key(tree.first()));
+    assert_eq!(Some(70), key(tree.last()));
+    assert_eq!(Some(30), key(tree.floor(&35)));
+    assert_eq!(Some(30), key(tree.floor(&30)));
+    assert_eq!(None, key(tree.floor(&5)));
+    assert_eq!(Some(40), key(tree.ceiling(&35)));
+    assert_eq!(Some(40), key(tree.ceiling(&40)));
+    assert_eq!(None, key(tree.ceiling(&75)));
+    assert_eq!(Some(30), key(tree.predecessor(&40)));
+    assert_eq!(None, key(tree.predecessor(&10)));
+    assert_eq!(Some(50), key(tree.successor(&40)));
+    assert_eq!(Some(10), key(tree.successor(&0)));
+    assert_eq!(None, key(tree.successor(&70)));
+}
+
+#[test]
+fn test_rb_split_join() {
+    let mut tree = RBT::<u64>::new();
+    for i in 0..100 {
+        tree.insert(i);
+    }
+    let mut upper = tree.split_off(&40);
+    assert_eq!((0..40).collect::<Vec<u64>>(), tree.clone().into_iter().collect::<Vec<u64>>());
+    assert_eq!((40..100).collect::<Vec<u64>>(), upper.clone().into_iter().collect::<Vec<u64>>());
+    assert_eq!((40, 60), (tree.len(), upper.len()));
+    assert_eq!(upper.nodes.get_root_color(&upper.root), red_black_tree::NodeColor::Black);
+    assert!(tree.split_off(&100).is_empty());
+
+    tree.append(&mut upper);
+    assert!(upper.is_empty());
+    assert_eq!((0..100).collect::<Vec<u64>>(), tree.clone().into_iter().collect::<Vec<u64>>());
+    assert_eq!(Some(57), tree.select(57).map(|entry| *entry.key()));
+
+    let mut overlap = RBT::<u64>::new();
+    for i in [5, 150, 200] {
+        overlap.insert(i);
+    }
+    tree.append(&mut overlap);
+    assert_eq!(102, tree.len());
+
+    let (mut left, mut right) = (RBT::<u64>::new(), RBT::<u64>::new());
+    for i in 0..10 {
+        left.insert(i);
+    }
+    for i in 11..1000 {
+        right.insert(i);
+    }
+    let mut joined = RBT::join(left, 10, right);
+    assert_eq!((0..1000).collect::<Vec<u64>>(), joined.clone().into_iter().collect::<Vec<u64>>());
+    assert_eq!(10, joined.rank(&10));
+    joined.delete(&500);
+    joined.insert(1000);
+    assert_eq!(1000, joined.len());
+}
+
+#[test]
+fn test_avl_split_join() {
+    let mut tree = AVLT::<u64>::new();
+    for i in 0..100 {
+        tree.insert(i);
+    }
+    let mut upper = tree.split_off(&40);
+    assert_eq!((0..40).collect::<Vec<u64>>(), tree.clone().into_iter().collect::<Vec<u64>>());
+    assert_eq!((40..100).collect::<Vec<u64>>(), upper.clone().into_iter().collect::<Vec<u64>>());
+    assert_eq!((40, 60), (tree.len(), upper.len()));
+    assert!(upper.get_height() <= 8);
+    assert!(tree.split_off(&100).is_empty());
+
+    tree.append(&mut upper);
+    assert!(upper.is_empty());
+    assert_eq!((0..100).collect::<Vec<u64>>(), tree.clone().into_iter().collect::<Vec<u64>>());
+    assert_eq!(Some(57), tree.select(57).map(|entry| *entry.key()));
+
+    let mut overlap = AVLT::<u64>::new();
+    for i in [5, 150, 200] {
+        overlap.insert(i);
+    }
+    tree.append(&mut overlap);
+    assert_eq!(102, tree.len());
+
+    let (mut left, mut right) = (AVLT::<u64>::new(), AVLT::<u64>::new());
+    for i in 0..10 {
+        left.insert(i);
+    }
+    for i in 11..1000 {
+        right.insert(i);
+    }
+    let mut joined = AVLT::join(left, 10, right);
+    assert_eq!((0..1000).collect::<Vec<u64>>(), joined.clone().into_iter().collect::<Vec<u64>>());
+    assert!(joined.nodes.get_balance_factor(&joined.root).abs() <= 1);
+    joined.delete(&500);
+    joined.insert(1000);
+    assert_eq!(1000, joined.len());
+}
+
+#[test]
+fn test_rb_set_operations() {
+    let (mut evens, mut thirds) = (RBT::<u64>::new(), RBT::<u64>::new());
+    for i in (0..30).step_by(2) {
+        evens.insert(i);
+    }
+    for i in (0..30).step_by(3) {
+        thirds.insert(i);
+    }
+    let keys = |tree: RBT<u64>| tree.into_iter().collect::<Vec<u64>>();
+    let union = vec![0, 2, 3, 4, 6, 8, 9, 10, 12, 14, 15, 16, 18, 20, 21, 22, 24, 26, 27, 28];
+    let intersection = vec![0, 6, 12, 18, 24];
+    let difference = vec![2, 4, 8, 10, 14, 16, 20, 22, 26, 28];
+    let symmetric = vec![2, 3, 4, 8, 9, 10, 14, 15, 16, 20, 21, 22, 26, 27, 28];
+
+    assert_eq!(union, evens.union(&thirds).map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(intersection, evens.intersection(&thirds).map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(difference, evens.difference(&thirds).map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(symmetric, evens.symmetric_difference(&thirds).map(|entry| *entry.key()).collect::<Vec<u64>>());
+
+    assert_eq!(union, keys(&evens | &thirds));
+    assert_eq!(intersection, keys(&evens & &thirds));
+    assert_eq!(difference, keys(&evens - &thirds));
+    assert_eq!(symmetric, keys(&evens ^ &thirds));
+    assert_eq!(15, evens.len());
+
+    let mut merged = evens.clone().into_union(thirds.clone());
+    assert_eq!(20, merged.len());
+    assert_eq!(5, merged.rank(&8));
+    merged.delete(&0);
+    merged.insert(1);
+    assert_eq!(Some(1), merged.first().map(|entry| *entry.key()));
+    assert!(evens.clone().into_intersection(RBT::new()).is_empty());
+    assert_eq!(15, evens.clone().into_difference(RBT::new()).len());
+    assert_eq!(10, RBT::new().into_symmetric_difference(thirds).len());
+}
+
+#[test]
+fn test_avl_set_operations() {
+    let (mut evens, mut thirds) = (AVLT::<u64>::new(), AVLT::<u64>::new());
+    for i in (0..30).step_by(2) {
+        evens.insert(i);
+    }
+    for i in (0..30).step_by(3) {
+        thirds.insert(i);
+    }
+    let keys = |tree: AVLT<u64>| tree.into_iter().collect::<Vec<u64>>();
+    let union = vec![0, 2, 3, 4, 6, 8, 9, 10, 12, 14, 15, 16, 18, 20, 21, 22, 24, 26, 27, 28];
+    let intersection = vec![0, 6, 12, 18, 24];
+    let difference = vec![2, 4, 8, 10, 14, 16, 20, 22, 26, 28];
+    let symmetric = vec![2, 3, 4, 8, 9, 10, 14, 15, 16, 20, 21, 22, 26, 27, 28];
+
+    assert_eq!(union, evens.union(&thirds).map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(intersection, evens.intersection(&thirds).map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(difference, evens.difference(&thirds).map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(symmetric, evens.symmetric_difference(&thirds).map(|entry| *entry.key()).collect::<Vec<u64>>());
+
+    assert_eq!(union, keys(&evens | &thirds));
+    assert_eq!(intersection, keys(&evens & &thirds));
+    assert_eq!(difference, keys(&evens - &thirds));
+    assert_eq!(symmetric, keys(&evens ^ &thirds));
+    assert_eq!(15, evens.len());
+
+    let mut merged = evens.clone().into_union(thirds.clone());
+    assert_eq!(20, merged.len());
+    assert_eq!(5, merged.rank(&8));
+    merged.delete(&0);
+    merged.insert(1);
+    assert_eq!(Some(1), merged.first().map(|entry| *entry.key()));
+    assert!(evens.clone().into_intersection(AVLT::new()).is_empty());
+    assert_eq!(15, evens.clone().into_difference(AVLT::new()).len());
+    assert_eq!(10, AVLT::new().into_symmetric_difference(thirds).len());
+}
+
+#[test]
+fn test_rb_arena_slots() {
+    // every node the tree no longer links to has to go back to the free list
+    let mut rng = StdRng::seed_from_u64(18);
+    let (mut left, mut right) = (RBT::<u32>::new(), RBT::<u32>::new());
+    for _ in 0..400 {
+        left.insert(rng.gen_range(0..200));
+        right.insert(rng.gen_range(100..300));
+        left.delete(&rng.gen_range(0..200));
+    }
+    assert_eq!(left.len(), left.nodes.live());
+    for merged in [
+        left.clone().into_union(right.clone()),
+        left.clone().into_intersection(right.clone()),
+        left.clone().into_difference(right.clone()),
+        left.clone().into_symmetric_difference(right.clone()),
+        left.clone().into_intersection(RBT::new()),
+        RBT::new().into_difference(right.clone()),
+    ] {
+        assert_eq!(merged.len(), merged.nodes.live());
+        assert_eq!(Ok(()), merged.validate());
+    }
+    let upper = left.split_off(&100);
+    assert_eq!((left.len(), upper.len()), (left.nodes.live(), upper.nodes.live()));
+    while left.pop_first().is_some() {}
+    assert_eq!(0, left.nodes.live());
+}
+
+#[test]
+fn test_avl_arena_slots() {
+    let mut rng = StdRng::seed_from_u64(18);
+    let (mut left, mut right) = (AVLT::<u32>::new(), AVLT::<u32>::new());
+    for _ in 0..400 {
+        left.insert(rng.gen_range(0..200));
+        right.insert(rng.gen_range(100..300));
+        left.delete(&rng.gen_range(0..200));
+    }
+    assert_eq!(left.len(), left.nodes.live());
+    for merged in [
+        left.clone().into_union(right.clone()),
+        left.clone().into_intersection(right.clone()),
+        left.clone().into_difference(right.clone()),
+        left.clone().into_symmetric_difference(right.clone()),
+        left.clone().into_intersection(AVLT::new()),
+        AVLT::new().into_difference(right.clone()),
+    ] {
+        assert_eq!(merged.len(), merged.nodes.live());
+        assert_eq!(Ok(()), merged.validate());
+    }
+    let upper = left.split_off(&100);
+    assert_eq!((left.len(), upper.len()), (left.nodes.live(), upper.nodes.live()));
+    while left.pop_first().is_some() {}
+    assert_eq!(0, left.nodes.live());
+}
+
+#[test]
+fn test_rb_bulk_load() {
+    let tree = RBT::from_sorted_iter(0..1000u64);
+    assert_eq!(1000, tree.len());
+    assert_eq!((0..1000).collect::<Vec<u64>>(), tree.clone().into_iter().collect::<Vec<u64>>());
+    assert_eq!(10, tree.nodes.get_height(&tree.root));
+    assert_eq!(red_black_tree::NodeColor::Black, tree.nodes.get_root_color(&tree.root));
+
+    let mut tree = RBT::from_sorted_iter([5u64, 3, 9, 3, 1, 9]);
+    assert_eq!(vec![1, 3, 5, 9], tree.clone().into_iter().collect::<Vec<u64>>());
+    tree.bulk_load([4, 5, 12]);
+    assert_eq!(vec![1, 3, 4, 5, 9, 12], tree.clone().into_iter().collect::<Vec<u64>>());
+    assert_eq!(2, tree.rank(&4));
+    assert!(RBT::<u64>::from_sorted_iter(Vec::new()).is_empty());
+}
+
+#[test]
+fn test_avl_bulk_load() {
+    let mut tree = AVLT::from_sorted_iter(0..1000u64);
+    assert_eq!(1000, tree.len());
+    assert_eq!((0..1000).collect::<Vec<u64>>(), tree.clone().into_iter().collect::<Vec<u64>>());
+    assert_eq!(10, tree.get_height());
+    assert_eq!(10, tree.nodes.get_height(&tree.root));
+
+    let mut tree = AVLT::from_sorted_iter([5u64, 3, 9, 3, 1, 9]);
+    assert_eq!(vec![1, 3, 5, 9], tree.clone().into_iter().collect::<Vec<u64>>());
+    tree.bulk_load([4, 5, 12]);
+    assert_eq!(vec![1, 3, 4, 5, 9, 12], tree.clone().into_iter().collect::<Vec<u64>>());
+    assert_eq!(2, tree.rank(&4));
+    assert!(AVLT::<u64>::from_sorted_iter(Vec::new()).is_empty());
+}
+
+#[test]
+fn test_rb_collection_traits() {
+    let tree: RBT<u64> = [8, 3, 5, 1, 3].into_iter().collect();
+    assert_eq!("{1, 3, 5, 8}", format!("{:?}", tree));
+    assert_eq!("{}", format!("{:?}", RBT::<u64>::default()));
+
+    let mut other = RBT::<u64>::new();
+    for i in [5, 1, 8] {
+        other.insert(i);
+    }
+    assert_ne!(tree, other);
+    other.extend([3]);
+    assert_eq!(tree, other);
+    other.extend(&[13, 21]);
+    assert_eq!(vec![1, 3, 5, 8, 13, 21], other.into_iter().collect::<Vec<u64>>());
+
+    let mut map: RBTMap<u64, &str> = [(2, "b"), (1, "a"), (2, "c")].into_iter().collect();
+    assert_eq!(r#"{1: "a", 2: "c"}"#, format!("{:?}", map));
+    map.extend([(3, "d")]);
+    let mut copy = RBTMap::default();
+    copy.insert(3, "d");
+    copy.insert(1, "a");
+    copy.insert(2, "c");
+    assert_eq!(map, copy);
+}
+
+#[test]
+fn test_avl_collection_traits() {
+    let tree: AVLT<u64> = [8, 3, 5, 1, 3].into_iter().collect();
+    assert_eq!("{1, 3, 5, 8}", format!("{:?}", tree));
+    assert_eq!("{}", format!("{:?}", AVLT::<u64>::default()));
+
+    let mut other = AVLT::<u64>::new();
+    for i in [5, 1, 8] {
+        other.insert(i);
+    }
+    assert_ne!(tree, other);
+    other.extend([3]);
+    assert_eq!(tree, other);
+    other.extend(&[13, 21]);
+    assert_eq!(vec![1, 3, 5, 8, 13, 21], other.into_iter().collect::<Vec<u64>>());
+
+    let mut map: AVLTMap<u64, &str> = [(2, "b"), (1, "a"), (2, "c")].into_iter().collect();
+    assert_eq!(r#"{1: "a", 2: "c"}"#, format!("{:?}", map));
+    map.extend([(3, "d")]);
+    let mut copy = AVLTMap::default();
+    copy.insert(3, "d");
+    copy.insert(1, "a");
+    copy.insert(2, "c");
+    assert_eq!(map, copy);
+}
+
+#[test]
+fn test_rb_map_entry() {
+    let mut counts = RBTMap::<&str, u64>::new();
+    for word in "the cat and the hat and the bat".split(' ') {
+        *counts.entry(word).or_insert(0).value_mut() += 1;
+    }
+    assert_eq!(r#"{"and": 2, "bat": 1, "cat": 1, "hat": 1, "the": 3}"#, format!("{:?}", counts));
+
+    counts.entry("cat").and_modify(|count| *count *= 10).or_insert(100);
+    counts.entry("dog").and_modify(|count| *count *= 10).or_insert(100);
+    assert_eq!(10, *counts.get(&"cat").unwrap().value());
+    assert_eq!(100, *counts.get(&"dog").unwrap().value());
+    assert_eq!(0, *counts.entry("emu").or_default().value());
+    assert_eq!(7, *counts.entry("fox").or_insert_with(|| 7).value());
+    assert_eq!("fox", *counts.entry("fox").key());
+
+    match counts.entry("the") {
+        tree_map_type::Entry::Occupied(mut entry) => {
+            assert_eq!(3, *entry.get());
+            assert_eq!(3, entry.insert(4));
+            assert_eq!(4, entry.remove());
+        },
+        tree_map_type::Entry::Vacant(_) => panic!("\"the\" was counted"),
+    }
+    match counts.entry("yak") {
+        tree_map_type::Entry::Occupied(_) => panic!("\"yak\" was never counted"),
+        tree_map_type::Entry::Vacant(entry) => assert_eq!("yak", entry.into_key()),
+    }
+    assert!(!counts.contains_key(&"the"));
+    assert!(!counts.contains_key(&"yak"));
+
+    // an occupied entry hands back the key the map already holds
+    let mut words = RBTMap::<&str, u64, _>::with_comparator(|a: &&str, b: &&str| a.to_lowercase().cmp(&b.to_lowercase()));
+    words.insert("Cat", 1);
+    assert_eq!("Cat", *words.entry("cAT").key());
+    match words.entry("CAT") {
+        tree_map_type::Entry::Occupied(entry) => assert_eq!("Cat", *entry.key()),
+        tree_map_type::Entry::Vacant(_) => panic!("\"Cat\" was inserted"),
+    }
+    assert_eq!("dog", *words.entry("dog").key());
+}
+
+#[test]
+fn test_avl_map_entry() {
+    let mut counts = AVLTMap::<&str, u64>::new();
+    for word in "the cat and the hat and the bat".split(' ') {
+        *counts.entry(word).or_insert(0).value_mut() += 1;
+    }
+    assert_eq!(r#"{"and": 2, "bat": 1, "cat": 1, "hat": 1, "the": 3}"#, format!("{:?}", counts));
+
+    counts.entry("cat").and_modify(|count| *count *= 10).or_insert(100);
+    counts.entry("dog").and_modify(|count| *count *= 10).or_insert(100);
+    assert_eq!(10, *counts.get(&"cat").unwrap().value());
+    assert_eq!(100, *counts.get(&"dog").unwrap().value());
+    assert_eq!(0, *counts.entry("emu").or_default().value());
+    assert_eq!(7, *counts.entry("fox").or_insert_with(|| 7).value());
+    assert_eq!("fox", *counts.entry("fox").key());
+
+    match counts.entry("the") {
+        avl_tree_map_type::Entry::Occupied(mut entry) => {
+            assert_eq!(3, *entry.get());
+            assert_eq!(3, entry.insert(4));
+            assert_eq!(4, entry.remove());
+        },
+        avl_tree_map_type::Entry::Vacant(_) => panic!("\"the\" was counted"),
+    }
+    match counts.entry("yak") {
+        avl_tree_map_type::Entry::Occupied(_) => panic!("\"yak\" was never counted"),
+        avl_tree_map_type::Entry::Vacant(entry) => assert_eq!("yak", entry.into_key()),
+    }
+    assert!(!counts.contains_key(&"the"));
+    assert!(!counts.contains_key(&"yak"));
+
+    // an occupied entry hands back the key the map already holds
+    let mut words = AVLTMap::<&str, u64, _>::with_comparator(|a: &&str, b: &&str| a.to_lowercase().cmp(&b.to_lowercase()));
+    words.insert("Cat", 1);
+    assert_eq!("Cat", *words.entry("cAT").key());
+    match words.entry("CAT") {
+        avl_tree_map_type::Entry::Occupied(entry) => assert_eq!("Cat", *entry.key()),
+        avl_tree_map_type::Entry::Vacant(_) => panic!("\"Cat\" was inserted"),
+    }
+    assert_eq!("dog", *words.entry("dog").key());
+}
+
+#[test]
+fn test_rb_multiset() {
+    let mut events = RBTMultiset::<u64>::new();
+    for timestamp in [30, 10, 20, 10, 30, 10] {
+        events.insert(timestamp);
+    }
+    assert_eq!(4, events.insert(10));
+    assert_eq!((7, 3), (events.len(), events.distinct_len()));
+    assert_eq!(vec![10, 10, 10, 10, 20, 30, 30], events.iter().map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!((4, 1, 0), (events.count(&10), events.count(&20), events.count(&15)));
+
+    assert!(events.remove_one(&20));
+    assert!(!events.remove_one(&20));
+    assert!(!events.contains(&20));
+    assert!(events.remove_one(&30));
+    assert_eq!(1, events.count(&30));
+    assert_eq!(4, events.remove_all(&10));
+    assert_eq!(0, events.remove_all(&10));
+    assert_eq!("{30}", format!("{:?}", events));
+    assert_eq!(1, events.len());
+
+    let collected: RBTMultiset<u64> = [2, 1, 2].into_iter().collect();
+    assert_eq!("{1, 2, 2}", format!("{:?}", collected));
+    let mut other = RBTMultiset::default();
+    other.extend([2, 2, 1]);
+    assert_eq!(collected, other);
+    other.remove_one(&2);
+    assert_ne!(collected, other);
+}
+
+#[test]
+fn test_avl_multiset() {
+    let mut events = AVLTMultiset::<u64>::new();
+    for timestamp in [30, 10, 20, 10, 30, 10] {
+        events.insert(timestamp);
+    }
+    assert_eq!(4, events.insert(10));
+    assert_eq!((7, 3), (events.len(), events.distinct_len()));
+    assert_eq!(vec![10, 10, 10, 10, 20, 30, 30], events.iter().map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!((4, 1, 0), (events.count(&10), events.count(&20), events.count(&15)));
+
+    assert!(events.remove_one(&20));
+    assert!(!events.remove_one(&20));
+    assert!(!events.contains(&20));
+    assert!(events.remove_one(&30));
+    assert_eq!(1, events.count(&30));
+    assert_eq!(4, events.remove_all(&10));
+    assert_eq!(0, events.remove_all(&10));
+    assert_eq!("{30}", format!("{:?}", events));
+    assert_eq!(1, events.len());
+
+    let collected: AVLTMultiset<u64> = [2, 1, 2].into_iter().collect();
+    assert_eq!("{1, 2, 2}", format!("{:?}", collected));
+    let mut other = AVLTMultiset::default();
+    other.extend([2, 2, 1]);
+    assert_eq!(collected, other);
+    other.remove_one(&2);
+    assert_ne!(collected, other);
+}
+
+#[test]
+fn test_rb_multiset_rev() {
+    let events: RBTMultiset<u64> = [30, 10, 20, 10, 30, 10].into_iter().collect();
+    assert_eq!(vec![30, 30, 20, 10, 10, 10], events.iter().rev().map(|entry| *entry.key()).collect::<Vec<u64>>());
+
+    // both ends meet inside the repeats of one key
+    let ones: RBTMultiset<u64> = [1, 1, 1, 1].into_iter().collect();
+    let mut iter = ones.iter();
+    assert_eq!(Some(1), iter.next().map(|entry| *entry.key()));
+    assert_eq!(Some(1), iter.next_back().map(|entry| *entry.key()));
+    assert_eq!(2, iter.count());
+
+    let mut iter = events.iter();
+    let mut taken = Vec::new();
+    while let (Some(front), Some(back)) = (iter.next(), iter.next_back()) {
+        taken.push((*front.key(), *back.key()));
+    }
+    assert_eq!(vec![(10, 30), (10, 30), (10, 20)], taken);
+    assert!(iter.next().is_none() && iter.next_back().is_none());
+}
+
+#[test]
+fn test_avl_multiset_rev() {
+    let events: AVLTMultiset<u64> = [30, 10, 20, 10, 30, 10, 40].into_iter().collect();
+    assert_eq!(vec![40, 30, 30, 20, 10, 10, 10], events.iter().rev().map(|entry| *entry.key()).collect::<Vec<u64>>());
+
+    let mut iter = events.iter();
+    assert_eq!(Some(40), iter.next_back().map(|entry| *entry.key()));
+    assert_eq!(Some(10), iter.next().map(|entry| *entry.key()));
+    let rest = iter.rev().map(|entry| *entry.key()).collect::<Vec<u64>>();
+    assert_eq!(vec![30, 30, 20, 10, 10], rest);
+}
+
+#[test]
+fn test_rb_comparator() {
+    let mut descending = RBT::with_comparator(|a: &u64, b: &u64| b.cmp(a));
+    for i in 0..200 {
+        descending.insert(i);
+    }
+    for i in (0..200).step_by(3) {
+        descending.delete(&i);
+    }
+    let expected: Vec<u64> = (0..200).rev().filter(|i| i % 3 != 0).collect();
+    assert_eq!(expected, descending.iter().map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(Some(199), descending.first().map(|entry| *entry.key()));
+    assert_eq!(Some(197), descending.ceiling(&198).map(|entry| *entry.key()));
+    assert_eq!(7, descending.count_range(..=190));
+
+    let mut floats = RBT::with_comparator(|a: &f64, b: &f64| a.total_cmp(b));
+    for x in [2.5, -0.0, 0.0, f64::INFINITY, -1.25, 2.5] {
+        floats.insert(x);
+    }
+    assert_eq!("{-1.25, -0.0, 0.0, 2.5, inf}", format!("{:?}", floats));
+    assert_eq!(2, floats.rank(&0.0));
+
+    let mut words = RBTMap::with_comparator(|a: &String, b: &String| a.to_lowercase().cmp(&b.to_lowercase()));
+    for word in ["apple", "Banana", "APPLE", "cherry", "banana"] {
+        *words.entry(word.to_string()).or_insert(0).value_mut() += 1;
+    }
+    assert_eq!(Some(2), words.get(&"BANANA".to_string()).map(|entry| *entry.value()));
+    assert_eq!("{\"apple\": 2, \"Banana\": 2, \"cherry\": 1}", format!("{:?}", words));
+
+    let by_id = |a: &(u32, &'static str), b: &(u32, &'static str)| a.0.cmp(&b.0);
+    let mut staff = RBT::with_comparator(by_id);
+    staff.extend([(3, "carol"), (1, "alice"), (2, "bob")]);
+    let mut others = RBT::with_comparator(by_id);
+    others.extend([(2, "robert"), (4, "dave")]);
+    assert_eq!(vec![1, 3], staff.difference(&others).map(|entry| entry.key().0).collect::<Vec<u32>>());
+    let high = staff.split_off(&(2, ""));
+    assert_eq!("{(1, \"alice\")}", format!("{:?}", staff));
+    assert_eq!("{(2, \"bob\"), (3, \"carol\")}", format!("{:?}", high));
+
+    let mut by_length = RBTMultiset::with_comparator(|a: &&str, b: &&str| a.len().cmp(&b.len()));
+    by_length.extend(["ab", "cd", "efg"]);
+    assert_eq!((2, 1), (by_length.count(&"zz"), by_length.count(&"zzz")));
+}
+
+#[test]
+fn test_avl_comparator() {
+    let mut descending = AVLT::with_comparator(|a: &u64, b: &u64| b.cmp(a));
+    for i in 0..200 {
+        descending.insert(i);
+    }
+    for i in (0..200).step_by(3) {
+        descending.delete(&i);
+    }
+    let expected: Vec<u64> = (0..200).rev().filter(|i| i % 3 != 0).collect();
+    assert_eq!(expected, descending.iter().map(|entry| *entry.key()).collect::<Vec<u64>>());
+    assert_eq!(Some(199), descending.first().map(|entry| *entry.key()));
+    assert_eq!(Some(197), descending.ceiling(&198).map(|entry| *entry.key()));
+    assert_eq!(7, descending.count_range(..=190));
+
+    let mut floats = AVLT::with_comparator(|a: &f64, b: &f64| a.total_cmp(b));
+    for x in [2.5, -0.0, 0.0, f64::INFINITY, -1.25, 2.5] {
+        floats.insert(x);
+    }
+    assert_eq!("{-1.25, -0.0, 0.0, 2.5, inf}", format!("{:?}", floats));
+    assert_eq!(2, floats.rank(&0.0));
+
+    let mut words = AVLTMap::with_comparator(|a: &String, b: &String| a.to_lowercase().cmp(&b.to_lowercase()));
+    for word in ["apple", "Banana", "APPLE", "cherry", "banana"] {
+        *words.entry(word.to_string()).or_insert(0).value_mut() += 1;
+    }
+    assert_eq!(Some(2), words.get(&"BANANA".to_string()).map(|entry| *entry.value()));
+    assert_eq!("{\"apple\": 2, \"Banana\": 2, \"cherry\": 1}", format!("{:?}", words));
+
+    let by_id = |a: &(u32, &'static str), b: &(u32, &'static str)| a.0.cmp(&b.0);
+    let mut staff = AVLT::with_comparator(by_id);
+    staff.extend([(3, "carol"), (1, "alice"), (2, "bob")]);
+    let mut others = AVLT::with_comparator(by_id);
+    others.extend([(2, "robert"), (4, "dave")]);
+    assert_eq!(vec![1, 3], staff.difference(&others).map(|entry| entry.key().0).collect::<Vec<u32>>());
+    let high = staff.split_off(&(2, ""));
+    assert_eq!("{(1, \"alice\")}", format!("{:?}", staff));
+    assert_eq!("{(2, \"bob\"), (3, \"carol\")}", format!("{:?}", high));
+
+    let mut by_length = AVLTMultiset::with_comparator(|a: &&str, b: &&str| a.len().cmp(&b.len()));
+    by_length.extend(["ab", "cd", "efg"]);
+    assert_eq!((2, 1), (by_length.count(&"zz"), by_length.count(&"zzz")));
+}
+
+struct SumOfValues;
+
+impl Augment<u64, u64> for SumOfValues {
+    type Summary = u64;
+
+    fn identity() -> u64 { 0 }
+
+    fn summarize(_key: &u64, value: &u64) -> u64 { *value }
+
+    fn combine(left: &u64, right: &u64) -> u64 { left + right }
+}
+
+// not commutative, so it also checks that summaries are combined in key order
+#[derive(Clone)]
+struct KeysInOrder;
+
+impl Augment<u64, ()> for KeysInOrder {
+    type Summary = Vec<u64>;
+
+    fn identity() -> Vec<u64> { Vec::new() }
+
+    fn summarize(key: &u64, _value: &()) -> Vec<u64> { vec![*key] }
+
+    fn combine(left: &Vec<u64>, right: &Vec<u64>) -> Vec<u64> { [left.as_slice(), right.as_slice()].concat() }
+}
+
+#[test]
+fn test_rb_fold_range() {
+    let mut totals = RBTMap::<u64, u64, NaturalOrder, SumOfValues>::augmented(NaturalOrder);
+    for i in 0..300 {
+        totals.insert(i, i * 2);
+    }
+    for i in (0..300).step_by(4) {
+        totals.remove(&i);
+    }
+    *totals.get_mut(&1).unwrap().value_mut() = 1000;
+    *totals.entry(301).or_insert(0).value_mut() += 5;
+    let expected = |lo: u64, hi: u64| (lo..hi).filter(|i| i % 4 != 0 && *i != 1).map(|i| i * 2).sum::<u64>();
+    assert_eq!(expected(0, 300) + 1000 + 5, totals.fold_range(..));
+    assert_eq!(expected(50, 151), totals.fold_range(50..=150));
+    assert_eq!(expected(0, 10) + 1000, totals.fold_range(..10));
+    assert_eq!(0, totals.fold_range(120..120));
+    assert_eq!(5, totals.fold_range((Bound::Excluded(299), Bound::Unbounded)));
+
+    let mut keys = RBT::<u64, (), NaturalOrder, KeysInOrder>::augmented(NaturalOrder);
+    for i in [5, 1, 9, 3, 7, 2, 8] {
+        keys.insert(i);
+    }
+    keys.delete(&7);
+    assert_eq!(vec![2, 3, 5, 8], keys.fold_range(2..9));
+    assert_eq!(vec![1, 2, 3, 5, 8, 9], keys.clone().fold_range(..));
+}
+
+#[test]
+fn test_avl_fold_range() {
+    let mut totals = AVLTMap::<u64, u64, NaturalOrder, SumOfValues>::augmented(NaturalOrder);
+    for i in 0..300 {
+        totals.insert(i, i * 2);
+    }
+    for i in (0..300).step_by(4) {
+        totals.remove(&i);
+    }
+    *totals.get_mut(&1).unwrap().value_mut() = 1000;
+    *totals.entry(301).or_insert(0).value_mut() += 5;
+    let expected = |lo: u64, hi: u64| (lo..hi).filter(|i| i % 4 != 0 && *i != 1).map(|i| i * 2).sum::<u64>();
+    assert_eq!(expected(0, 300) + 1000 + 5, totals.fold_range(..));
+    assert_eq!(expected(50, 151), totals.fold_range(50..=150));
+    assert_eq!(expected(0, 10) + 1000, totals.fold_range(..10));
+    assert_eq!(0, totals.fold_range(120..120));
+    assert_eq!(5, totals.fold_range((Bound::Excluded(299), Bound::Unbounded)));
+
+    let mut keys = AVLT::<u64, (), NaturalOrder, KeysInOrder>::augmented(NaturalOrder);
+    for i in [5, 1, 9, 3, 7, 2, 8] {
+        keys.insert(i);
+    }
+    keys.delete(&7);
+    assert_eq!(vec![2, 3, 5, 8], keys.fold_range(2..9));
+    assert_eq!(vec![1, 2, 3, 5, 8, 9], keys.clone().fold_range(..));
+}
+
+#[test]
+fn test_interval_tree() {
+    let mut bookings = IntervalTree::new();
+    assert!(bookings.insert(9, 11));
+    assert!(bookings.insert(13, 14));
+    assert!(bookings.insert(10, 12));
+    assert!(!bookings.insert(9, 11));
+    assert!(!bookings.insert(15, 15));
+    assert_eq!("{9..11, 10..12, 13..14}", format!("{:?}", bookings));
+    assert!(bookings.any_overlap(&11, &13));
+    assert!(!bookings.any_overlap(&12, &13));
+    assert!(!bookings.any_overlap(&14, &20));
+    assert_eq!(vec![(9, 11), (10, 12)], bookings.stab(&10).collect::<Vec<(u32, u32)>>());
+    assert_eq!(vec![(10, 12)], bookings.stab(&11).collect::<Vec<(u32, u32)>>());
+    assert_eq!(0, bookings.stab(&12).count());
+    assert!(bookings.remove(&10, &12));
+    assert!(!bookings.remove(&10, &12));
+    assert!(!bookings.any_overlap(&11, &13));
+    assert_eq!(0, bookings.overlapping(&10, &10).count());
+
+    let mut rng = rand::thread_rng();
+    let mut intervals = IntervalTree::new();
+    let mut expected: Vec<(u32, u32)> = Vec::new();
+    for _ in 0..2000 {
+        let lo = rng.gen_range(0..1000);
+        let hi = lo + rng.gen_range(1..50);
+        if rng.gen_bool(0.3) && !expected.is_empty() {
+            let (lo, hi) = expected.swap_remove(rng.gen_range(0..expected.len()));
+            assert!(intervals.remove(&lo, &hi));
+        } else {
+            assert_eq!(!expected.contains(&(lo, hi)), intervals.insert(lo, hi));
+            if !expected.contains(&(lo, hi)) {
+                expected.push((lo, hi));
+            }
+        }
+    }
+    expected.sort();
+    assert_eq!(expected.len(), intervals.len());
+    for _ in 0..200 {
+        let lo = rng.gen_range(0..1050);
+        let hi = lo + rng.gen_range(1..30);
+        let overlaps: Vec<(u32, u32)> = expected.iter().filter(|(a, b)| *a < hi && lo < *b).cloned().collect();
+        assert_eq!(overlaps, intervals.overlapping(&lo, &hi).collect::<Vec<(u32, u32)>>());
+        assert_eq!(!overlaps.is_empty(), intervals.any_overlap(&lo, &hi));
+        let stabbed: Vec<(u32, u32)> = expected.iter().filter(|(a, b)| *a <= lo && lo < *b).cloned().collect();
+        assert_eq!(stabbed, intervals.stab(&lo).collect::<Vec<(u32, u32)>>());
+    }
+}
+
+#[test]
+fn test_rb_persistent() {
+    let empty = PRBT::new();
+    let mut versions = vec![empty.clone()];
+    for i in 0..100u32 {
+        let next = versions.last().unwrap().insert((i * 37) % 100);
+        versions.push(next);
+    }
+    for i in (0..100u32).step_by(2) {
+        let next = versions.last().unwrap().delete(&i);
+        versions.push(next);
+    }
+    for (version, tree) in versions.iter().enumerate() {
+        let expected: Vec<u32> = if version <= 100 {
+            let mut keys: Vec<u32> = (0..version as u32).map(|i| (i * 37) % 100).collect();
+            keys.sort();
+            keys
+        } else {
+            (0..100).filter(|i| i % 2 == 1 || *i >= 2 * (version as u32 - 100)).collect()
+        };
+        assert_eq!(expected, tree.iter().cloned().collect::<Vec<u32>>());
+        assert_eq!(expected.len(), tree.len());
+        assert_eq!(Ok(()), tree.validate());
+        assert!(tree.get_height() <= 2 * (tree.len() as f64 + 1.0).log2().ceil() as u128);
+    }
+    assert!(empty.is_empty());
+    assert_eq!((Some(&1), Some(&99)), (versions[150].first(), versions[150].last()));
+
+    let snapshot = versions[150].clone();
+    assert!(snapshot.ptr_eq(&versions[150]));
+    assert!(snapshot.insert(1).ptr_eq(&snapshot));
+    assert!(snapshot.delete(&2).ptr_eq(&snapshot));
+    let undo = snapshot.insert(0);
+    assert!(undo.contains(&0) && !snapshot.contains(&0));
+    assert_eq!(snapshot, undo.delete(&0));
+    assert_eq!("{1, 3, 5}", format!("{:?}", [5, 3, 1, 3].into_iter().collect::<PRBT<u32>>()));
+}
+
+#[test]
+fn test_avl_persistent() {
+    let empty = PAVLT::new();
+    let mut versions = vec![empty.clone()];
+    for i in 0..100u32 {
+        let next = versions.last().unwrap().insert((i * 37) % 100);
+        versions.push(next);
+    }
+    for i in (0..100u32).step_by(2) {
+        let next = versions.last().unwrap().delete(&i);
+        versions.push(next);
+    }
+    for (version, tree) in versions.iter().enumerate() {
+        let expected: Vec<u32> = if version <= 100 {
+            let mut keys: Vec<u32> = (0..version as u32).map(|i| (i * 37) % 100).collect();
+            keys.sort();
+            keys
+        } else {
+            (0..100).filter(|i| i % 2 == 1 || *i >= 2 * (version as u32 - 100)).collect()
+        };
+        assert_eq!(expected, tree.iter().cloned().collect::<Vec<u32>>());
+        assert_eq!(expected.len(), tree.len());
+        assert_eq!(Ok(()), tree.validate());
+        assert!(tree.get_height() <= 2 * (tree.len() as f64 + 1.0).log2().ceil() as u128);
+    }
+    assert!(empty.is_empty());
+    assert_eq!((Some(&1), Some(&99)), (versions[150].first(), versions[150].last()));
+
+    let snapshot = versions[150].clone();
+    assert!(snapshot.ptr_eq(&versions[150]));
+    assert!(snapshot.insert(1).ptr_eq(&snapshot));
+    assert!(snapshot.delete(&2).ptr_eq(&snapshot));
+    let undo = snapshot.insert(0);
+    assert!(undo.contains(&0) && !snapshot.contains(&0));
+    assert_eq!(snapshot, undo.delete(&0));
+    assert_eq!("{1, 3, 5}", format!("{:?}", [5, 3, 1, 3].into_iter().collect::<PAVLT<u32>>()));
+}
+
+#[test]
+fn test_rb_sync() {
+    fn shared<S: Send + Sync>(_tree: &S) {}
+    let tree = SyncRBT::new();
+    shared(&tree);
+    std::thread::scope(|scope| {
+        for worker in 0..4u32 {
+            let tree = &tree;
+            scope.spawn(move || {
+                for i in 0..250 {
+                    tree.insert(worker * 1000 + i);
+                }
+                for i in (0..250).step_by(5) {
+                    tree.delete(&(worker * 1000 + i));
+                }
+            });
+        }
+        for _ in 0..2 {
+            let tree = &tree;
+            scope.spawn(move || {
+                for _ in 0..50 {
+                    let snapshot = tree.snapshot();
+                    assert!(snapshot.iter().zip(snapshot.iter().skip(1)).all(|(a, b)| a < b));
+                    assert_eq!(snapshot.len(), snapshot.iter().count());
+                    let _ = tree.contains(&1);
+                }
+            });
+        }
+    });
+    assert_eq!(800, tree.len());
+    assert!(tree.contains(&3001) && !tree.contains(&3005));
+    assert_eq!(Some(2249), tree.search(&2249));
+
+    let before = tree.snapshot();
+    tree.update(|current| (0..10).fold(current.clone(), |version, key| version.delete(&(key * 1000 + 1))));
+    assert_eq!(796, tree.len());
+    assert_eq!(800, before.len());
+    let mut as_search_tree = tree;
+    assert!(SearchTree::insert(&mut as_search_tree, 1));
+    assert!(!SearchTree::insert(&mut as_search_tree, 1));
+    assert_eq!(Some(1), SearchTree::delete(&mut as_search_tree, &1));
+    assert!(!SearchTree::contains(&as_search_tree, &1));
+}
+
+#[test]
+fn test_avl_sync() {
+    fn shared<S: Send + Sync>(_tree: &S) {}
+    let tree = SyncAVLT::new();
+    shared(&tree);
+    std::thread::scope(|scope| {
+        for worker in 0..4u32 {
+            let tree = &tree;
+            scope.spawn(move || {
+                for i in 0..250 {
+                    tree.insert(worker * 1000 + i);
+                }
+                for i in (0..250).step_by(5) {
+                    tree.delete(&(worker * 1000 + i));
+                }
+            });
+        }
+        for _ in 0..2 {
+            let tree = &tree;
+            scope.spawn(move || {
+                for _ in 0..50 {
+                    let snapshot = tree.snapshot();
+                    assert!(snapshot.iter().zip(snapshot.iter().skip(1)).all(|(a, b)| a < b));
+                    assert_eq!(snapshot.len(), snapshot.iter().count());
+                    let _ = tree.contains(&1);
+                }
+            });
+        }
+    });
+    assert_eq!(800, tree.len());
+    assert!(tree.contains(&3001) && !tree.contains(&3005));
+    assert_eq!(Some(2249), tree.search(&2249));
+
+    let before = tree.snapshot();
+    tree.update(|current| (0..10).fold(current.clone(), |version, key| version.delete(&(key * 1000 + 1))));
+    assert_eq!(796, tree.len());
+    assert_eq!(800, before.len());
+    let mut as_search_tree = tree;
+    assert!(SearchTree::insert(&mut as_search_tree, 1));
+    assert!(!SearchTree::insert(&mut as_search_tree, 1));
+    assert_eq!(Some(1), SearchTree::delete(&mut as_search_tree, &1));
+    assert!(!SearchTree::contains(&as_search_tree, &1));
+}
+
+// Key type without `Clone`; trees only ever move it or hand out references.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Ticket(u32);
+
+#[test]
+fn test_rb_borrowed_keys() {
+    let mut names = RBT::<String>::new();
+    for name in ["delta", "alpha", "charlie", "bravo"] {
+        names.insert(name.to_string());
+    }
+    assert!(names.search("charlie").is_some());
+    assert!(names.search("echo").is_none());
+    assert_eq!(2, names.rank("charlie"));
+    assert_eq!(2, names.count_range::<str, _>((Bound::Included("b"), Bound::Excluded("d"))));
+    assert_eq!("alpha", names.floor("b").unwrap().key().as_str());
+    names.delete("alpha");
+    assert_eq!(3, names.len());
+
+    let mut ages = RBTMap::<String, u32>::new();
+    ages.insert("ann".to_string(), 31);
+    ages.insert("bob".to_string(), 27);
+    *ages.get_mut("bob").unwrap().value_mut() += 1;
+    assert_eq!(28, *ages.get("bob").unwrap().value());
+    assert_eq!(Some(31), ages.remove("ann"));
+    assert!(!ages.contains_key("ann"));
+
+    let mut tickets = RBT::new();
+    for id in [5, 3, 8, 1, 4] {
+        tickets.insert(Ticket(id));
+    }
+    tickets.delete(&Ticket(3));
+    assert_eq!(vec![1, 4, 5, 8], tickets.iter().map(|entry| entry.key().0).collect::<Vec<u32>>());
+    assert_eq!(Ticket(4), *tickets.successor(&Ticket(1)).unwrap().key());
+}
+
+#[test]
+fn test_avl_borrowed_keys() {
+    let mut names = AVLT::<String>::new();
+    for name in ["delta", "alpha", "charlie", "bravo"] {
+        names.insert(name.to_string());
+    }
+    assert!(names.search("charlie").is_some());
+    assert!(names.search("echo").is_none());
+    assert_eq!(2, names.rank("charlie"));
+    assert_eq!(2, names.count_range::<str, _>((Bound::Included("b"), Bound::Excluded("d"))));
+    assert_eq!("alpha", names.floor("b").unwrap().key().as_str());
+    names.delete("alpha");
+    assert_eq!(3, names.len());
+
+    let mut ages = AVLTMap::<String, u32>::new();
+    ages.insert("ann".to_string(), 31);
+    ages.insert("bob".to_string(), 27);
+    *ages.get_mut("bob").unwrap().value_mut() += 1;
+    assert_eq!(28, *ages.get("bob").unwrap().value());
+    assert_eq!(Some(31), ages.remove("ann"));
+    assert!(!ages.contains_key("ann"));
+
+    let mut tickets = AVLT::new();
+    for id in [5, 3, 8, 1, 4] {
+        tickets.insert(Ticket(id));
+    }
+    tickets.delete(&Ticket(3));
+    assert_eq!(vec![1, 4, 5, 8], tickets.iter().map(|entry| entry.key().0).collect::<Vec<u32>>());
+    assert_eq!(Ticket(4), *tickets.successor(&Ticket(1)).unwrap().key());
+}
+
+#[test]
+fn test_rb_persistent_borrowed_keys() {
+    let names: PRBT<String> = ["delta", "alpha", "charlie"].into_iter().map(String::from).collect();
+    assert!(names.contains("alpha") && !names.contains("bravo"));
+    assert_eq!(Some("charlie"), names.get("charlie").map(String::as_str));
+    let fewer = names.delete("alpha");
+    assert!(names.delete("bravo").ptr_eq(&names));
+    assert_eq!(vec!["charlie", "delta"], fewer.iter().map(String::as_str).collect::<Vec<&str>>());
+    assert!(names.contains("alpha"));
+}
+
+#[test]
+fn test_avl_persistent_borrowed_keys() {
+    let names: PAVLT<String> = ["delta", "alpha", "charlie"].into_iter().map(String::from).collect();
+    assert!(names.contains("alpha") && !names.contains("bravo"));
+    assert_eq!(Some("charlie"), names.get("charlie").map(String::as_str));
+    let fewer = names.delete("alpha");
+    assert!(names.delete("bravo").ptr_eq(&names));
+    assert_eq!(vec!["charlie", "delta"], fewer.iter().map(String::as_str).collect::<Vec<&str>>());
+    assert!(names.contains("alpha"));
+}
+
+#[test]
+fn test_rb_persistent_comparator() {
+    // Keys that cannot be cloned, kept in descending order.
+    #[derive(Debug, PartialEq)]
+    struct Ticket(u32);
+    let mut tickets = PRBT::with_comparator(|a: &Ticket, b: &Ticket| b.0.cmp(&a.0));
+    for id in [5, 1, 8, 4, 3] {
+        tickets = tickets.insert(Ticket(id));
+    }
+    let fewer = tickets.delete(&Ticket(3));
+    assert_eq!(vec![8, 5, 4, 1], fewer.iter().map(|ticket| ticket.0).collect::<Vec<u32>>());
+    assert_eq!((Some(&Ticket(8)), Some(&Ticket(1))), (fewer.first(), fewer.last()));
+    let kept = tickets.get_shared(&Ticket(3)).unwrap();
+    drop(tickets);
+    assert_eq!(Ticket(3), *kept);
+    assert!(fewer.insert(Ticket(3)) != fewer);
+}
+
+#[test]
+fn test_avl_persistent_comparator() {
+    #[derive(Debug, PartialEq)]
+    struct Ticket(u32);
+    let mut tickets = PAVLT::with_comparator(|a: &Ticket, b: &Ticket| b.0.cmp(&a.0));
+    for id in [5, 1, 8, 4, 3] {
+        tickets = tickets.insert(Ticket(id));
+    }
+    let fewer = tickets.delete(&Ticket(3));
+    assert_eq!(vec![8, 5, 4, 1], fewer.iter().map(|ticket| ticket.0).collect::<Vec<u32>>());
+    assert_eq!((Some(&Ticket(8)), Some(&Ticket(1))), (fewer.first(), fewer.last()));
+    let kept = tickets.get_shared(&Ticket(3)).unwrap();
+    drop(tickets);
+    assert_eq!(Ticket(3), *kept);
+    assert!(fewer.insert(Ticket(3)) != fewer);
+}
+
+#[test]
+fn test_rb_sync_borrowed_keys() {
+    let names = SyncRBT::new();
+    for name in ["delta", "alpha", "charlie"] {
+        names.insert(name.to_string());
+    }
+    assert!(names.contains("alpha") && !names.contains("bravo"));
+    assert_eq!(Some("charlie".to_string()), names.search("charlie"));
+    assert_eq!(Some("alpha".to_string()), names.delete("alpha"));
+    assert_eq!(None, names.delete("alpha"));
+    assert_eq!(2, names.len());
+}
+
+#[test]
+fn test_avl_sync_borrowed_keys() {
+    let names = SyncAVLT::new();
+    for name in ["delta", "alpha", "charlie"] {
+        names.insert(name.to_string());
+    }
+    assert!(names.contains("alpha") && !names.contains("bravo"));
+    assert_eq!(Some("charlie".to_string()), names.search("charlie"));
+    assert_eq!(Some("alpha".to_string()), names.delete("alpha"));
+    assert_eq!(None, names.delete("alpha"));
+    assert_eq!(2, names.len());
+}
+
+#[test]
+fn test_rb_set_results() {
+    let mut tree = RBT::new();
+    assert!(tree.insert(20));
+    assert!(tree.insert(10));
+    assert!(!tree.insert(20));
+    assert_eq!(Some(10), tree.delete(&10));
+    assert_eq!(None, tree.delete(&10));
+    assert_eq!(None, tree.take(&10));
+    for key in [40, 30, 50] {
+        tree.insert(key);
+    }
+    assert_eq!(Some(30), tree.take(&30));
+    assert_eq!(Some(20), tree.pop_first());
+    assert_eq!(Some(50), tree.pop_last());
+    assert_eq!(vec![40], tree.iter().map(|entry| *entry.key()).collect::<Vec<u32>>());
+
+    // keys comparing equal are swapped by `replace` but left alone by `insert`
+    let mut words = RBT::with_comparator(|a: &String, b: &String| a.to_lowercase().cmp(&b.to_lowercase()));
+    assert_eq!(None, words.replace("Tree".to_string()));
+    assert!(!words.insert("TREE".to_string()));
+    assert_eq!(Some("Tree".to_string()), words.replace("tree".to_string()));
+    assert_eq!("tree", words.first().unwrap().key().as_str());
+    assert_eq!(Some("tree".to_string()), words.pop_last());
+    assert_eq!(None, words.pop_first());
+    assert_eq!(None, words.pop_last());
+
+    let mut as_search_tree = RBT::<u32>::new();
+    assert!(SearchTree::insert(&mut as_search_tree, 7));
+    assert!(!SearchTree::insert(&mut as_search_tree, 7));
+    assert_eq!(Some(7), SearchTree::delete(&mut as_search_tree, &7));
+    assert_eq!(None, SearchTree::delete(&mut as_search_tree, &7));
+}
+
+#[test]
+fn test_avl_set_results() {
+    let mut tree = AVLT::new();
+    assert!(tree.insert(20));
+    assert!(tree.insert(10));
+    assert!(!tree.insert(20));
+    assert_eq!(Some(10), tree.delete(&10));
+    assert_eq!(None, tree.delete(&10));
+    assert_eq!(None, tree.take(&10));
+    for key in [40, 30, 50] {
+        tree.insert(key);
+    }
+    assert_eq!(Some(30), tree.take(&30));
+    assert_eq!(Some(20), tree.pop_first());
+    assert_eq!(Some(50), tree.pop_last());
+    assert_eq!(vec![40], tree.iter().map(|entry| *entry.key()).collect::<Vec<u32>>());
+
+    // keys comparing equal are swapped by `replace` but left alone by `insert`
+    let mut words = AVLT::with_comparator(|a: &String, b: &String| a.to_lowercase().cmp(&b.to_lowercase()));
+    assert_eq!(None, words.replace("Tree".to_string()));
+    assert!(!words.insert("TREE".to_string()));
+    assert_eq!(Some("Tree".to_string()), words.replace("tree".to_string()));
+    assert_eq!("tree", words.first().unwrap().key().as_str());
+    assert_eq!(Some("tree".to_string()), words.pop_last());
+    assert_eq!(None, words.pop_first());
+    assert_eq!(None, words.pop_last());
+
+    let mut as_search_tree = AVLT::<u32>::new();
+    assert!(SearchTree::insert(&mut as_search_tree, 7));
+    assert!(!SearchTree::insert(&mut as_search_tree, 7));
+    assert_eq!(Some(7), SearchTree::delete(&mut as_search_tree, &7));
+    assert_eq!(None, SearchTree::delete(&mut as_search_tree, &7));
+}
+
+#[test]
+fn test_rb_validate() {
+    let mut rng = rand::thread_rng();
+    let mut tree = RBT::new();
+    assert_eq!(Ok(()), tree.validate());
+    for _ in 0..2000 {
+        let key: u32 = rng.gen_range(0..300);
+        if rng.gen_bool(0.6) {
+            tree.insert(key);
+        } else {
+            tree.delete(&key);
+        }
+        assert_eq!(Ok(()), tree.validate());
+    }
+
+    let mut tree: RBT<u32> = (1..=4).collect();
+    tree.nodes.set_root_color(&tree.nodes.find_node(&tree.root, &3), NodeColor::Red);
+    assert_eq!(Err(TreeViolation::RedChildOfRed { key: "4".to_string() }), tree.validate());
+    tree.nodes.set_root_color(&tree.nodes.find_node(&tree.root, &3), NodeColor::Black);
+    tree.nodes.set_root_color(&tree.nodes.find_node(&tree.root, &1), NodeColor::Red);
+    let violation = tree.validate().unwrap_err();
+    assert_eq!(TreeViolation::BlackHeightMismatch { key: "2".to_string(), left: 0, right: 1 }, violation);
+    assert_eq!("2", violation.key());
+    tree.nodes.set_root_color(&tree.root, NodeColor::Red);
+    assert_eq!(Err(TreeViolation::RedRoot { key: "2".to_string() }), tree.validate());
+
+    let mut tree: RBT<u32> = (1..=3).collect();
+    tree.nodes.replace_root_key(&tree.nodes.find_node(&tree.root, &1), 5);
+    assert_eq!(Err(TreeViolation::OutOfOrder { key: "5".to_string() }), tree.validate());
+    assert_eq!("key 5 is out of order", tree.validate().unwrap_err().to_string());
+}
+
+#[test]
+fn test_avl_validate() {
+    let mut rng = rand::thread_rng();
+    let mut tree = AVLT::new();
+    assert_eq!(Ok(()), tree.validate());
+    for _ in 0..2000 {
+        let key: u32 = rng.gen_range(0..300);
+        if rng.gen_bool(0.6) {
+            tree.insert(key);
+        } else {
+            tree.delete(&key);
+        }
+        assert_eq!(Ok(()), tree.validate());
+    }
+
+    let mut tree: AVLT<u32> = (1..=3).collect();
+    tree.nodes.set_height(&tree.root, 5);
+    assert_eq!(Err(TreeViolation::WrongHeight { key: "2".to_string(), stored: 5, actual: 2 }), tree.validate());
+    tree.nodes.set_height(&tree.root, 2);
+    tree.nodes.replace_root_key(&tree.nodes.find_node(&tree.root, &3), 0);
+    assert_eq!(Err(TreeViolation::OutOfOrder { key: "0".to_string() }), tree.validate());
+
+    // hang a whole chain below the right child without rebalancing
+    let mut tree: AVLT<u32> = (1..=3).collect();
+    let right = tree.nodes.find_node(&tree.root, &3);
+    let chain = tree.nodes.new_entry(4, ());
+    let tail = tree.nodes.new_entry(5, ());
+    tree.nodes.set_child(&chain, tail, avl_tree::Direction::Right);
+    tree.nodes.set_parent(&tail, &chain);
+    tree.nodes.set_child(&right, chain, avl_tree::Direction::Right);
+    assert_eq!(Err(TreeViolation::BrokenParentLink { key: "4".to_string() }), tree.validate());
+    tree.nodes.set_parent(&chain, &right);
+    tree.nodes.update_height(&tail);
+    tree.nodes.update_height(&chain);
+    tree.nodes.update_height(&right);
+    tree.nodes.update_height(&tree.root);
+    tree.nodes.update_size_to_root(&tail);
+    assert_eq!(Err(TreeViolation::Unbalanced { key: "3".to_string(), balance_factor: 2 }), tree.validate());
+}
+
+/*
+    Differential testing: the red-black tree, the AVL tree and a `BTreeSet` replay the same seeded
+    operations and have to agree after every step. A failing run is shrunk to a short list of
+    operations that still fails, which is printed along with the seed.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Insert(u32),
+    Delete(u32),
+    Search(u32),
+}
+
+fn random_ops(seed: u64, len: usize, key_range: u32) -> Vec<Op> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..len).map(|_| {
+        let key = rng.gen_range(0..key_range);
+        match rng.gen_range(0..10) {
+            0..=4 => Op::Insert(key),
+            5..=7 => Op::Delete(key),
+            _ => Op::Search(key),
+        }
+    }).collect()
+}
+
+/*
+    Replays ops on the mutable and persistent trees and the oracle and describes the first step
+    where they disagree. The persistent trees keep every version, which are all checked again at the
+    end, so an update that changed a node an older version still shares shows up as well.
+*/
+fn run_differential(ops: &[Op]) -> Result<(), String> {
+    let mut rbt = RBT::new();
+    let mut avlt = AVLT::new();
+    let mut oracle = BTreeSet::new();
+    let mut versions = vec![(PRBT::new(), PAVLT::new(), oracle.clone())];
+    for (step, op) in ops.iter().enumerate() {
+        let (rb_result, avl_result, expected) = match *op {
+            Op::Insert(key) => (Some(rbt.insert(key)), Some(avlt.insert(key)), Some(oracle.insert(key))),
+            Op::Delete(key) => (Some(rbt.delete(&key).is_some()), Some(avlt.delete(&key).is_some()), Some(oracle.remove(&key))),
+            Op::Search(key) => (Some(rbt.search(&key).is_some()), Some(avlt.search(&key).is_some()), Some(oracle.contains(&key))),
+        };
+        if rb_result != expected || avl_result != expected {
+            return Err(format!("step {} {:?}: red-black {:?}, AVL {:?}, BTreeSet {:?}", step, op, rb_result, avl_result, expected));
+        }
+        if rbt.len() != oracle.len() || avlt.len() != oracle.len() {
+            return Err(format!("step {} {:?}: sizes {} / {}, expected {}", step, op, rbt.len(), avlt.len(), oracle.len()));
+        }
+        if !rbt.iter().map(|entry| *entry.key()).eq(oracle.iter().copied()) {
+            return Err(format!("step {} {:?}: red-black keys {:?}, expected {:?}", step, op, rbt, oracle));
+        }
+        if !avlt.iter().map(|entry| *entry.key()).eq(oracle.iter().copied()) {
+            return Err(format!("step {} {:?}: AVL keys {:?}, expected {:?}", step, op, avlt, oracle));
+        }
+        if let Err(violation) = rbt.validate().and(avlt.validate()) {
+            return Err(format!("step {} {:?}: {}", step, op, violation));
+        }
+        let (prbt, pavlt, _) = versions.last().unwrap();
+        let (prbt, pavlt) = match *op {
+            Op::Insert(key) => (prbt.insert(key), pavlt.insert(key)),
+            Op::Delete(key) => (prbt.delete(&key), pavlt.delete(&key)),
+            Op::Search(_) => (prbt.clone(), pavlt.clone()),
+        };
+        versions.push((prbt, pavlt, oracle.clone()));
+    }
+    for (version, (prbt, pavlt, expected)) in versions.iter().enumerate() {
+        if !prbt.iter().eq(expected.iter()) || !pavlt.iter().eq(expected.iter()) {
+            return Err(format!("version {}: persistent keys {:?} / {:?}, expected {:?}", version, prbt, pavlt, expected));
+        }
+        if let Err(violation) = prbt.validate().and(pavlt.validate()) {
+            return Err(format!("version {}: {}", version, violation));
+        }
+    }
+    Ok(())
+}
+
+/*
+    Drops chunks of operations, halving the chunk size down to single operations, as long as the
+    rest still fails, then halves each remaining key (everywhere it occurs) while that still fails.
+*/
+fn shrink_ops<F: Fn(&[Op]) -> bool>(mut ops: Vec<Op>, fails: F) -> Vec<Op> {
+    let mut chunk = ops.len().div_ceil(2).max(1);
+    loop {
+        let mut start = 0;
+        while start < ops.len() {
+            let mut candidate = ops.clone();
+            candidate.drain(start..(start + chunk).min(ops.len()));
+            if fails(&candidate) {
+                ops = candidate;
+            } else {
+                start += chunk;
+            }
+        }
+        if chunk == 1 {
+            break;
+        }
+        chunk /= 2;
+    }
+    let key_of = |op: &Op| match *op {
+        Op::Insert(key) | Op::Delete(key) | Op::Search(key) => key,
+    };
+    let with_key = |op: &Op, key: u32| match *op {
+        Op::Insert(_) => Op::Insert(key),
+        Op::Delete(_) => Op::Delete(key),
+        Op::Search(_) => Op::Search(key),
+    };
+    let mut keys: Vec<u32> = ops.iter().map(key_of).collect();
+    keys.sort_unstable();
+    keys.dedup();
+    for mut key in keys {
+        while key > 0 {
+            let candidate: Vec<Op> = ops.iter().map(|op| if key_of(op) == key {with_key(op, key / 2)} else {*op}).collect();
+            if !fails(&candidate) {
+                break;
+            }
+            ops = candidate;
+            key /= 2;
+        }
+    }
+    ops
+}
+
+#[test]
+fn test_differential_against_btreeset() {
+    // small key ranges make deletes and repeated inserts hit present keys often
+    for seed in 0..64 {
+        let key_range = [8, 64, 1000][seed as usize % 3];
+        let ops = random_ops(seed, 500, key_range);
+        if let Err(failure) = run_differential(&ops) {
+            let minimal = shrink_ops(ops, |ops| run_differential(ops).is_err());
+            panic!("seed {}: {}\nminimal reproducer: {:?}\n{}", seed, failure, minimal, run_differential(&minimal).unwrap_err());
+        }
+    }
+}
+
+#[test]
+fn test_shrink_ops() {
+    // pretend inserting a key that was deleted before breaks the trees
+    let fails = |ops: &[Op]| ops.iter().enumerate().any(|(i, op)| match op {
+        Op::Insert(key) => ops[..i].contains(&Op::Delete(*key)),
+        _ => false,
+    });
+    let ops = random_ops(7, 300, 20);
+    assert!(fails(&ops));
+    let minimal = shrink_ops(ops, fails);
+    assert_eq!(vec![Op::Delete(0), Op::Insert(0)], minimal);
+}
+
+/*
+    Exhaustive small-model check. A tree's future depends only on its current shape, so instead of
+    replaying all n! insertion orders (and all n! deletion orders after each of them) one by one,
+    the search walks the graph of distinct shapes: first inserting every missing key into every
+    shape reached so far, then deleting every present key from every full tree and what follows.
+    Each insertion order and each deletion order is a path through this graph, so every one of them
+    is covered while each shape is only checked once.
+*/
+trait ModelTree: SearchTree<u32> + Clone + Default {
+
+    // Keys in preorder with NIL markers; red-black trees also record which nodes are red.
+    fn shape(&self) -> Vec<Option<(u32, bool)>>;
+
+    fn keys(&self) -> Vec<u32>;
+
+    fn check(&self) -> Result<(), TreeViolation>;
+
+    // The rebalancing case `op` starts with, read off the shape before it runs.
+    fn fixup_case(&self, op: Op) -> Option<&'static str>;
+}
+
+fn rb_shape(nodes: &RBArena<u32>, root: &red_black_tree::RBTChild, shape: &mut Vec<Option<(u32, bool)>>) {
+    if nodes.get_root_nil(root) {
+        shape.push(None);
+        return;
+    }
+    shape.push(Some((*nodes.get_root_key_ref(root), nodes.get_root_color(root) == NodeColor::Red)));
+    rb_shape(nodes, &nodes.get_left(root), shape);
+    rb_shape(nodes, &nodes.get_right(root), shape);
+}
+
+fn avl_shape(nodes: &AVLArena<u32>, root: &avl_tree::AVLChild, shape: &mut Vec<Option<(u32, bool)>>) {
+    if nodes.get_root_nil(root) {
+        shape.push(None);
+        return;
+    }
+    shape.push(Some((*nodes.get_root_key_ref(root), false)));
+    avl_shape(nodes, &nodes.get_left(root), shape);
+    avl_shape(nodes, &nodes.get_right(root), shape);
+}
+
+// A tree rebuilt from `ModelTree::shape`.
+struct ShapeNode {
+    key: u32,
+    red: bool,
+    left: Shape,
+    right: Shape,
+}
+
+type Shape = Option<Box<ShapeNode>>;
+
+fn parse_shape(preorder: &mut impl Iterator<Item = Option<(u32, bool)>>) -> Shape {
+    let (key, red) = preorder.next().flatten()?;
+    let left = parse_shape(preorder);
+    let right = parse_shape(preorder);
+    Some(Box::new(ShapeNode { key, red, left, right }))
+}
+
+fn shape_height(root: &Shape) -> i64 {
+    root.as_ref().map_or(0, |node| 1 + shape_height(&node.left).max(shape_height(&node.right)))
+}
+
+fn shape_balance(node: &ShapeNode) -> i64 {
+    shape_height(&node.right) - shape_height(&node.left)
+}
+
+fn shape_is_red(root: &Shape) -> bool {
+    root.as_ref().is_some_and(|node| node.red)
+}
+
+/*
+    The nodes above the slot an update changes, each with whether the slot lies to its left, and
+    for a delete the node that actually gets unlinked: the deleted node itself, or its successor
+    when it has two children. An insert changes the empty slot the new key goes into.
+*/
+fn update_path(root: &Shape, op: Op) -> (Vec<(&ShapeNode, bool)>, Option<&ShapeNode>) {
+    let key = match op {
+        Op::Insert(key) | Op::Delete(key) | Op::Search(key) => key,
+    };
+    let mut path = Vec::new();
+    let mut slot = root;
+    while let Some(node) = slot.as_deref().filter(|node| node.key != key) {
+        path.push((node, key < node.key));
+        slot = if key < node.key {&node.left} else {&node.right};
+    }
+    let mut unlinked = slot.as_deref();
+    if let Some(node) = unlinked.filter(|node| node.left.is_some() && node.right.is_some()) {
+        path.push((node, false));
+        let mut successor = node.right.as_deref().unwrap();
+        while let Some(left) = successor.left.as_deref() {
+            path.push((successor, true));
+            successor = left;
+        }
+        unlinked = Some(successor);
+    }
+    (path, unlinked)
+}
+
+fn rb_fixup_case(root: &Shape, op: Op) -> Option<&'static str> {
+    let (path, unlinked) = update_path(root, op);
+    let &(parent, slot_is_left) = path.last()?;
+    match op {
+        Op::Insert(_) if parent.red => {
+            let &(grandparent, parent_is_left) = path.iter().rev().nth(1)?;
+            let uncle = if parent_is_left {&grandparent.right} else {&grandparent.left};
+            if shape_is_red(uncle) {
+                Some("insert: red uncle")
+            } else if slot_is_left != parent_is_left {
+                Some("insert: inner child")
+            } else {
+                Some("insert: outer child")
+            }
+        },
+        // Only unlinking a black leaf loses a black level; a single child is always red and just turns black.
+        Op::Delete(_) => {
+            let removed = unlinked?;
+            if removed.red || removed.left.is_some() || removed.right.is_some() {
+                return None;
+            }
+            let sibling = if slot_is_left {&parent.right} else {&parent.left};
+            let sibling = sibling.as_deref()?;
+            let (near, far) = if slot_is_left {(&sibling.left, &sibling.right)} else {(&sibling.right, &sibling.left)};
+            if sibling.red {
+                Some("delete: case 1")
+            } else if !shape_is_red(near) && !shape_is_red(far) {
+                Some("delete: case 2")
+            } else if !shape_is_red(far) {
+                Some("delete: case 3")
+            } else {
+                Some("delete: case 4")
+            }
+        },
+        _ => None,
+    }
+}
+
+// Walks up from the changed slot with the new heights, like `delete_fixup`, to the first rotation.
+fn avl_fixup_case(root: &Shape, op: Op) -> Option<&'static str> {
+    let (path, unlinked) = update_path(root, op);
+    let (mut height, mut slot_balance) = match op {
+        Op::Insert(_) => (1, 0),
+        _ => {
+            let removed = unlinked?;
+            let child = removed.left.as_ref().or(removed.right.as_ref());
+            (child.map_or(0, |child| shape_height(&child.left).max(shape_height(&child.right)) + 1),
+             child.map_or(0, |child| shape_balance(child)))
+        },
+    };
+    for &(node, slot_is_left) in path.iter().rev() {
+        let other = if slot_is_left {&node.right} else {&node.left};
+        let (left_height, right_height) = if slot_is_left {(height, shape_height(other))} else {(shape_height(other), height)};
+        let balance = right_height - left_height;
+        if balance.abs() == 2 {
+            // the taller child decides between a single and a double rotation
+            let taller_balance = if (balance < 0) == slot_is_left {slot_balance} else {other.as_deref().map_or(0, shape_balance)};
+            return Some(match (balance < 0, taller_balance) {
+                (true, b) if b <= 0 => "left-left",
+                (true, _) => "left-right",
+                (false, b) if b >= 0 => "right-right",
+                (false, _) => "right-left",
+            });
+        }
+        height = 1 + left_height.max(right_height);
+        slot_balance = balance;
+    }
+    None
+}
+
+impl ModelTree for RBT<u32> {
+
+    fn shape(&self) -> Vec<Option<(u32, bool)>> {
+        let mut shape = Vec::new();
+        rb_shape(&self.nodes, &self.root, &mut shape);
+        shape
+    }
+
+    fn keys(&self) -> Vec<u32> {
+        self.iter().map(|entry| *entry.key()).collect()
+    }
+
+    fn check(&self) -> Result<(), TreeViolation> {
+        self.validate()
+    }
+
+    fn fixup_case(&self, op: Op) -> Option<&'static str> {
+        rb_fixup_case(&parse_shape(&mut self.shape().into_iter()), op)
+    }
+}
+
+impl ModelTree for AVLT<u32> {
+
+    fn shape(&self) -> Vec<Option<(u32, bool)>> {
+        let mut shape = Vec::new();
+        avl_shape(&self.nodes, &self.root, &mut shape);
+        shape
+    }
+
+    fn keys(&self) -> Vec<u32> {
+        self.iter().map(|entry| *entry.key()).collect()
+    }
+
+    fn check(&self) -> Result<(), TreeViolation> {
+        self.validate()
+    }
+
+    fn fixup_case(&self, op: Op) -> Option<&'static str> {
+        avl_fixup_case(&parse_shape(&mut self.shape().into_iter()), op)
+    }
+}
+
+/*
+    Runs every insertion order of the keys 0..n and every deletion order after it, checking the
+    return values, the contents and the invariants after each step, and collects the fixup cases the
+    steps start with into `cases`. Returns the number of distinct shapes seen, or the first failing
+    sequence.
+*/
+fn check_all_sequences<S: ModelTree>(n: u32, cases: &mut BTreeSet<&'static str>) -> Result<usize, String> {
+    let mut seen = std::collections::HashSet::new();
+    let mut full = Vec::new();
+    let mut stack = vec![(S::new(), Vec::new())];
+    while let Some((tree, ops)) = stack.pop() {
+        let present: BTreeSet<u32> = tree.keys().into_iter().collect();
+        if present.len() == n as usize {
+            full.push((tree.clone(), ops.clone()));
+        }
+        for key in (0..n).filter(|key| !present.contains(key)) {
+            let mut next = tree.clone();
+            let mut next_ops: Vec<Op> = ops.clone();
+            next_ops.push(Op::Insert(key));
+            cases.extend(tree.fixup_case(Op::Insert(key)));
+            if !next.insert(key) {
+                return Err(format!("{:?}: insert reported a present key", next_ops));
+            }
+            check_step(&next, &present, Op::Insert(key), &next_ops)?;
+            if seen.insert((true, next.shape())) {
+                stack.push((next, next_ops));
+            }
+        }
+    }
+    let mut stack = full;
+    while let Some((tree, ops)) = stack.pop() {
+        let present: BTreeSet<u32> = tree.keys().into_iter().collect();
+        for &key in &present {
+            let mut next = tree.clone();
+            let mut next_ops = ops.clone();
+            next_ops.push(Op::Delete(key));
+            cases.extend(tree.fixup_case(Op::Delete(key)));
+            if next.delete(&key) != Some(key) {
+                return Err(format!("{:?}: delete did not return the key", next_ops));
+            }
+            check_step(&next, &present, Op::Delete(key), &next_ops)?;
+            if seen.insert((false, next.shape())) {
+                stack.push((next, next_ops));
+            }
+        }
+    }
+    Ok(seen.len())
+}
+
+fn check_step<S: ModelTree>(tree: &S, before: &BTreeSet<u32>, op: Op, ops: &[Op]) -> Result<(), String> {
+    let mut expected = before.clone();
+    match op {
+        Op::Insert(key) => expected.insert(key),
+        Op::Delete(key) => expected.remove(&key),
+        Op::Search(_) => false,
+    };
+    if !tree.keys().into_iter().eq(expected.iter().copied()) {
+        return Err(format!("{:?}: keys {:?}, expected {:?}", ops, tree.keys(), expected));
+    }
+    tree.check().map_err(|violation| format!("{:?}: {}", ops, violation))
+}
+
+#[test]
+fn test_rb_exhaustive_small() {
+    let mut tree = RBT::new();
+    tree.insert(0);
+    tree.insert(2);
+    assert_eq!(Some("insert: inner child"), tree.fixup_case(Op::Insert(1)));
+    assert_eq!(Some("insert: outer child"), tree.fixup_case(Op::Insert(3)));
+    let mut cases = BTreeSet::new();
+    for n in 1..=8 {
+        if let Err(failure) = check_all_sequences::<RBT<u32>>(n, &mut cases) {
+            panic!("{} keys, first failing sequence {}", n, failure);
+        }
+    }
+    // every rebalancing case must have come up somewhere in the sweep
+    let expected = BTreeSet::from([
+        "insert: red uncle", "insert: inner child", "insert: outer child",
+        "delete: case 1", "delete: case 2", "delete: case 3", "delete: case 4",
+    ]);
+    assert_eq!(expected, cases);
+}
+
+#[test]
+fn test_avl_exhaustive_small() {
+    let mut tree = AVLT::new();
+    tree.insert(2);
+    tree.insert(0);
+    assert_eq!(Some("left-right"), tree.fixup_case(Op::Insert(1)));
+    assert_eq!(None, tree.fixup_case(Op::Insert(3)));
+    let mut cases = BTreeSet::new();
+    for n in 1..=8 {
+        if let Err(failure) = check_all_sequences::<AVLT<u32>>(n, &mut cases) {
+            panic!("{} keys, first failing sequence {}", n, failure);
+        }
+    }
+    // every rebalancing case must have come up somewhere in the sweep
+    assert_eq!(BTreeSet::from(["left-left", "left-right", "right-right", "right-left"]), cases);
+}
+
+#[test]
+fn test_rb_tree_errors() {
+    // node-level calls on an empty handle
+    let mut nodes = RBArena::<u32>::new();
+    let empty: red_black_tree::RBTChild = None;
+    assert_eq!(Err(TreeError::EmptyNode), nodes.try_get_root_key(&empty));
+    assert!(nodes.try_get_root_key_ref(&empty).is_err());
+    assert!(nodes.is_node_equal(&empty, &None));
+    let child = nodes.new_node(1);
+    assert_eq!(Err(TreeError::EmptyNode), nodes.try_set_child(&empty, child, red_black_tree::Direction::Left));
+    nodes.set_child(&empty, child, red_black_tree::Direction::Left);
+    nodes.set_child_nil(&empty, red_black_tree::Direction::Right);
+    assert!(nodes.get_root_nil(&nodes.get_minimum(&empty)));
+    assert!(nodes.get_root_nil(&nodes.get_maximum(&empty)));
+    assert!(nodes.left_rotate_at(None, &empty).is_none());
+    assert!(nodes.right_rotate_at(None, &empty).is_none());
+    assert_eq!(Err(TreeError::EmptyNode), nodes.try_left_rotate_at(None, &empty).map(|_| ()));
+    assert_eq!(Err(TreeError::EmptyNode), nodes.try_right_rotate_at(None, &empty).map(|_| ()));
+    // rotations that cannot happen
+    let mut tree: RBT<u32> = (1..=3).collect();
+    let nodes = &mut tree.nodes;
+    let root = nodes.left_rotate(tree.root, &9);
+    assert_eq!(2, nodes.get_root_key(&root));
+    assert_eq!(Err(TreeError::KeyNotFound), nodes.try_left_rotate(root, &9).map(|_| ()));
+    assert_eq!(Err(TreeError::KeyNotFound), nodes.try_right_rotate(root, &9).map(|_| ()));
+    assert_eq!(Err(TreeError::MissingChild), nodes.try_left_rotate(root, &3).map(|_| ()));
+    assert_eq!(Err(TreeError::MissingChild), nodes.try_right_rotate(root, &1).map(|_| ()));
+    let root = nodes.try_left_rotate(root, &2).unwrap();
+    assert_eq!(Ok(3), nodes.try_get_root_key(&root));
+    assert_eq!(Ok(2), nodes.try_get_root_key(&nodes.get_left(&root)));
+
+    // tree-level Result versions
+    let mut tree: RBT<u32> = (1..=3).collect();
+    assert_eq!(Ok(()), tree.try_insert(4));
+    assert_eq!(Err(TreeError::DuplicateKey), tree.try_insert(4));
+    assert_eq!(Ok(4), tree.try_delete(&4));
+    assert_eq!(Err(TreeError::KeyNotFound), tree.try_delete(&4));
+    assert_eq!(Ok(1), tree.try_remove_at(0));
+    let error = tree.try_remove_at(2).unwrap_err();
+    assert_eq!(TreeError::IndexOutOfBounds { index: 2, len: 2 }, error);
+    assert_eq!("index 2 is out of bounds for 2 keys", error.to_string());
+    assert_eq!(Ok(()), tree.validate());
+}
+
+#[test]
+fn test_avl_tree_errors() {
+    // node-level calls on an empty handle
+    let mut nodes = AVLArena::<u32>::new();
+    let empty: avl_tree::AVLChild = None;
+    assert_eq!(Err(TreeError::EmptyNode), nodes.try_get_root_key(&empty));
+    assert!(nodes.try_get_root_key_ref(&empty).is_err());
+    assert!(nodes.is_node_equal(&empty, &None));
+    let child = nodes.new_node(1);
+    assert_eq!(Err(TreeError::EmptyNode), nodes.try_set_child(&empty, child, avl_tree::Direction::Left));
+    nodes.set_child(&empty, child, avl_tree::Direction::Left);
+    nodes.set_child_nil(&empty, avl_tree::Direction::Right);
+    assert!(nodes.get_root_nil(&nodes.get_minimum(&empty)));
+    assert!(nodes.get_root_nil(&nodes.get_maximum(&empty)));
+    assert!(nodes.left_rotate_at(None, &empty).is_none());
+    assert!(nodes.right_rotate_at(None, &empty).is_none());
+    assert_eq!(Err(TreeError::EmptyNode), nodes.try_left_rotate_at(None, &empty).map(|_| ()));
+    assert_eq!(Err(TreeError::EmptyNode), nodes.try_right_rotate_at(None, &empty).map(|_| ()));
+    assert_eq!(0, nodes.get_height(&empty));
+    nodes.set_root_key(&empty, 1);
+    // rotations that cannot happen
+    let mut tree: AVLT<u32> = (1..=3).collect();
+    let nodes = &mut tree.nodes;
+    let root = nodes.left_rotate(tree.root, &9);
+    assert_eq!(2, nodes.get_root_key(&root));
+    assert_eq!(Err(TreeError::KeyNotFound), nodes.try_left_rotate(root, &9).map(|_| ()));
+    assert_eq!(Err(TreeError::KeyNotFound), nodes.try_right_rotate(root, &9).map(|_| ()));
+    assert_eq!(Err(TreeError::MissingChild), nodes.try_left_rotate(root, &3).map(|_| ()));
+    assert_eq!(Err(TreeError::MissingChild), nodes.try_right_rotate(root, &1).map(|_| ()));
+    let root = nodes.try_left_rotate(root, &2).unwrap();
+    assert_eq!(Ok(3), nodes.try_get_root_key(&root));
+    assert_eq!(Ok(2), nodes.try_get_root_key(&nodes.get_left(&root)));
+
+    // tree-level Result versions
+    let mut tree: AVLT<u32> = (1..=3).collect();
+    assert_eq!(Ok(()), tree.try_insert(4));
+    assert_eq!(Err(TreeError::DuplicateKey), tree.try_insert(4));
+    assert_eq!(Ok(4), tree.try_delete(&4));
+    assert_eq!(Err(TreeError::KeyNotFound), tree.try_delete(&4));
+    assert_eq!(Ok(1), tree.try_remove_at(0));
+    let error = tree.try_remove_at(2).unwrap_err();
+    assert_eq!(TreeError::IndexOutOfBounds { index: 2, len: 2 }, error);
+    assert_eq!("index 2 is out of bounds for 2 keys", error.to_string());
+    assert_eq!(Ok(()), tree.validate());
+}
+
+fn rb_view_keys(node: Option<tree_type::RBNodeView<'_, u32>>, keys: &mut Vec<u32>) -> usize {
+    // walks the views in order and returns the size of the subtree seen
+    match node {
+        Some(node) => {
+            for child in [node.left(), node.right()].into_iter().flatten() {
+                assert_eq!(*node.key(), *child.parent().unwrap().key());
+            }
+            let left = rb_view_keys(node.left(), keys);
+            keys.push(*node.key());
+            let right = rb_view_keys(node.right(), keys);
+            assert_eq!(node.size(), 1 + left + right);
+            node.size()
+        },
+        None => 0,
+    }
+}
+
+#[test]
+fn test_rb_node_view() {
+    let tree: RBT<u32> = [8, 3, 12, 1, 5, 10, 14, 4].into_iter().collect();
+    let root = tree.root_node().unwrap();
+    assert!(root.parent().is_none());
+    assert_eq!(NodeColor::Black, root.color());
+    let mut keys = Vec::new();
+    assert_eq!(8, rb_view_keys(Some(root), &mut keys));
+    assert_eq!(tree.iter().map(|entry| *entry.key()).collect::<Vec<_>>(), keys);
+
+    let twelve = tree.search(&12).unwrap();
+    assert_eq!(10, *twelve.parent().unwrap().key());
+    assert_eq!(NodeColor::Black, twelve.color());
+    assert!(twelve.left().is_none());
+    assert_eq!(NodeColor::Red, twelve.right().unwrap().color());
+    assert_eq!(Some(3), SearchTree::search(&tree, &3).map(|node| *node.key()));
+    assert!(tree.search(&6).is_none());
+    assert!(RBT::<u32>::new().root_node().is_none());
+}
+
+#[test]
+fn test_avl_node_view() {
+    let tree: AVLT<u32> = (1..=10).collect();
+    let root = tree.root_node().unwrap();
+    assert_eq!(5, *root.key());
+    assert_eq!(4, root.height());
+    assert_eq!(0, root.balance_factor());
+    assert_eq!(10, root.size());
+
+    let mut stack = vec![root];
+    let mut seen = 0;
+    while let Some(node) = stack.pop() {
+        assert!(node.balance_factor().abs() <= 1);
+        let below = [node.left(), node.right()].into_iter().flatten().collect::<Vec<_>>();
+        let child_height = below.iter().map(|child| child.height()).max().unwrap_or(0);
+        assert_eq!(node.height(), 1 + child_height);
+        for child in &below {
+            assert_eq!(*node.key(), *child.parent().unwrap().key());
+        }
+        stack.extend(below);
+        seen += 1;
+    }
+    assert_eq!(10, seen);
+
+    let nine = tree.search(&9).unwrap();
+    assert_eq!(8, *nine.parent().unwrap().key());
+    assert_eq!(Some(10), nine.right().map(|node| *node.key()));
+    assert_eq!(Some(7), SearchTree::search(&tree, &7).map(|node| *node.key()));
+    assert!(tree.search(&11).is_none());
+}
+
+#[test]
+fn test_rb_rotate_without_child() {
+    let mut nodes = RBArena::new();
+    // rotating at a node that lacks the child to move up leaves the tree as it was
+    let root = nodes.new_node(5);
+    for i in [5, 2, 10, 8, 6, 9, 12] {
+        nodes.insert_node(&root, i);
+    }
+    let root = nodes.left_rotate(root, &12);
+    let root = nodes.right_rotate(root, &2);
+    assert_eq!(5, nodes.get_root_key(&root));
+    assert_eq!(12, nodes.get_root_key(&nodes.get_right(&nodes.find_node(&root, &10))));
+    assert_eq!(10, nodes.get_root_key(&nodes.get_parent(&nodes.find_node(&root, &12))));
+    assert_eq!(2, nodes.get_root_key(&nodes.get_left(&root)));
+    assert!(nodes.get_root_nil(&nodes.get_parent(&root)));
+}
+
+#[test]
+fn test_avl_rotate_without_child() {
+    let mut nodes = AVLArena::new();
+    let root = nodes.new_node(5);
+    for i in [5, 2, 10, 8, 6, 9, 12] {
+        nodes.insert_node(&root, i);
+    }
+    let root = nodes.left_rotate(root, &12);
+    let root = nodes.right_rotate(root, &2);
+    assert_eq!(5, nodes.get_root_key(&root));
+    assert_eq!(12, nodes.get_root_key(&nodes.get_right(&nodes.find_node(&root, &10))));
+    assert_eq!(10, nodes.get_root_key(&nodes.get_parent(&nodes.find_node(&root, &12))));
+    assert_eq!(2, nodes.get_root_key(&nodes.get_left(&root)));
+}
+
+#[test]
+fn test_rb_remove_with_live_view() {
+    let mut tree: RBT<u32> = (1..=5).collect();
+    // views borrow the arena, so the removals can only start once the view is done with
+    let view = red_black_tree::RBNodeView::from_node(&tree.nodes, &tree.find_node(&3)).unwrap();
+    assert_eq!(3, *view.key());
+    assert_eq!(Some(3), tree.delete(&3));
+    assert_eq!(Some(5), tree.pop_last());
+    assert_eq!(vec![1, 2, 4], tree.iter().map(|entry| *entry.key()).collect::<Vec<u32>>());
+    assert_eq!(Ok(()), tree.validate());
+}
+
+#[test]
+fn test_avl_remove_with_live_view() {
+    let mut tree: AVLT<u32> = (1..=5).collect();
+    let view = avl_tree::AVLNodeView::from_node(&tree.nodes, &tree.find_node(&1)).unwrap();
+    assert_eq!(1, *view.key());
+    assert_eq!(Some(1), tree.pop_first());
+    assert_eq!(Some(4), tree.delete(&4));
+    assert_eq!(vec![2, 3, 5], tree.iter().map(|entry| *entry.key()).collect::<Vec<u32>>());
+    assert_eq!(Ok(()), tree.validate());
+}
+
 // #[test]
 // fn test_rb_insert_find() {
 //     let root = RBTreeNode::new(5, NodeColor::Black, None);
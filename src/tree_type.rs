@@ -1,57 +1,202 @@
 use super::*;
-use std::{fmt::Debug, cmp::Ordering};
-use red_black_tree::{RBTreeNode as RB, NodeColor as NC, Direction as Dir, RBTChild as TRoot};
+use std::{fmt, fmt::Debug, cmp::Ordering};
+use std::borrow::Borrow;
+use std::collections::{VecDeque, vec_deque};
+use std::iter::Peekable;
+use std::ops::{Bound, RangeBounds, BitAnd, BitOr, BitXor, Sub};
+use search_tree::SearchTree;
+use comparator::{Comparator, NaturalOrder};
+use augment::Augment;
+use tree_violation::TreeViolation;
+use tree_error::TreeError;
+use red_black_tree::{RBArena, NodeColor as NC, Direction as Dir, RBTChild as TRoot, RBTSlot};
 
-#[derive(Clone, Debug)]
-pub struct RedBlackTree<T: Ord + Clone> {
-    pub root: TRoot<T>,
+// The node modules are private unless `unstable-internals` is on; these are the parts of them that
+// belong to the public API.
+pub use red_black_tree::{NodeColor, RBEntryRef, RBEntryMut, RBNodeView};
+
+/*
+    Keys are ordered by `C`, which is the key's own `Ord` unless the tree is built with
+    `with_comparator`.
+*/
+pub struct RedBlackTree<T, V = (), C = NaturalOrder, A: Augment<T, V> = ()> {
+    pub(crate) root: TRoot,
+    pub(crate) nodes: RBArena<T, V, A>,
     num_leaves: u128,
     height: u128,
     _is_num_leaves_available: bool,
-    _is_height_available: bool
+    _is_height_available: bool,
+    comparator: C,
 }
 
-impl<T: Ord + Clone + Debug> RedBlackTree<T> {
+impl<T: Debug, C: Comparator<T>, A: Augment<T, ()>> RedBlackTree<T, (), C, A> {
+
+    // Adds `key` and returns whether it was new; a key already in the tree is kept as it is.
+    pub fn insert(&mut self, key: T) -> bool {
+        match self.find_slot(&key) {
+            Ok(_) => false,
+            Err((parent, direction)) => {
+                self.insert_at(&parent, direction, key, ());
+                true
+            },
+        }
+    }
+
+    // Adds `key`, swapping out an equal key already in the tree, which is returned.
+    pub fn replace(&mut self, key: T) -> Option<T> {
+        match self.find_slot(&key) {
+            Ok(existing) => self.nodes.replace_root_key(&existing, key),
+            Err((parent, direction)) => {
+                self.insert_at(&parent, direction, key, ());
+                None
+            },
+        }
+    }
+
+    // Same as `delete`; named after `BTreeSet::take`.
+    pub fn take<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        self.delete(key)
+    }
 
-    pub fn new() -> RedBlackTree<T> {
+    pub fn pop_first(&mut self) -> Option<T> {
+        self.remove_at(0)
+    }
+
+    pub fn pop_last(&mut self) -> Option<T> {
+        let last = self.len().checked_sub(1)?;
+        self.remove_at(last)
+    }
+
+    pub fn remove_at(&mut self, index: usize) -> Option<T> {
+        self.remove_entry_at(index).map(|(key, _)| key)
+    }
+
+    // Like `insert`, but a key that is already present is an error.
+    pub fn try_insert(&mut self, key: T) -> Result<(), TreeError> {
+        match self.insert(key) {
+            true => Ok(()),
+            false => Err(TreeError::DuplicateKey),
+        }
+    }
+
+    pub fn try_remove_at(&mut self, index: usize) -> Result<T, TreeError> {
+        let len = self.len();
+        self.remove_at(index).ok_or(TreeError::IndexOutOfBounds { index, len })
+    }
+
+    pub fn join(left: Self, pivot: T, right: Self) -> Self {
+        Self::join_entry(left, pivot, (), right)
+    }
+
+    pub fn bulk_load<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.load_entries(iter.into_iter().map(|key| (key, ())).collect())
+    }
+}
+
+impl<T: Ord + Debug, V> RedBlackTree<T, V> {
+
+    pub fn new() -> RedBlackTree<T, V> {
+        RedBlackTree::with_comparator(NaturalOrder)
+    }
+}
+
+impl<T: Ord + Debug> RedBlackTree<T> {
+
+    /*
+        Builds a balanced tree directly in O(n) when the keys arrive in ascending order. Any other
+        input is sorted and deduplicated first.
+    */
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_entries(iter.into_iter().map(|key| (key, ())).collect(), NaturalOrder)
+    }
+}
+
+impl<T: Debug, V, C: Comparator<T>> RedBlackTree<T, V, C> {
+
+    pub fn with_comparator(comparator: C) -> RedBlackTree<T, V, C> {
+        RedBlackTree::augmented(comparator)
+    }
+}
+
+impl<T: Debug, V, C: Comparator<T>, A: Augment<T, V>> RedBlackTree<T, V, C, A> {
+
+    /*
+        Empty tree that keeps the summaries of `A` for `fold_range`, e.g.
+        `RedBlackTree::<u64, u64, NaturalOrder, SumOfValues>::augmented(NaturalOrder)`.
+    */
+    pub fn augmented(comparator: C) -> RedBlackTree<T, V, C, A> {
         RedBlackTree { 
             root: None, 
+            nodes: RBArena::new(),
             num_leaves: 0, 
             height: 0,
             _is_num_leaves_available: false,
-            _is_height_available: false
+            _is_height_available: false,
+            comparator,
         }
     }
 
-    pub fn search(&self, key: &T) -> TRoot<T> {
-        RB::find_node(&self.root, key.clone())
+    pub fn search<Q: ?Sized>(&self, key: &Q) -> Option<RBNodeView<'_, T, V, A>> where T: Borrow<Q>, C: Comparator<Q> {
+        RBNodeView::from_node(&self.nodes, &self.find_node(key))
+    }
+
+    pub(crate) fn find_node<Q: ?Sized>(&self, key: &Q) -> TRoot where T: Borrow<Q>, C: Comparator<Q> {
+        self.nodes.find_node_by(&self.root, key, &self.comparator)
+    }
+
+    pub fn root_node(&self) -> Option<RBNodeView<'_, T, V, A>> {
+        RBNodeView::from_node(&self.nodes, &self.root)
+    }
+
+    /*
+        The raw root, for driving the node functions of `red_black_tree` by hand. Nothing stops those from
+        breaking the tree, so this is only available with the `unstable-internals` feature.
+    */
+    #[cfg(feature = "unstable-internals")]
+    pub fn raw_root(&self) -> &TRoot {
+        &self.root
+    }
+
+    #[cfg(feature = "unstable-internals")]
+    pub fn raw_root_mut(&mut self) -> &mut TRoot {
+        self._is_num_leaves_available = false;
+        self._is_height_available = false;
+        &mut self.root
+    }
+
+    /*
+        Checks every structural rule of the tree in O(n) and reports the first broken one, e.g. to
+        call after each operation in tests or debug builds.
+    */
+    pub fn validate(&self) -> Result<(), TreeViolation> {
+        self.nodes.validate(&self.root, &self.comparator)
     }
 
     pub fn is_empty(&self) -> bool {
-        RB::get_root_nil(&self.root)
+        self.nodes.get_root_nil(&self.root)
     }
 
-    fn asc_print(root: &TRoot<T>) {
-        let left = RB::get_left(root);
-        if !RB::get_root_nil(&left) {
-            Self::asc_print(&left);
+    fn asc_print(&self, root: &TRoot) {
+        let left = self.nodes.get_left(root);
+        if !self.nodes.get_root_nil(&left) {
+            self.asc_print(&left);
         }
-        print!("{:?} -> ", RB::get_root_key(root));
-        let right = RB::get_right(root);
-        if !RB::get_root_nil(&right) {
-            Self::asc_print(&right);
+        print!("{:?} -> ", self.nodes.get_root_key_ref(root));
+        let right = self.nodes.get_right(root);
+        if !self.nodes.get_root_nil(&right) {
+            self.asc_print(&right);
         }
     }
 
-    fn desc_print(root: &TRoot<T>) {
-        let right = RB::get_right(root);
-        if !RB::get_root_nil(&right) {
-            Self::desc_print(&right);
+    fn desc_print(&self, root: &TRoot) {
+        let right = self.nodes.get_right(root);
+        if !self.nodes.get_root_nil(&right) {
+            self.desc_print(&right);
         }
-        print!("{:?} -> ", RB::get_root_key(root));
-        let left = RB::get_left(root);
-        if !RB::get_root_nil(&left) {
-            Self::desc_print(&left);
+        print!("{:?} -> ", self.nodes.get_root_key_ref(root));
+        let left = self.nodes.get_left(root);
+        if !self.nodes.get_root_nil(&left) {
+            self.desc_print(&left);
         }
     }
 
@@ -62,28 +207,318 @@ impl<T: Ord + Clone + Debug> RedBlackTree<T> {
         }
         match order {
             Ordering::Less => {
-                Self::asc_print(&self.root);
+                self.asc_print(&self.root);
                 println!("done");
             },
             Ordering::Equal => println!("Choose Less or Greater please."),
             Ordering::Greater => {
-                Self::desc_print(&self.root);
+                self.desc_print(&self.root);
                 println!("done");
             },
         }
     }
 
     pub fn print_tree(&self) {
-        RB::print_tree(&self.root)
+        self.nodes.print_tree(&self.root)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, V, A> {
+        if self.is_empty() {
+            return Iter { nodes: &self.nodes, front: None, back: None };
+        }
+        Iter {
+            nodes: &self.nodes,
+            front: self.nodes.get_minimum(&self.root),
+            back: self.nodes.get_maximum(&self.root),
+        }
+    }
+
+    pub fn range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> Range<'_, T, V, A> where T: Borrow<Q>, C: Comparator<Q> {
+        let front = self.nodes.find_lower_bound(&self.root, range.start_bound(), &self.comparator);
+        if !self.nodes.satisfies_upper_bound(&front, range.end_bound(), &self.comparator) {
+            return Range { iter: Iter { nodes: &self.nodes, front: None, back: None } };
+        }
+        Range {
+            iter: Iter {
+                nodes: &self.nodes,
+                front,
+                back: self.nodes.find_upper_bound(&self.root, range.end_bound(), &self.comparator),
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.get_size(&self.root)
+    }
+
+    pub fn select(&self, index: usize) -> Option<RBEntryRef<'_, T, V, A>> {
+        RBEntryRef::from_node(&self.nodes, &self.nodes.select_node(&self.root, index))
+    }
+
+    pub fn rank<Q: ?Sized>(&self, key: &Q) -> usize where T: Borrow<Q>, C: Comparator<Q> {
+        self.nodes.count_below_lower_bound(&self.root, Bound::Included(key), &self.comparator)
+    }
+
+    pub fn count_range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> usize where T: Borrow<Q>, C: Comparator<Q> {
+        let below = self.nodes.count_below_lower_bound(&self.root, range.start_bound(), &self.comparator);
+        let upto = self.nodes.count_within_upper_bound(&self.root, range.end_bound(), &self.comparator);
+        upto.saturating_sub(below)
+    }
+
+    /*
+        Combines the summaries of all entries within the range in key order, in O(log n). Trees
+        without an augmentation (`A = ()`) just return `()`.
+    */
+    pub fn fold_range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> A::Summary where T: Borrow<Q>, C: Comparator<Q> {
+        self.nodes.fold_range(&self.root, range.start_bound(), range.end_bound(), &self.comparator)
+    }
+
+    pub fn first(&self) -> Option<RBEntryRef<'_, T, V, A>> {
+        RBEntryRef::from_node(&self.nodes, &self.nodes.find_lower_bound(&self.root, Bound::Unbounded, &self.comparator))
+    }
+
+    pub fn last(&self) -> Option<RBEntryRef<'_, T, V, A>> {
+        RBEntryRef::from_node(&self.nodes, &self.nodes.find_upper_bound(&self.root, Bound::Unbounded, &self.comparator))
+    }
+
+    pub fn floor<Q: ?Sized>(&self, key: &Q) -> Option<RBEntryRef<'_, T, V, A>> where T: Borrow<Q>, C: Comparator<Q> {
+        RBEntryRef::from_node(&self.nodes, &self.nodes.find_upper_bound(&self.root, Bound::Included(key), &self.comparator))
+    }
+
+    pub fn ceiling<Q: ?Sized>(&self, key: &Q) -> Option<RBEntryRef<'_, T, V, A>> where T: Borrow<Q>, C: Comparator<Q> {
+        RBEntryRef::from_node(&self.nodes, &self.nodes.find_lower_bound(&self.root, Bound::Included(key), &self.comparator))
+    }
+
+    pub fn predecessor<Q: ?Sized>(&self, key: &Q) -> Option<RBEntryRef<'_, T, V, A>> where T: Borrow<Q>, C: Comparator<Q> {
+        RBEntryRef::from_node(&self.nodes, &self.nodes.find_upper_bound(&self.root, Bound::Excluded(key), &self.comparator))
+    }
+
+    pub fn successor<Q: ?Sized>(&self, key: &Q) -> Option<RBEntryRef<'_, T, V, A>> where T: Borrow<Q>, C: Comparator<Q> {
+        RBEntryRef::from_node(&self.nodes, &self.nodes.find_lower_bound(&self.root, Bound::Excluded(key), &self.comparator))
+    }
+
+    pub(crate) fn remove_entry_at(&mut self, index: usize) -> Option<(T, V)> {
+        let node = self.nodes.select_node(&self.root, index);
+        if self.nodes.get_root_nil(&node) {
+            return None;
+        }
+        let node = self.unlink_node(node);
+        self.nodes.free_entry(node)
+    }
+
+    fn from_root(nodes: RBArena<T, V, A>, root: TRoot, comparator: C) -> Self {
+        let mut tree = RedBlackTree::augmented(comparator);
+        tree.nodes = nodes;
+        tree.set_root(root);
+        tree
+    }
+
+    /*
+        Takes the roots out of both trees and brings the nodes of `other` over into this tree's arena.
+        Whichever tree is smaller is the one whose nodes move; `other` is left empty.
+    */
+    fn take_roots(&mut self, other: &mut Self) -> (TRoot, TRoot) {
+        let (mut root, mut other_root) = (self.root.take(), other.root.take());
+        let mut other_nodes = std::mem::take(&mut other.nodes);
+        other.set_root(None);
+        if self.nodes.get_size(&root) < other_nodes.get_size(&other_root) {
+            std::mem::swap(&mut self.nodes, &mut other_nodes);
+            root = self.nodes.adopt(&mut other_nodes, &root);
+        } else {
+            other_root = self.nodes.adopt(&mut other_nodes, &other_root);
+        }
+        (root, other_root)
+    }
+
+    fn set_root(&mut self, root: TRoot) {
+        self.nodes.set_parent(&root, &None);
+        self.nodes.set_root_color(&root, NC::Black);
+        self.root = root;
+        self._is_num_leaves_available = false;
+        self._is_height_available = false;
+    }
+
+    /*
+        Moves every key >= `key` into a new tree and keeps the smaller ones. The split itself takes
+        O(log n); afterwards the smaller half moves over into an arena of its own.
+    */
+    pub fn split_off<Q: ?Sized>(&mut self, key: &Q) -> Self where T: Borrow<Q>, C: Clone + Comparator<Q> {
+        let black_height = self.nodes.get_black_height(&self.root);
+        let root = self.root.take();
+        let (less, _, found, greater, greater_bh) = self.nodes.split(&root, black_height, key, &self.comparator);
+        let greater = if self.nodes.get_root_nil(&found) {
+            greater
+        } else {
+            self.nodes.join(&None, 0, &found, &greater, greater_bh).0
+        };
+        let mut moved = RBArena::new();
+        if self.nodes.get_size(&less) < self.nodes.get_size(&greater) {
+            let less = moved.adopt(&mut self.nodes, &less);
+            let nodes = std::mem::replace(&mut self.nodes, moved);
+            self.set_root(less);
+            Self::from_root(nodes, greater, self.comparator.clone())
+        } else {
+            let greater = moved.adopt(&mut self.nodes, &greater);
+            self.set_root(less);
+            Self::from_root(moved, greater, self.comparator.clone())
+        }
+    }
+
+    /*
+        Moves every entry of `other` into this tree. When all keys of one tree are below those of the
+        other the two are joined in O(log n); overlapping trees are merged with a union in which the
+        entries of `other` win.
+    */
+    pub fn append(&mut self, other: &mut Self) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            std::mem::swap(self, other);
+            return;
+        }
+        let (self_root, other_root) = self.take_roots(other);
+        let (self_min, self_max) = (self.nodes.get_minimum(&self_root), self.nodes.get_maximum(&self_root));
+        let (other_min, other_max) = (self.nodes.get_minimum(&other_root), self.nodes.get_maximum(&other_root));
+        let (self_bh, other_bh) = (self.nodes.get_black_height(&self_root), self.nodes.get_black_height(&other_root));
+        let root = if self.comparator.compare(self.nodes.get_root_key_ref(&self_max), self.nodes.get_root_key_ref(&other_min)) == Ordering::Less {
+            self.nodes.join_concat(&self_root, self_bh, &other_root, other_bh).0
+        } else if self.comparator.compare(self.nodes.get_root_key_ref(&other_max), self.nodes.get_root_key_ref(&self_min)) == Ordering::Less {
+            self.nodes.join_concat(&other_root, other_bh, &self_root, self_bh).0
+        } else {
+            self.nodes.union(&other_root, other_bh, &self_root, self_bh, &self.comparator).0
+        };
+        self.set_root(root);
+    }
+
+    pub fn into_union(mut self, mut other: Self) -> Self {
+        let (root, other_root) = self.take_roots(&mut other);
+        let (self_bh, other_bh) = (self.nodes.get_black_height(&root), self.nodes.get_black_height(&other_root));
+        let root = self.nodes.union(&root, self_bh, &other_root, other_bh, &self.comparator).0;
+        self.set_root(root);
+        self
+    }
+
+    pub fn into_intersection(mut self, mut other: Self) -> Self {
+        let (root, other_root) = self.take_roots(&mut other);
+        let (self_bh, other_bh) = (self.nodes.get_black_height(&root), self.nodes.get_black_height(&other_root));
+        let root = self.nodes.intersection(&root, self_bh, &other_root, other_bh, &self.comparator).0;
+        self.set_root(root);
+        self
+    }
+
+    pub fn into_difference(mut self, mut other: Self) -> Self {
+        let (root, other_root) = self.take_roots(&mut other);
+        let (self_bh, other_bh) = (self.nodes.get_black_height(&root), self.nodes.get_black_height(&other_root));
+        let root = self.nodes.difference(&root, self_bh, &other_root, other_bh, &self.comparator).0;
+        self.set_root(root);
+        self
+    }
+
+    pub fn into_symmetric_difference(mut self, mut other: Self) -> Self {
+        let (root, other_root) = self.take_roots(&mut other);
+        let (self_bh, other_bh) = (self.nodes.get_black_height(&root), self.nodes.get_black_height(&other_root));
+        let root = self.nodes.symmetric_difference(&root, self_bh, &other_root, other_bh, &self.comparator).0;
+        self.set_root(root);
+        self
+    }
+
+    pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, T, V, C, A> {
+        Union { merge: MergeIter::new(self.iter(), other.iter(), &self.comparator) }
+    }
+
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, T, V, C, A> {
+        Intersection { merge: MergeIter::new(self.iter(), other.iter(), &self.comparator) }
+    }
+
+    pub fn difference<'a>(&'a self, other: &'a Self) -> Difference<'a, T, V, C, A> {
+        Difference { merge: MergeIter::new(self.iter(), other.iter(), &self.comparator) }
+    }
+
+    pub fn symmetric_difference<'a>(&'a self, other: &'a Self) -> SymmetricDifference<'a, T, V, C, A> {
+        SymmetricDifference { merge: MergeIter::new(self.iter(), other.iter(), &self.comparator) }
+    }
+
+    /*
+        Joins `left`, the entry and `right` into one tree in O(log n) when every key of `left` is below
+        `key` and every key of `right` is above it. Otherwise the trees are merged entry by entry.
+    */
+    pub(crate) fn join_entry(mut left: Self, key: T, value: V, mut right: Self) -> Self {
+        let ordered = (left.is_empty() || left.comparator.compare(left.nodes.get_root_key_ref(&left.nodes.get_maximum(&left.root)), &key) == Ordering::Less)
+            && (right.is_empty() || left.comparator.compare(&key, right.nodes.get_root_key_ref(&right.nodes.get_minimum(&right.root))) == Ordering::Less);
+        if !ordered {
+            left.append(&mut right);
+            left.insert_entry(key, value);
+            return left;
+        }
+        let (left_root, right_root) = left.take_roots(&mut right);
+        let (left_bh, right_bh) = (left.nodes.get_black_height(&left_root), left.nodes.get_black_height(&right_root));
+        let pivot = left.nodes.new_entry(key, value);
+        let root = left.nodes.join(&left_root, left_bh, &pivot, &right_root, right_bh).0;
+        left.set_root(root);
+        left
+    }
+
+    fn sort_entries(entries: &mut Vec<(T, V)>, comparator: &C) {
+        if entries.windows(2).all(|pair| comparator.compare(&pair[0].0, &pair[1].0) == Ordering::Less) {
+            return;
+        }
+        // the sort is stable, so after reversing the last entry given for a key comes first and survives
+        entries.sort_by(|a, b| comparator.compare(&a.0, &b.0));
+        entries.reverse();
+        entries.dedup_by(|a, b| comparator.compare(&a.0, &b.0) == Ordering::Equal);
+        entries.reverse();
+    }
+
+    pub(crate) fn from_entries(mut entries: Vec<(T, V)>, comparator: C) -> Self {
+        Self::sort_entries(&mut entries, &comparator);
+        let len = entries.len();
+        let mut nodes = RBArena::new();
+        let root = nodes.build_sorted(&mut entries.into_iter(), len);
+        Self::from_root(nodes, root, comparator)
+    }
+
+    /*
+        Builds the new entries into their own tree and merges it in with a union, so loaded values
+        replace existing ones just like `insert` would.
+    */
+    pub(crate) fn load_entries(&mut self, mut entries: Vec<(T, V)>) {
+        Self::sort_entries(&mut entries, &self.comparator);
+        let len = entries.len();
+        let loaded = self.nodes.build_sorted(&mut entries.into_iter(), len);
+        let (loaded_bh, self_bh) = (self.nodes.get_black_height(&loaded), self.nodes.get_black_height(&self.root));
+        let root = self.nodes.union(&loaded, loaded_bh, &self.root.take(), self_bh, &self.comparator).0;
+        self.set_root(root);
+    }
+
+    pub(crate) fn into_entries(mut self) -> VecDeque<(T, V)> {
+        let mut entries = VecDeque::new();
+        let root = self.root.take();
+        self.drain_entries(root, &mut entries);
+        entries
+    }
+
+    fn drain_entries(&mut self, root: TRoot, entries: &mut VecDeque<(T, V)>) {
+        if self.nodes.get_root_nil(&root) {
+            return;
+        }
+        let (left, right) = (self.nodes.get_left(&root), self.nodes.get_right(&root));
+        self.nodes.set_child(&root, None, Dir::Left);
+        self.nodes.set_child(&root, None, Dir::Right);
+        self.drain_entries(left, entries);
+        if let Some(entry) = self.nodes.free_entry(root) {
+            entries.push_back(entry);
+        }
+        self.drain_entries(right, entries);
     }
 
     pub fn get_num_leaves(&mut self) -> u128 {
         if self._is_num_leaves_available {
             return self.num_leaves;
         }
-        self.num_leaves = RB::count_leaves(&self.root);
+        self.num_leaves = self.nodes.count_leaves(&self.root);
         self._is_num_leaves_available = true;
-        return self.num_leaves;
+        self.num_leaves
     }
 
 
@@ -91,254 +526,642 @@ impl<T: Ord + Clone + Debug> RedBlackTree<T> {
         if self._is_height_available {
             return self.height;
         }
-        self.height = RB::get_height(&self.root);
+        self.height = self.nodes.get_height(&self.root);
         self._is_height_available = true;
-        return self.height;
+        self.height
     }
 
 
-    pub fn transplant(&mut self, u: &TRoot<T>, v: &TRoot<T>) {
-        let u_node = u.clone();
-        let v_node = v.clone();
-        match RB::get_parent(&u_node) {
+    pub(crate) fn transplant(&mut self, u: &TRoot, v: &TRoot) {
+        let u_node = *u;
+        let v_node = *v;
+        match self.nodes.get_parent(&u_node) {
             Some(_) => {
-                if RB::is_node_equal(&u_node, &RB::get_left(&RB::get_parent(&u_node))) {  // If the u_node is a left child.
-                    RB::set_child(&RB::get_parent(&u_node), v_node.clone(), Dir::Left);  // Set the left child of u's parent
+                if self.nodes.is_node_equal(&u_node, &self.nodes.get_left(&self.nodes.get_parent(&u_node))) {  // If the u_node is a left child.
+                    self.nodes.set_child(&self.nodes.get_parent(&u_node), v_node, Dir::Left);  // Set the left child of u's parent
                     // to be v. In other words, removes u and replaces it with v.
                 } else {  // u_node is a right child
-                    RB::set_child(&RB::get_parent(&u_node), v_node.clone(), Dir::Right);  // Replaces u with v
+                    self.nodes.set_child(&self.nodes.get_parent(&u_node), v_node, Dir::Right);  // Replaces u with v
                 }
             },
-            None => self.root = v_node.clone(),
+            None => self.root = v_node,
         };
-        RB::set_parent(&v_node, &RB::get_parent(&u_node));
-    }
-
-    pub fn get_minimum(&self) -> TRoot<T> {
-        RB::get_minimum(&self.root)
+        self.nodes.set_parent(&v_node, &self.nodes.get_parent(&u_node));
     }
 
-    fn delete_fixup(&mut self, fix_root: &TRoot<T>, fix_root_parent: Option<&TRoot<T>>) {
+    fn delete_fixup(&mut self, fix_root: &TRoot, fix_root_parent: Option<&TRoot>) {
         //self.print();
-        //RB::print_tree(&fix_root);
-        let mut x = fix_root.clone();
+        //self.nodes.print_tree(&fix_root);
+        let mut x = *fix_root;
         let mut parent = match fix_root_parent {
-            Some(p) => p.clone(),
-            None => RB::get_parent(&x),
+            Some(p) => *p,
+            None => self.nodes.get_parent(&x),
         };
-        //println!("{:?}", !RB::is_node_equal(&x, &self.root) && RB::get_root_color(&x) == NC::Black);
-        while !RB::is_node_equal(&x, &self.root) && RB::get_root_color(&x) == NC::Black {
-            if RB::is_node_equal(&x, &RB::get_left(&parent)) {
-                let mut w = RB::get_right(&parent);
+        //println!("{:?}", !self.nodes.is_node_equal(&x, &self.root) && self.nodes.get_root_color(&x) == NC::Black);
+        while !self.nodes.is_node_equal(&x, &self.root) && self.nodes.get_root_color(&x) == NC::Black {
+            // an empty x is the left child when that side is empty, its sibling never is
+            let x_is_left = match (&x, self.nodes.get_left(&parent)) {
+                (None, None) => true,
+                (_, left) => self.nodes.is_node_equal(&x, &left),
+            };
+            if x_is_left {
+                let mut w = self.nodes.get_right(&parent);
                 // type 1
-                if RB::get_root_color(&w) == NC::Red {
-                    RB::set_root_color(&w, NC::Black);
-                    RB::set_root_color(&parent, NC::Red);
-                    self.root = RB::left_rotate(self.root.clone(), RB::get_root_key(&parent));
-                    w = RB::get_right(&parent);
+                if self.nodes.get_root_color(&w) == NC::Red {
+                    self.nodes.set_root_color(&w, NC::Black);
+                    self.nodes.set_root_color(&parent, NC::Red);
+                    self.root = self.nodes.left_rotate_at(self.root, &parent);
+                    w = self.nodes.get_right(&parent);
                 }
                 // type 2
-                if RB::get_root_color(&RB::get_left(&w)) == NC::Black
-                && RB::get_root_color(&RB::get_right(&w)) == NC::Black {
-                    RB::set_root_color(&w, NC::Red);
-                    x = parent.clone();
-                    parent = RB::get_parent(&x);
+                if self.nodes.get_root_color(&self.nodes.get_left(&w)) == NC::Black
+                && self.nodes.get_root_color(&self.nodes.get_right(&w)) == NC::Black {
+                    self.nodes.set_root_color(&w, NC::Red);
+                    x = parent;
+                    parent = self.nodes.get_parent(&x);
                 } else {
                     // type 3
-                    if RB::get_root_color(&RB::get_right(&w)) == NC::Black {
-                        RB::set_root_color(&RB::get_left(&w), NC::Black);
-                        RB::set_root_color(&w, NC::Red);
-                        self.root = RB::right_rotate(self.root.clone(), RB::get_root_key(&w));
-                        w = RB::get_right(&parent);
+                    if self.nodes.get_root_color(&self.nodes.get_right(&w)) == NC::Black {
+                        self.nodes.set_root_color(&self.nodes.get_left(&w), NC::Black);
+                        self.nodes.set_root_color(&w, NC::Red);
+                        self.root = self.nodes.right_rotate_at(self.root, &w);
+                        w = self.nodes.get_right(&parent);
                     }
                     // type 4
-                    RB::set_root_color(&w, RB::get_root_color(&parent));
-                    RB::set_root_color(&parent, NC::Black);
-                    RB::set_root_color(&RB::get_right(&w), NC::Black);
-                    self.root = RB::left_rotate(self.root.clone(), RB::get_root_key(&parent));
-                    x = self.root.clone();
-                    parent = RB::get_parent(&x);
+                    self.nodes.set_root_color(&w, self.nodes.get_root_color(&parent));
+                    self.nodes.set_root_color(&parent, NC::Black);
+                    self.nodes.set_root_color(&self.nodes.get_right(&w), NC::Black);
+                    self.root = self.nodes.left_rotate_at(self.root, &parent);
+                    x = self.root;
+                    parent = self.nodes.get_parent(&x);
                 }
             } else {
-                let mut w = RB::get_left(&parent);
+                let mut w = self.nodes.get_left(&parent);
                 // type 1
-                if RB::get_root_color(&w) == NC::Red {
-                    RB::set_root_color(&w, NC::Black);
-                    RB::set_root_color(&parent, NC::Red);
-                    self.root = RB::right_rotate(self.root.clone(), RB::get_root_key(&parent));
-                    w = RB::get_left(&parent);
+                if self.nodes.get_root_color(&w) == NC::Red {
+                    self.nodes.set_root_color(&w, NC::Black);
+                    self.nodes.set_root_color(&parent, NC::Red);
+                    self.root = self.nodes.right_rotate_at(self.root, &parent);
+                    w = self.nodes.get_left(&parent);
                 }
                 // type 2
-                if RB::get_root_color(&RB::get_right(&w)) == NC::Black
-                && RB::get_root_color(&RB::get_left(&w)) == NC::Black {
-                    RB::set_root_color(&w, NC::Red);
-                    x = parent.clone();
-                    parent = RB::get_parent(&x);
+                if self.nodes.get_root_color(&self.nodes.get_right(&w)) == NC::Black
+                && self.nodes.get_root_color(&self.nodes.get_left(&w)) == NC::Black {
+                    self.nodes.set_root_color(&w, NC::Red);
+                    x = parent;
+                    parent = self.nodes.get_parent(&x);
                 } else {
                     // type 3
-                    if RB::get_root_color(&RB::get_left(&w)) == NC::Black {
-                        RB::set_root_color(&RB::get_right(&w), NC::Black);
-                        RB::set_root_color(&w, NC::Red);
-                        self.root = RB::left_rotate(self.root.clone(), RB::get_root_key(&w));
-                        w = RB::get_left(&parent);
+                    if self.nodes.get_root_color(&self.nodes.get_left(&w)) == NC::Black {
+                        self.nodes.set_root_color(&self.nodes.get_right(&w), NC::Black);
+                        self.nodes.set_root_color(&w, NC::Red);
+                        self.root = self.nodes.left_rotate_at(self.root, &w);
+                        w = self.nodes.get_left(&parent);
                     }
                     // type 4
-                    RB::set_root_color(&w, RB::get_root_color(&parent));
-                    RB::set_root_color(&parent, NC::Black);
-                    RB::set_root_color(&RB::get_left(&w), NC::Black);
-                    self.root = RB::right_rotate(self.root.clone(), RB::get_root_key(&parent));
-                    x = self.root.clone();
-                    parent = RB::get_parent(&x);
+                    self.nodes.set_root_color(&w, self.nodes.get_root_color(&parent));
+                    self.nodes.set_root_color(&parent, NC::Black);
+                    self.nodes.set_root_color(&self.nodes.get_left(&w), NC::Black);
+                    self.root = self.nodes.right_rotate_at(self.root, &parent);
+                    x = self.root;
+                    parent = self.nodes.get_parent(&x);
                 }
             }
         }
-        RB::set_root_color(&x, NC::Black);
+        self.nodes.set_root_color(&x, NC::Black);
+    }
+
+    // Removes `key` and returns the key that was stored, or `None` if it was not in the tree.
+    pub fn delete<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        self.remove_entry(key).map(|(key, _)| key)
+    }
+
+    pub fn try_delete<Q: ?Sized>(&mut self, key: &Q) -> Result<T, TreeError> where T: Borrow<Q>, C: Comparator<Q> {
+        self.delete(key).ok_or(TreeError::KeyNotFound)
     }
 
-    pub fn delete(&mut self, key: &T) {
+    pub(crate) fn remove_entry<Q: ?Sized>(&mut self, key: &Q) -> Option<(T, V)> where T: Borrow<Q>, C: Comparator<Q> {
+        let node = self.remove_node(key);
+        self.nodes.free_entry(node)
+    }
+
+    pub(crate) fn remove_node<Q: ?Sized>(&mut self, key: &Q) -> TRoot where T: Borrow<Q>, C: Comparator<Q> {
+        let z = self.nodes.find_node_by(&self.root, key, &self.comparator);
+        self.unlink_node(z)
+    }
+
+    /*
+        Unlinks the node z of this tree and rebalances. The removed node is returned fully detached
+        (no parent, no children) so callers can take its value out.
+    */
+    pub(crate) fn unlink_node(&mut self, z: TRoot) -> TRoot {
+        if self.nodes.get_root_nil(&z) {
+            return None;
+        }
         self._is_num_leaves_available = false;
         self._is_height_available = false;
-        let z = RB::find_node(&self.root, key.clone());
-        match z {
-            None => {
-                return;
-            },
-            _ => (),
-        };
-        if RB::is_node_equal(&self.root, &z)
-        && RB::get_root_nil(&RB::get_left(&self.root))
-        && RB::get_root_nil(&RB::get_right(&self.root)) {
+        if self.nodes.is_node_equal(&self.root, &z)
+        && self.nodes.get_root_nil(&self.nodes.get_left(&self.root))
+        && self.nodes.get_root_nil(&self.nodes.get_right(&self.root)) {
             self.root = None;
-            return;
+            return z;
         }
 
-        //RB::print_tree(&z);
-        //RB::solidify_all_nil(&self.root);
-        let x: TRoot<T>;
-        let mut y = z.clone();
-        let mut y_orig_color = RB::get_root_color(&y);
+        // x takes the place of the unlinked node and may be empty, so its parent is tracked separately
+        let x: TRoot;
+        let x_parent: TRoot;
+        let mut y_orig_color = self.nodes.get_root_color(&z);
 
-        if RB::get_root_nil(&RB::get_left(&z)) {
-            if RB::get_root_nil(&RB::get_right(&z)) {
-                RB::set_child_nil(&z, Dir::Right);
-            }
-            x = RB::get_right(&z);
-            self.transplant(&z, &RB::get_right(&z));
-        } else if RB::get_root_nil(&RB::get_right(&z)) {
-            if RB::get_root_nil(&RB::get_left(&z)) {
-                RB::set_child_nil(&z, Dir::Left);
-            }
-            x = RB::get_left(&z);
-            self.transplant(&z, &RB::get_left(&z));
+        if self.nodes.get_root_nil(&self.nodes.get_left(&z)) {
+            x = self.nodes.get_right(&z);
+            x_parent = self.nodes.get_parent(&z);
+            self.transplant(&z, &x);
+        } else if self.nodes.get_root_nil(&self.nodes.get_right(&z)) {
+            x = self.nodes.get_left(&z);
+            x_parent = self.nodes.get_parent(&z);
+            self.transplant(&z, &x);
         } else {
-            y = RB::get_minimum(&RB::get_right(&z));
-            y_orig_color = RB::get_root_color(&y);
-            if RB::get_root_nil(&RB::get_right(&y)) {
-                RB::set_child_nil(&y, Dir::Right);
-            }
-            x = RB::get_right(&y);
+            let y = self.nodes.get_minimum(&self.nodes.get_right(&z));
+            y_orig_color = self.nodes.get_root_color(&y);
+            x = self.nodes.get_right(&y);
 
-            if RB::is_node_equal(&RB::get_parent(&y), &z) {
-                RB::set_parent(&x, &y);
+            if self.nodes.is_node_equal(&self.nodes.get_parent(&y), &z) {
+                x_parent = y;
             } else {
-                self.transplant(&y, &RB::get_right(&y));
-                RB::set_child(&y, RB::get_right(&z), Dir::Right);
-                RB::set_parent(&RB::get_right(&y), &y);
+                x_parent = self.nodes.get_parent(&y);
+                self.transplant(&y, &x);
+                self.nodes.set_child(&y, self.nodes.get_right(&z), Dir::Right);
+                self.nodes.set_parent(&self.nodes.get_right(&y), &y);
             }
 
             self.transplant(&z, &y);
-            RB::set_child(&y, RB::get_left(&z), Dir::Left);
-            RB::set_parent(&RB::get_left(&y), &y);
-            RB::set_root_color(&y, RB::get_root_color(&z));
+            self.nodes.set_child(&y, self.nodes.get_left(&z), Dir::Left);
+            self.nodes.set_parent(&self.nodes.get_left(&y), &y);
+            self.nodes.set_root_color(&y, self.nodes.get_root_color(&z));
         }
 
-        let x_nil_fix = x.clone();
+        self.nodes.update_size_to_root(&x_parent);  // Every node above the unlinked position lost one descendant.
 
         match y_orig_color {
             NC::Red => (),
-            NC::Black => self.delete_fixup(&x, None),
+            NC::Black => self.delete_fixup(&x, Some(&x_parent)),
         };
 
-        if !RB::get_root_nil(&x_nil_fix) {
-            return;
+        self.nodes.set_child(&z, None, Dir::Left);
+        self.nodes.set_child(&z, None, Dir::Right);
+        self.nodes.set_parent(&z, &None);
+        z
+    }
+
+    /*
+        Inserts `key` with `value`. If the key is already present only its value is replaced (the old
+        one is returned) and the colouring is left alone. Either way the tree is descended only once.
+    */
+    pub(crate) fn insert_entry(&mut self, key: T, value: V) -> Option<V> {
+        match self.find_slot(&key) {
+            Ok(existing) => self.nodes.replace_root_value(&existing, value),
+            Err((parent, direction)) => {
+                self.insert_at(&parent, direction, key, value);
+                None
+            },
         }
-        
-        if RB::is_node_equal(&x_nil_fix, &RB::get_left(&RB::get_parent(&x_nil_fix))) {
-            RB::set_child(&RB::get_parent(&x_nil_fix), None, Dir::Left);
-        } else if RB::is_node_equal(&x_nil_fix, &RB::get_right(&RB::get_parent(&x_nil_fix))) {
-            RB::set_child(&RB::get_parent(&x_nil_fix), None, Dir::Right);
-        } else {()}
-        //RB::virtualize_all_nil(&self.root);
     }
 
-    pub fn insert(&mut self, key: &T) {
+    /*
+        Finds the node holding `key`, or else the parent and side of the empty slot it belongs in.
+    */
+    pub(crate) fn find_slot(&self, key: &T) -> RBTSlot {
+        self.nodes.find_slot(&self.root, key, &self.comparator)
+    }
+
+    /*
+        Hangs a new entry into a slot returned by `find_slot` and rebalances; returns the new node.
+    */
+    pub(crate) fn insert_at(&mut self, parent: &TRoot, direction: Dir, key: T, value: V) -> TRoot {
         self._is_num_leaves_available = false;
         self._is_height_available = false;
-        match &self.root {
-            Some(_) => RB::insert_node(&self.root, key.clone()),
-            None => self.root = RB::new(key.clone()),
+        let z = if self.nodes.get_root_nil(parent) {
+            self.root = self.nodes.new_entry(key, value);
+            self.root
+        } else {
+            self.nodes.attach_entry(parent, key, value, direction)
         };
-        let mut z = RB::find_node(&self.root, key.clone());
+        self.nodes.update_size_to_root(parent);
+        self.insert_fixup(z);
+        z
+    }
 
-        while RB::get_root_color(&RB::get_parent(&z)) == NC::Red {  // Keep looping if the current node is red
+    fn insert_fixup(&mut self, z: TRoot) {
+        let mut z = z;
+        while self.nodes.get_root_color(&self.nodes.get_parent(&z)) == NC::Red {  // Keep looping if the current node is red
             // The if block checks if the parent of z is a left node
-            if RB::is_node_equal(
-                &RB::get_parent(&z),
-                &RB::get_left(&RB::get_parent(&RB::get_parent(&z)))
+            if self.nodes.is_node_equal(
+                &self.nodes.get_parent(&z),
+                &self.nodes.get_left(&self.nodes.get_parent(&self.nodes.get_parent(&z)))
             ) {
                 /*
                     Here we know that the parent of z is a left node. Get the uncle of z (sibling of z's parent)
                 */
-                let y = RB::get_right(&RB::get_parent(&RB::get_parent(&z)));
-                if RB::get_root_color(&y) == NC::Red {  // If z's uncle is red
+                let y = self.nodes.get_right(&self.nodes.get_parent(&self.nodes.get_parent(&z)));
+                if self.nodes.get_root_color(&y) == NC::Red {  // If z's uncle is red
                     /*
                         This entire if block just performs node recoloring
                     */
                     // The layer composed of z's parent and uncle becomes black.
-                    RB::set_root_color(&RB::get_parent(&z), NC::Black);  // Set z's parent's color to black
-                    RB::set_root_color(&y, NC::Black);  // Set z's uncle's color to black
+                    self.nodes.set_root_color(&self.nodes.get_parent(&z), NC::Black);  // Set z's parent's color to black
+                    self.nodes.set_root_color(&y, NC::Black);  // Set z's uncle's color to black
                     // Set z's grandparent to be re
-                    RB::set_root_color(&RB::get_parent(&RB::get_parent(&z)), NC::Red);
+                    self.nodes.set_root_color(&self.nodes.get_parent(&self.nodes.get_parent(&z)), NC::Red);
                     // Set z to be the grandparent
-                    z = RB::get_parent(&RB::get_parent(&z));
+                    z = self.nodes.get_parent(&self.nodes.get_parent(&z));
                 } else {  // If z's uncle is black. Here we know that z's parent is a left node.
                     // The if condition checks if z is a right node
-                    if RB::is_node_equal(
+                    if self.nodes.is_node_equal(
                         &z,
-                        &RB::get_right(&RB::get_parent(&z))
+                        &self.nodes.get_right(&self.nodes.get_parent(&z))
                     ) {
-                        z = RB::get_parent(&z);  // Set z to be the current node's parent
-                        self.root = RB::left_rotate(self.root.clone(), RB::get_root_key(&z));  // Perform a left rotation at z
+                        z = self.nodes.get_parent(&z);  // Set z to be the current node's parent
+                        self.root = self.nodes.left_rotate_at(self.root, &z);  // Perform a left rotation at z
                     }
-                    RB::set_root_color(&RB::get_parent(&z), NC::Black);  // Set z's parent's color to be black
-                    RB::set_root_color(&RB::get_parent(&RB::get_parent(&z)), NC::Red);  // Set z's grandparent's color to be red
-                    self.root = RB::right_rotate(self.root.clone(), RB::get_root_key(&RB::get_parent(&RB::get_parent(&z))));
+                    self.nodes.set_root_color(&self.nodes.get_parent(&z), NC::Black);  // Set z's parent's color to be black
+                    self.nodes.set_root_color(&self.nodes.get_parent(&self.nodes.get_parent(&z)), NC::Red);  // Set z's grandparent's color to be red
+                    self.root = self.nodes.right_rotate_at(self.root, &self.nodes.get_parent(&self.nodes.get_parent(&z)));
                     // Perform a right rotation at z's grandparent
                 }
             } else {
                 /*
                     Here we know that the parent of z is a right node. Get the uncle of z (sibling of z's parent)
                 */
-                let y = RB::get_left(&RB::get_parent(&RB::get_parent(&z)));
-                if RB::get_root_color(&y) == NC::Red {
-                    RB::set_root_color(&RB::get_parent(&z), NC::Black);
-                    RB::set_root_color(&y, NC::Black);
-                    RB::set_root_color(&RB::get_parent(&RB::get_parent(&z)), NC::Red);
-                    z = RB::get_parent(&RB::get_parent(&z));
+                let y = self.nodes.get_left(&self.nodes.get_parent(&self.nodes.get_parent(&z)));
+                if self.nodes.get_root_color(&y) == NC::Red {
+                    self.nodes.set_root_color(&self.nodes.get_parent(&z), NC::Black);
+                    self.nodes.set_root_color(&y, NC::Black);
+                    self.nodes.set_root_color(&self.nodes.get_parent(&self.nodes.get_parent(&z)), NC::Red);
+                    z = self.nodes.get_parent(&self.nodes.get_parent(&z));
                 } else {
-                    if RB::is_node_equal(
+                    if self.nodes.is_node_equal(
                         &z,
-                        &RB::get_left(&RB::get_parent(&z))
+                        &self.nodes.get_left(&self.nodes.get_parent(&z))
                     ) {
-                        z = RB::get_parent(&z);
-                        self.root = RB::right_rotate(self.root.clone(), RB::get_root_key(&z));
+                        z = self.nodes.get_parent(&z);
+                        self.root = self.nodes.right_rotate_at(self.root, &z);
                     }
-                    RB::set_root_color(&RB::get_parent(&z), NC::Black);
-                    RB::set_root_color(&RB::get_parent(&RB::get_parent(&z)), NC::Red);
-                    self.root = RB::left_rotate(self.root.clone(), RB::get_root_key(&RB::get_parent(&RB::get_parent(&z))));
+                    self.nodes.set_root_color(&self.nodes.get_parent(&z), NC::Black);
+                    self.nodes.set_root_color(&self.nodes.get_parent(&self.nodes.get_parent(&z)), NC::Red);
+                    self.root = self.nodes.left_rotate_at(self.root, &self.nodes.get_parent(&self.nodes.get_parent(&z)));
                 }
             }
         }
-        RB::set_root_color(&self.root, NC::Black);
+        self.nodes.set_root_color(&self.root, NC::Black);
+    }
+}
+impl<T: Debug, C: Comparator<T>, A: Augment<T, ()>> SearchTree<T> for RedBlackTree<T, (), C, A> {
+    type Node<'a> = Option<RBNodeView<'a, T, (), A>> where Self: 'a;
+    type Order = C;
+
+    fn insert(&mut self, key: T) -> bool {
+        RedBlackTree::insert(self, key)
+    }
+
+    fn delete<Q: ?Sized>(&mut self, key: &Q) -> Option<T> where T: Borrow<Q>, C: Comparator<Q> {
+        RedBlackTree::delete(self, key)
+    }
+
+    fn search<Q: ?Sized>(&self, key: &Q) -> Option<RBNodeView<'_, T, (), A>> where T: Borrow<Q>, C: Comparator<Q> {
+        RedBlackTree::search(self, key)
+    }
+
+    fn contains<Q: ?Sized>(&self, key: &Q) -> bool where T: Borrow<Q>, C: Comparator<Q> {
+        RedBlackTree::search(self, key).is_some()
+    }
+
+    fn is_empty(&self) -> bool {
+        RedBlackTree::is_empty(self)
+    }
+
+    fn get_height(&mut self) -> u128 {
+        RedBlackTree::get_height(self)
+    }
+
+    fn get_num_leaves(&mut self) -> u128 {
+        RedBlackTree::get_num_leaves(self)
+    }
+
+    fn print_tree(&self) {
+        RedBlackTree::print_tree(self)
+    }
+
+    fn print_traverse(&self, order: Ordering) {
+        RedBlackTree::print_traverse(self, order)
+    }
+}
+
+// Cloning copies the arena, so every node of the copy sits at the same index as in the original.
+impl<T: Clone + Debug, V: Clone, C: Clone, A: Augment<T, V>> Clone for RedBlackTree<T, V, C, A> {
+
+    fn clone(&self) -> Self {
+        RedBlackTree {
+            root: self.root,
+            nodes: self.nodes.clone(),
+            num_leaves: self.num_leaves,
+            height: self.height,
+            _is_num_leaves_available: self._is_num_leaves_available,
+            _is_height_available: self._is_height_available,
+            comparator: self.comparator.clone(),
+        }
+    }
+}
+
+impl<T: Debug, V, C: Comparator<T> + Default, A: Augment<T, V>> Default for RedBlackTree<T, V, C, A> {
+
+    fn default() -> Self {
+        RedBlackTree::augmented(C::default())
+    }
+}
+
+/*
+    Two trees are equal when they hold the same entries, whatever shape balancing gave them. Keys are
+    matched with the comparator, so keys it considers the same count as equal.
+*/
+impl<T: Debug, V: PartialEq, C: Comparator<T>, A: Augment<T, V>> PartialEq for RedBlackTree<T, V, C, A> {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| {
+            self.comparator.compare(a.key(), b.key()) == Ordering::Equal && *a.value() == *b.value()
+        })
     }
-}
\ No newline at end of file
+}
+
+impl<T: Debug, V: Eq, C: Comparator<T>, A: Augment<T, V>> Eq for RedBlackTree<T, V, C, A> {}
+
+// Prints the keys in order, e.g. `{1, 2, 3}`, like `BTreeSet` does.
+impl<T: Debug, V, C: Comparator<T>, A: Augment<T, V>> Debug for RedBlackTree<T, V, C, A> {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut set = f.debug_set();
+        for entry in self.iter() {
+            set.entry(entry.key());
+        }
+        set.finish()
+    }
+}
+
+impl<T: Debug, C: Comparator<T> + Default, A: Augment<T, ()>> FromIterator<T> for RedBlackTree<T, (), C, A> {
+
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        RedBlackTree::from_entries(iter.into_iter().map(|key| (key, ())).collect(), C::default())
+    }
+}
+
+impl<T: Debug, C: Comparator<T>, A: Augment<T, ()>> Extend<T> for RedBlackTree<T, (), C, A> {
+
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.bulk_load(iter)
+    }
+}
+
+impl<'a, T: Clone + Debug + 'a, C: Comparator<T>, A: Augment<T, ()>> Extend<&'a T> for RedBlackTree<T, (), C, A> {
+
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.bulk_load(iter.into_iter().cloned())
+    }
+}
+
+/*
+    In-order iterator over a borrowed tree. It walks the parent pointers from the minimum towards the
+    maximum (and back from the maximum for `next_back`) and stops once both ends meet.
+*/
+pub struct Iter<'a, T, V = (), A: Augment<T, V> = ()> {
+    nodes: &'a RBArena<T, V, A>,
+    front: TRoot,
+    back: TRoot,
+}
+
+impl<'a, T: Debug, V, A: Augment<T, V>> Iterator for Iter<'a, T, V, A> {
+    type Item = RBEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.nodes.get_root_nil(&self.front) {
+            return None;
+        }
+        let node = self.front.take();
+        if self.nodes.is_node_equal(&node, &self.back) {
+            self.back = None;
+        } else {
+            self.front = self.nodes.get_successor(&node);
+        }
+        RBEntryRef::from_node(self.nodes, &node)
+    }
+}
+
+impl<'a, T: Debug, V, A: Augment<T, V>> DoubleEndedIterator for Iter<'a, T, V, A> {
+
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.nodes.get_root_nil(&self.back) {
+            return None;
+        }
+        let node = self.back.take();
+        if self.nodes.is_node_equal(&node, &self.front) {
+            self.front = None;
+        } else {
+            self.back = self.nodes.get_predecessor(&node);
+        }
+        RBEntryRef::from_node(self.nodes, &node)
+    }
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> IntoIterator for &'a RedBlackTree<T, V, C, A> {
+    type Item = RBEntryRef<'a, T, V, A>;
+    type IntoIter = Iter<'a, T, V, A>;
+
+    fn into_iter(self) -> Iter<'a, T, V, A> {
+        self.iter()
+    }
+}
+
+/*
+    Iterator over the keys within a range. Both ends are located with a single descent each, after
+    which it walks in order like `Iter`.
+*/
+pub struct Range<'a, T, V = (), A: Augment<T, V> = ()> {
+    iter: Iter<'a, T, V, A>,
+}
+
+impl<'a, T: Debug, V, A: Augment<T, V>> Iterator for Range<'a, T, V, A> {
+    type Item = RBEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+impl<'a, T: Debug, V, A: Augment<T, V>> DoubleEndedIterator for Range<'a, T, V, A> {
+
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back()
+    }
+}
+
+pub struct IntoIter<T> {
+    entries: vec_deque::IntoIter<(T, ())>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.entries.next().map(|(key, _)| key)
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+
+    fn next_back(&mut self) -> Option<T> {
+        self.entries.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<T: Debug, C: Comparator<T>, A: Augment<T, ()>> IntoIterator for RedBlackTree<T, (), C, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { entries: self.into_entries().into_iter() }
+    }
+}
+
+/*
+    Set operators on borrowed trees, like the ones on `BTreeSet`. Both operands are copied and the
+    copies are combined with the join-based `into_*` operations.
+*/
+impl<T: Clone + Debug, V: Clone, C: Comparator<T> + Clone, A: Augment<T, V>> BitOr<&RedBlackTree<T, V, C, A>> for &RedBlackTree<T, V, C, A> {
+    type Output = RedBlackTree<T, V, C, A>;
+
+    fn bitor(self, rhs: &RedBlackTree<T, V, C, A>) -> RedBlackTree<T, V, C, A> {
+        self.clone().into_union(rhs.clone())
+    }
+}
+
+impl<T: Clone + Debug, V: Clone, C: Comparator<T> + Clone, A: Augment<T, V>> BitAnd<&RedBlackTree<T, V, C, A>> for &RedBlackTree<T, V, C, A> {
+    type Output = RedBlackTree<T, V, C, A>;
+
+    fn bitand(self, rhs: &RedBlackTree<T, V, C, A>) -> RedBlackTree<T, V, C, A> {
+        self.clone().into_intersection(rhs.clone())
+    }
+}
+
+impl<T: Clone + Debug, V: Clone, C: Comparator<T> + Clone, A: Augment<T, V>> Sub<&RedBlackTree<T, V, C, A>> for &RedBlackTree<T, V, C, A> {
+    type Output = RedBlackTree<T, V, C, A>;
+
+    fn sub(self, rhs: &RedBlackTree<T, V, C, A>) -> RedBlackTree<T, V, C, A> {
+        self.clone().into_difference(rhs.clone())
+    }
+}
+
+impl<T: Clone + Debug, V: Clone, C: Comparator<T> + Clone, A: Augment<T, V>> BitXor<&RedBlackTree<T, V, C, A>> for &RedBlackTree<T, V, C, A> {
+    type Output = RedBlackTree<T, V, C, A>;
+
+    fn bitxor(self, rhs: &RedBlackTree<T, V, C, A>) -> RedBlackTree<T, V, C, A> {
+        self.clone().into_symmetric_difference(rhs.clone())
+    }
+}
+
+/*
+    Walks two trees side by side. Every step takes the entry with the smaller key from either side,
+    or one from each side when the keys are equal; the lazy set iterators filter these steps.
+*/
+type EntryPair<'a, T, V, A> = (Option<RBEntryRef<'a, T, V, A>>, Option<RBEntryRef<'a, T, V, A>>);
+
+struct MergeIter<'a, T: Debug, V, C, A: Augment<T, V>> {
+    left: Peekable<Iter<'a, T, V, A>>,
+    right: Peekable<Iter<'a, T, V, A>>,
+    comparator: &'a C,
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> MergeIter<'a, T, V, C, A> {
+
+    fn new(left: Iter<'a, T, V, A>, right: Iter<'a, T, V, A>, comparator: &'a C) -> Self {
+        MergeIter { left: left.peekable(), right: right.peekable(), comparator }
+    }
+
+    fn next_pair(&mut self) -> EntryPair<'a, T, V, A> {
+        let ordering = match (self.left.peek(), self.right.peek()) {
+            (Some(left), Some(right)) => self.comparator.compare(left.key(), right.key()),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return (None, None),
+        };
+        match ordering {
+            Ordering::Less => (self.left.next(), None),
+            Ordering::Equal => (self.left.next(), self.right.next()),
+            Ordering::Greater => (None, self.right.next()),
+        }
+    }
+}
+
+pub struct Union<'a, T: Debug, V = (), C = NaturalOrder, A: Augment<T, V> = ()> {
+    merge: MergeIter<'a, T, V, C, A>,
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> Iterator for Union<'a, T, V, C, A> {
+    type Item = RBEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (left, right) = self.merge.next_pair();
+        left.or(right)
+    }
+}
+
+pub struct Intersection<'a, T: Debug, V = (), C = NaturalOrder, A: Augment<T, V> = ()> {
+    merge: MergeIter<'a, T, V, C, A>,
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> Iterator for Intersection<'a, T, V, C, A> {
+    type Item = RBEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.merge.next_pair() {
+                (Some(left), Some(_)) => return Some(left),
+                (None, None) => return None,
+                _ => (),
+            }
+        }
+    }
+}
+
+pub struct Difference<'a, T: Debug, V = (), C = NaturalOrder, A: Augment<T, V> = ()> {
+    merge: MergeIter<'a, T, V, C, A>,
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> Iterator for Difference<'a, T, V, C, A> {
+    type Item = RBEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.merge.next_pair() {
+                (Some(left), None) => return Some(left),
+                (None, None) => return None,
+                _ => (),
+            }
+        }
+    }
+}
+
+pub struct SymmetricDifference<'a, T: Debug, V = (), C = NaturalOrder, A: Augment<T, V> = ()> {
+    merge: MergeIter<'a, T, V, C, A>,
+}
+
+impl<'a, T: Debug, V, C: Comparator<T>, A: Augment<T, V>> Iterator for SymmetricDifference<'a, T, V, C, A> {
+    type Item = RBEntryRef<'a, T, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.merge.next_pair() {
+                (Some(entry), None) | (None, Some(entry)) => return Some(entry),
+                (None, None) => return None,
+                _ => (),
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::error::Error;
+use std::fmt;
+
+/*
+    A broken structural rule reported by `validate`. Every variant names the key of the node where
+    the rule was found broken, formatted with `Debug` since keys do not have to be `Clone`.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TreeViolation {
+    // The key does not lie between the keys of the ancestors it sits below.
+    OutOfOrder { key: String },
+    // The node's parent pointer does not lead back to the node it hangs from.
+    BrokenParentLink { key: String },
+    WrongSize { key: String, stored: usize, actual: usize },
+    RedRoot { key: String },
+    // A red node whose parent is red as well.
+    RedChildOfRed { key: String },
+    // The paths through the left and the right child pass different numbers of black nodes.
+    BlackHeightMismatch { key: String, left: usize, right: usize },
+    WrongHeight { key: String, stored: u128, actual: u128 },
+    // The heights of the children differ by more than one (right minus left).
+    Unbalanced { key: String, balance_factor: i64 },
+}
+
+impl TreeViolation {
+
+    pub fn key(&self) -> &str {
+        match self {
+            TreeViolation::OutOfOrder { key }
+            | TreeViolation::BrokenParentLink { key }
+            | TreeViolation::WrongSize { key, .. }
+            | TreeViolation::RedRoot { key }
+            | TreeViolation::RedChildOfRed { key }
+            | TreeViolation::BlackHeightMismatch { key, .. }
+            | TreeViolation::WrongHeight { key, .. }
+            | TreeViolation::Unbalanced { key, .. } => key,
+        }
+    }
+}
+
+impl fmt::Display for TreeViolation {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeViolation::OutOfOrder { key } => write!(f, "key {} is out of order", key),
+            TreeViolation::BrokenParentLink { key } => write!(f, "key {} does not point back to its parent", key),
+            TreeViolation::WrongSize { key, stored, actual } => {
+                write!(f, "key {} stores subtree size {} but has {} nodes below", key, stored, actual)
+            },
+            TreeViolation::RedRoot { key } => write!(f, "root {} is red", key),
+            TreeViolation::RedChildOfRed { key } => write!(f, "red key {} has a red parent", key),
+            TreeViolation::BlackHeightMismatch { key, left, right } => {
+                write!(f, "key {} has black height {} on the left but {} on the right", key, left, right)
+            },
+            TreeViolation::WrongHeight { key, stored, actual } => {
+                write!(f, "key {} stores height {} but its subtree is {} high", key, stored, actual)
+            },
+            TreeViolation::Unbalanced { key, balance_factor } => {
+                write!(f, "key {} has balance factor {}", key, balance_factor)
+            },
+        }
+    }
+}
+
+impl Error for TreeViolation {}